@@ -0,0 +1,41 @@
+extern crate clap;
+mod intcode_asm;
+mod intcode_disasm;
+mod intcode_machine;
+mod intcode_object;
+mod intcode_symbols;
+mod util;
+
+use clap::{App, Arg};
+use intcode_asm::assemble_file;
+use std::path::Path;
+use util::error_exit;
+
+fn main() {
+    let args = App::new("intcode-asm")
+        .about("Assemble an Intcode module into a relocatable object for intcode-link")
+        .arg(Arg::with_name("source").required(true))
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .short("o")
+                .takes_value(true)
+                .help("Object file to write, by default the source with an .ico extension"),
+        )
+        .get_matches();
+
+    let source = args.value_of("source").unwrap();
+    let object = assemble_file(source).unwrap_or_else(|e| error_exit(&e));
+    let output = match args.value_of("output") {
+        Some(path) => String::from(path),
+        None => Path::new(source).with_extension("ico").to_string_lossy().into_owned(),
+    };
+    object.save(&output).unwrap_or_else(|e| error_exit(&e));
+    eprintln!(
+        "{}: {} words, {} globals, {} external references",
+        output,
+        object.code.len(),
+        object.globals.len(),
+        object.externs.len()
+    );
+}
@@ -0,0 +1,66 @@
+extern crate clap;
+mod intcode_coverage;
+mod intcode_disasm;
+mod intcode_machine;
+mod util;
+
+use clap::{App, AppSettings, Arg};
+use intcode_machine::{load_program, parse_program, run_all, Coverage, Machine, State, ValueType};
+use util::error_exit;
+
+fn main() {
+    let args = App::new("intcode-coverage")
+        .about("Runs a program and reports which instructions and branch directions were exercised")
+        .setting(AppSettings::AllowNegativeNumbers)
+        .arg(Arg::with_name("program").required(true))
+        .arg(
+            Arg::with_name("input")
+                .long("input")
+                .short("i")
+                .takes_value(true)
+                .help("Comma-separated input values"),
+        )
+        .arg(
+            Arg::with_name("merge")
+                .long("merge")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("lcov file from an earlier run to add to this one"),
+        )
+        .arg(
+            Arg::with_name("lcov")
+                .long("lcov")
+                .takes_value(true)
+                .help("Write the merged coverage as an lcov tracefile"),
+        )
+        .get_matches();
+
+    let path = args.value_of("program").unwrap();
+    let program: Vec<ValueType> = load_program(path).unwrap_or_else(|e| error_exit(&e));
+    let input: Vec<ValueType> = match args.value_of("input") {
+        Some(text) => parse_program(text).unwrap_or_else(|e| error_exit(&e)),
+        None => vec![],
+    };
+
+    let mut machine = Machine::new(&program);
+    let coverage = Coverage::attach(&mut machine);
+    match run_all(&mut machine, input.into_iter()) {
+        State::Halted => (),
+        state => eprintln!("Machine stopped with {:?}", state),
+    }
+    let mut coverage = coverage.lock().unwrap().clone();
+
+    for file in args.values_of("merge").into_iter().flatten() {
+        let text = std::fs::read_to_string(file)
+            .unwrap_or_else(|e| error_exit(&format!("Failed to read {}. Error = {:#}", file, e)));
+        let previous = intcode_coverage::parse_lcov(&text).unwrap_or_else(|e| error_exit(&e));
+        coverage.merge(&previous);
+    }
+
+    print!("{}", intcode_coverage::annotate(&program, &coverage));
+    if let Some(out) = args.value_of("lcov") {
+        std::fs::write(out, intcode_coverage::to_lcov(path, &program, &coverage))
+            .unwrap_or_else(|e| error_exit(&format!("Failed to write {}. Error = {:#}", out, e)));
+    }
+}
@@ -0,0 +1,311 @@
+extern crate clap;
+mod intcode_dap;
+mod intcode_disasm;
+mod intcode_machine;
+mod intcode_symbols;
+mod json;
+mod util;
+
+use clap::{App, Arg};
+use intcode_dap::Session;
+use json::Json;
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+
+/// Steps run between checks for a `pause` from the client.
+const SLICE: u64 = 100_000;
+
+/// Reads one message framed with a `Content-Length` header, or one per line with `lines`.
+fn read_message(input: &mut impl BufRead, lines: bool) -> Result<Option<String>, String> {
+    let mut line = String::new();
+    let mut length = None;
+    loop {
+        line.clear();
+        let read = input
+            .read_line(&mut line)
+            .map_err(|e| format!("Failed to read stdin. Error = {:#}", e))?;
+        if read == 0 {
+            return Ok(None);
+        }
+        let header = line.trim();
+        if lines {
+            match header.is_empty() {
+                true => continue,
+                false => return Ok(Some(String::from(header))),
+            }
+        }
+        if header.is_empty() {
+            if length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = Some(
+                value
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|e| format!("Bad header {:?}. Error = {:#}", header, e))?,
+            );
+        }
+    }
+    let mut body = vec![0; length.unwrap()];
+    input
+        .read_exact(&mut body)
+        .map_err(|e| format!("Failed to read stdin. Error = {:#}", e))?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|e| format!("Message is not UTF-8. Error = {:#}", e))
+}
+
+fn write_message(out: &mut impl Write, message: &Json, lines: bool) {
+    let text = message.to_string();
+    let result = match lines {
+        true => writeln!(out, "{}", text),
+        false => write!(out, "Content-Length: {}\r\n\r\n{}", text.len(), text),
+    };
+    result
+        .and_then(|_| out.flush())
+        .unwrap_or_else(|e| util::error_exit(&format!("Failed to write stdout. Error = {:#}", e)));
+}
+
+/// Requests that can interrupt a running machine; everything else waits for it to stop,
+/// which keeps scripted exchanges deterministic.
+fn interrupts(request: &Json) -> bool {
+    matches!(
+        request.get("command").as_str(),
+        Some("pause") | Some("disconnect") | Some("terminate")
+    )
+}
+
+/// Answers requests read from `input` until the client disconnects, or until `input` ends and
+/// the session has nothing left to run.
+fn serve(input: impl Read + Send + 'static, out: &mut impl Write, lines: bool) {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut input = BufReader::new(input);
+        loop {
+            match read_message(&mut input, lines) {
+                Ok(Some(text)) => match Json::parse(&text) {
+                    Ok(message) => {
+                        if sender.send(message).is_err() {
+                            return;
+                        }
+                    }
+                    Err(e) => eprintln!("Ignoring message {:?}: {}", text, e),
+                },
+                Ok(None) => return,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+            }
+        }
+    });
+
+    let mut session = Session::new();
+    let mut waiting: VecDeque<Json> = VecDeque::new();
+    let mut connected = true;
+    while !session.is_done() {
+        if session.is_running() {
+            session.resume(SLICE);
+            match receiver.try_recv() {
+                Ok(request) if interrupts(&request) => session.handle(&request),
+                Ok(request) => waiting.push_back(request),
+                Err(TryRecvError::Empty) => (),
+                Err(TryRecvError::Disconnected) => connected = false,
+            }
+        } else if let Some(request) = waiting.pop_front() {
+            session.handle(&request);
+        } else if connected {
+            match receiver.recv() {
+                Ok(request) => session.handle(&request),
+                Err(_) => connected = false,
+            }
+        } else {
+            break;
+        }
+        for message in session.take_messages() {
+            write_message(out, &message, lines);
+        }
+    }
+}
+
+fn main() {
+    let args = App::new("intcode-dap")
+        .about("Debug Adapter Protocol server for Intcode programs, over stdin and stdout")
+        .arg(
+            Arg::with_name("lines")
+                .long("lines")
+                .help("One JSON message per line instead of Content-Length framing, for scripts"),
+        )
+        .get_matches();
+    serve(io::stdin(), &mut io::stdout(), args.is_present("lines"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use json::object;
+    use std::io::Cursor;
+
+    /// in [n]; call count, which adds 1 to n until it reaches 10; out [n]; halt.
+    const CALL: &str = "109,100,3,50,21101,11,0,0,1105,1,20,4,50,99,0,0,0,0,0,0,109,1,1007,50,10,51,\
+                        1006,51,38,1001,50,1,50,1105,1,22,0,0,109,-1,2105,1,0";
+    const CALL_SYMBOLS: &str = "file 0 call.ics\nlabel 0 main\nlabel 20 count\nlabel 22 count_loop\n\
+                                line 0 0 1\nline 2 0 2\nline 4 0 3\nline 11 0 4\nline 13 0 5\ndata 50 n\n";
+
+    /// Writes `program`, and `symbols` as its sidecar, to a fresh directory; returns the program path.
+    fn save(name: &str, program: &str, symbols: Option<&str>) -> String {
+        let dir = std::env::temp_dir().join(format!("intcode-dap-{}-{}", std::process::id(), name));
+        std::fs::create_dir_all(&dir).unwrap();
+        if let Some(symbols) = symbols {
+            std::fs::write(dir.join("call.sym"), symbols).unwrap();
+        }
+        let path = dir.join("call.ic");
+        std::fs::write(&path, program).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn request(command: &str, arguments: Json) -> Json {
+        object(vec![
+            ("type", Json::from("request")),
+            ("command", Json::from(command)),
+            ("arguments", arguments),
+        ])
+    }
+
+    /// Sends `requests` one per line, as `--lines` does, and parses every message sent back.
+    fn transcript(requests: Vec<Json>) -> Vec<Json> {
+        let mut text = String::new();
+        for (seq, mut request) in requests.into_iter().enumerate() {
+            if let Json::Object(map) = &mut request {
+                map.insert(String::from("seq"), Json::from(seq + 1));
+            }
+            text.push_str(&format!("{}\n", request));
+        }
+        let mut out = vec![];
+        serve(Cursor::new(text.into_bytes()), &mut out, true);
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| Json::parse(line).unwrap())
+            .collect()
+    }
+
+    /// The command of each response and the name of each event.
+    fn kinds(messages: &[Json]) -> Vec<&str> {
+        messages
+            .iter()
+            .map(|m| m.get("command").as_str().or_else(|| m.get("event").as_str()).unwrap())
+            .collect()
+    }
+
+    fn find<'a>(messages: &'a [Json], kind: &str, nth: usize) -> &'a Json {
+        messages
+            .iter()
+            .filter(|m| m.get("command").as_str() == Some(kind) || m.get("event").as_str() == Some(kind))
+            .nth(nth)
+            .unwrap()
+    }
+
+    #[test]
+    fn stops_at_a_source_breakpoint_and_steps_over_it() {
+        let program = save("session", CALL, Some(CALL_SYMBOLS));
+        let source = program.replace("call.ic", "call.ics");
+        let messages = transcript(vec![
+            request("initialize", object(vec![])),
+            request(
+                "launch",
+                object(vec![
+                    ("program", Json::from(program.as_str())),
+                    ("input", Json::from(vec![Json::Int(3)])),
+                    ("stopOnEntry", Json::from(true)),
+                ]),
+            ),
+            request(
+                "setBreakpoints",
+                object(vec![
+                    ("source", object(vec![("path", Json::from(source.as_str()))])),
+                    ("breakpoints", Json::from(vec![object(vec![("line", Json::Int(4))])])),
+                ]),
+            ),
+            request("configurationDone", object(vec![])),
+            request("continue", object(vec![("threadId", Json::Int(1))])),
+            request("stackTrace", object(vec![("threadId", Json::Int(1))])),
+            request("scopes", object(vec![("frameId", Json::Int(1))])),
+            request("variables", object(vec![("variablesReference", Json::Int(1))])),
+            request("next", object(vec![("threadId", Json::Int(1))])),
+            request("continue", object(vec![("threadId", Json::Int(1))])),
+            request("disconnect", object(vec![])),
+        ]);
+        assert_eq!(
+            kinds(&messages),
+            vec![
+                "initialize",
+                "initialized",
+                "launch",
+                "setBreakpoints",
+                "configurationDone",
+                "process",
+                "stopped",
+                "continue",
+                "stopped",
+                "stackTrace",
+                "scopes",
+                "variables",
+                "next",
+                "output",
+                "stopped",
+                "continue",
+                "output",
+                "exited",
+                "terminated",
+                "disconnect",
+            ]
+        );
+        assert!(messages.iter().all(|m| m.get("success").as_bool() != Some(false)));
+        let seqs: Vec<i64> = messages.iter().map(|m| m.get("seq").as_i64().unwrap()).collect();
+        assert_eq!(seqs, (1..=messages.len() as i64).collect::<Vec<_>>());
+
+        let breakpoint = &find(&messages, "setBreakpoints", 0).get("body").get("breakpoints");
+        assert_eq!(breakpoint.as_array().unwrap()[0].get("verified").as_bool(), Some(true));
+        assert_eq!(breakpoint.as_array().unwrap()[0].get("instructionReference").as_str(), Some("11"));
+        let reasons: Vec<&str> =
+            (0..3).map(|n| find(&messages, "stopped", n).get("body").get("reason").as_str().unwrap()).collect();
+        assert_eq!(reasons, vec!["entry", "breakpoint", "step"]);
+
+        let frames = find(&messages, "stackTrace", 0).get("body").get("stackFrames").as_array().unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].get("instructionPointerReference").as_str(), Some("11"));
+        assert_eq!(frames[0].get("line").as_i64(), Some(4));
+        assert_eq!(frames[0].get("source").get("name").as_str(), Some("call.ics"));
+
+        let scopes = find(&messages, "scopes", 0).get("body").get("scopes").as_array().unwrap();
+        let names: Vec<&str> = scopes.iter().map(|s| s.get("name").as_str().unwrap()).collect();
+        assert_eq!(names, vec!["Registers", "Frame", "Memory"]);
+        let variables = find(&messages, "variables", 0).get("body").get("variables").as_array().unwrap();
+        assert_eq!(variables[0].get("name").as_str(), Some("pc"));
+        assert_eq!(variables[0].get("value").as_str(), Some("11"));
+
+        assert_eq!(find(&messages, "output", 0).get("body").get("output").as_str(), Some("10\n"));
+        assert_eq!(find(&messages, "exited", 0).get("body").get("exitCode").as_i64(), Some(0));
+    }
+
+    #[test]
+    fn interpreter_panic_stops_with_an_exception() {
+        let program = save("panic", "1101,1,1,5,42", None);
+        let messages = transcript(vec![
+            request("initialize", object(vec![])),
+            request("launch", object(vec![("program", Json::from(program.as_str()))])),
+            request("configurationDone", object(vec![])),
+            request("disconnect", object(vec![])),
+        ]);
+        let stopped = find(&messages, "stopped", 0).get("body");
+        assert_eq!(stopped.get("reason").as_str(), Some("exception"));
+        assert_eq!(stopped.get("description").as_str(), Some("Invalid command 42 at 4"));
+        assert_eq!(kinds(&messages).last(), Some(&"disconnect"));
+    }
+}
@@ -1,10 +1,14 @@
+mod intcode_framing;
 mod intcode_machine;
+mod intcode_replay;
 mod util;
 
+use intcode_framing::{FrameError, Framed, Record};
 use intcode_machine::{run_all, Machine, State, ValueType};
+use intcode_replay::SessionRecorder;
 use std::collections::HashMap;
 use std::io::BufRead;
-use util::{error_exit, part_id_from_cli, PartID};
+use util::{error_exit, options_from_cli, PartID};
 
 const BLACK: ValueType = 0;
 const WHITE: ValueType = 1;
@@ -16,6 +20,25 @@ const DIR_RIGHT: ValueType = 1;
 const DIR_DOWN: ValueType = 2;
 const DIR_LEFT: ValueType = 3;
 
+struct Paint {
+    color: ValueType,
+    turn: ValueType,
+}
+
+impl Record for Paint {
+    const WIDTH: usize = 2;
+
+    fn decode(values: &[ValueType]) -> Result<Paint, FrameError> {
+        match (values[0], values[1]) {
+            (BLACK, _) | (WHITE, _) if values[1] == LEFT || values[1] == RIGHT => Ok(Paint {
+                color: values[0],
+                turn: values[1],
+            }),
+            _ => Err(FrameError::invalid(values, "expected color and turn of 0 or 1")),
+        }
+    }
+}
+
 struct Robot {
     x: i64,
     y: i64,
@@ -64,7 +87,9 @@ fn main() {
     let mut robot = Robot::new();
     let mut map: HashMap<(i64, i64), i64> = HashMap::new();
 
-    let part = part_id_from_cli();
+    let options = options_from_cli();
+    let recording = options.record.as_ref().map(|_| SessionRecorder::attach(&mut machine));
+    let part = options.part;
     match part {
         PartID::One => (),
         PartID::Two => {
@@ -72,20 +97,21 @@ fn main() {
         }
     };
 
+    let mut frames: Framed<Paint> = Framed::new();
     loop {
         let cur_color = map.get(&(robot.x, robot.y)).unwrap_or(&BLACK);
         let state = run_all(&mut machine, yield_iter![*cur_color,]);
-        let color = machine.pop_output();
-        let direction = machine.pop_output();
-        map.entry((robot.x, robot.y))
-            .and_modify(|e| *e = color)
-            .or_insert(color);
-        robot = robot.step(direction);
+        for paint in frames.read(&mut machine) {
+            let paint = paint.unwrap_or_else(|e| error_exit(&format!("{}", e)));
+            map.insert((robot.x, robot.y), paint.color);
+            robot = robot.step(paint.turn);
+        }
         match state {
             State::Halted => break,
             _ => (),
         };
     }
+    frames.finish().unwrap_or_else(|e| error_exit(&format!("{}", e)));
 
     match part {
         PartID::One => println!("{}", map.len()),
@@ -107,4 +133,9 @@ fn main() {
             }
         }
     };
+
+    if let (Some(path), Some(recording)) = (options.record, recording) {
+        intcode_replay::save_session(&path, &recording.lock().unwrap())
+            .unwrap_or_else(|e| error_exit(&e));
+    }
 }
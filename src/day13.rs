@@ -1,11 +1,15 @@
 extern crate clap;
+mod intcode_framing;
 mod intcode_machine;
+mod intcode_replay;
 mod util;
 
-use intcode_machine::{run_all, Machine, State};
+use intcode_framing::{FrameError, Framed, Record};
+use intcode_machine::{run_all, Machine, State, ValueType};
+use intcode_replay::SessionRecorder;
 use std::collections::HashMap;
 use std::io::{stdin, BufRead};
-use util::{PartID, part_id_from_cli};
+use util::{error_exit, options_from_cli, PartID};
 
 struct Frame {
     map: HashMap<(i64, i64), i64>,
@@ -29,16 +33,34 @@ impl Frame {
     }
 }
 
-fn apply_change(machine: &mut Machine, frame: &mut Frame) {
-    while machine.has_output() {
-        let x = machine.pop_output();
-        let y = machine.pop_output();
-        let tile = machine.pop_output();
+enum Draw {
+    Tile { x: i64, y: i64, tile: i64 },
+    Score(i64),
+}
+
+impl Record for Draw {
+    const WIDTH: usize = 3;
 
-        if x == -1 {
-            frame.score = tile;
-            continue;
+    fn decode(values: &[ValueType]) -> Result<Draw, FrameError> {
+        match (values[0], values[1], values[2]) {
+            (-1, 0, score) => Ok(Draw::Score(score)),
+            (x, y, tile) if x >= 0 && y >= 0 && (0..=4).contains(&tile) => {
+                Ok(Draw::Tile { x, y, tile })
+            }
+            _ => Err(FrameError::invalid(values, "expected x, y and a tile id 0-4")),
         }
+    }
+}
+
+fn apply_change(machine: &mut Machine, frames: &mut Framed<Draw>, frame: &mut Frame) {
+    for draw in frames.read(machine) {
+        let (x, y, tile) = match draw.unwrap_or_else(|e| error_exit(&format!("{}", e))) {
+            Draw::Score(score) => {
+                frame.score = score;
+                continue;
+            }
+            Draw::Tile { x, y, tile } => (x, y, tile),
+        };
 
         if tile == 3 {
             frame.bar_x = x;
@@ -91,23 +113,32 @@ fn autoplay(frame: &Frame) -> i64 {
 fn main() {
     let mut machine = load_machine();
     let mut frame = Frame::new();
-    match part_id_from_cli() {
+    let mut frames: Framed<Draw> = Framed::new();
+    let options = options_from_cli();
+    let recording = options.record.as_ref().map(|_| SessionRecorder::attach(&mut machine));
+    match options.part {
         PartID::One => {
             run_all(&mut machine, yield_iter![]);
-            apply_change(&mut machine, &mut frame);
+            apply_change(&mut machine, &mut frames, &mut frame);
             let result = frame.map.iter().filter(|&(_, v)| *v == 2).count();
             println!("{}", result);
         }
         PartID::Two => {
             machine.memset(0, 2);
             let mut state = run_all(&mut machine, yield_iter![]);
-            apply_change(&mut machine, &mut frame);
+            apply_change(&mut machine, &mut frames, &mut frame);
             while state != State::Halted {
                 let joy_stick = autoplay(&frame);
                 state = run_all(&mut machine, yield_iter![joy_stick, ]);
-                apply_change(&mut machine, &mut frame);
+                apply_change(&mut machine, &mut frames, &mut frame);
             }
             println!("{}", frame.score);
         }
     }
+    frames.finish().unwrap_or_else(|e| error_exit(&format!("{}", e)));
+
+    if let (Some(path), Some(recording)) = (options.record, recording) {
+        intcode_replay::save_session(&path, &recording.lock().unwrap())
+            .unwrap_or_else(|e| error_exit(&e));
+    }
 }
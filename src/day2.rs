@@ -1,26 +1,36 @@
+mod intcode_machine;
+mod intcode_search;
 mod util;
 
+use intcode_machine::*;
+use intcode_search::{default_threads, search, Axis, Goal, Space};
 use std::io::BufRead;
+use std::sync::Arc;
 use util::error_exit;
 
 
-fn process(memory: &mut [usize], cursor: usize) {
-    let op = memory[cursor];
-    match op {
-        1 => {
-            let v1 = memory[memory[cursor + 1]];
-            let v2 = memory[memory[cursor + 2]];
-            memory[memory[cursor + 3]] = v1 + v2;
-            process(memory, cursor + 4);
-        },
-        2 => {
-            let v1 = memory[memory[cursor + 1]];
-            let v2 = memory[memory[cursor + 2]];
-            memory[memory[cursor + 3]] = v1 * v2;
-            process(memory, cursor + 4);
-        },
-        99 => (),
-        _ => error_exit("Error in reading program")
+/// Runs to the end, exiting on an instruction day2 does not have as the original
+/// interpreter did rather than letting the machine panic.
+fn run(machine: &mut Machine) -> State {
+    loop {
+        if machine.check_instruction().is_err() {
+            error_exit("Error in reading program");
+        }
+        match step(machine) {
+            State::Running => (),
+            state => return state,
+        }
+    }
+}
+
+fn process(image: &Arc<Vec<ValueType>>, noun: ValueType, verb: ValueType) -> ValueType {
+    let mut machine = Machine::from_image(image.clone());
+    machine.set_level(FeatureLevel::Day2);
+    machine.memset(1, noun);
+    machine.memset(2, verb);
+    match run(&mut machine) {
+        State::Halted => machine.memget(0),
+        _ => error_exit("Error in reading program"),
     }
 }
 
@@ -29,41 +39,40 @@ fn main() {
 
     let part = util::part_id_from_cli();
 
-    let mut memory: Vec<usize> = std::io::stdin()
+    let memory: Vec<ValueType> = std::io::stdin()
         .lock()
         .lines()
         .map(or_abort!("Failed to read from stdin"))
         .nth(0)
         .unwrap()
         .split(",")
-        .map(|line| line.parse::<usize>())
+        .map(|line| line.parse::<ValueType>())
         .map(or_abort!("Failed to parse"))
         .collect()
         ;
+    let image = Arc::new(memory);
 
     match part {
         util::PartID::One => {
-            memory[1] = 12;
-            memory[2] = 2;
-            process(&mut memory, 0);
-            println!("{}", memory[0]);
+            println!("{}", process(&image, 12, 2));
         },
         util::PartID::Two => {
-            let output: usize = 19690720;
-            let max_pos = memory.len();
-
-            // Naive solution
-            for i in 0..max_pos {
-                for j in 0..max_pos {
-                    let mut local_memory = memory.clone();
-                    local_memory[1] = i;
-                    local_memory[2] = j;
-                    process(&mut local_memory, 0);
-                    if local_memory[0] == output {
-                        println!("{}", local_memory[1] * 100 + local_memory[2]);
-                        return;
-                    }
+            let output: ValueType = 19690720;
+            let max_pos = image.len() as ValueType;
+            let mut base = Machine::from_image(image.clone());
+            base.set_level(FeatureLevel::Day2);
+            let space = Space::new(vec![Axis::cell(1, 0..max_pos), Axis::cell(2, 0..max_pos)]);
+            let found = search(&base, &space, default_threads(), Goal::First, |machine, _| {
+                match run(machine) {
+                    State::Halted if machine.memget(0) == output => Some(()),
+                    State::Halted => None,
+                    _ => error_exit("Error in reading program"),
                 }
+            });
+            if let Some(found) = found {
+                let (noun, verb) = (found.candidate.patches[0].1, found.candidate.patches[1].1);
+                println!("{}", noun * 100 + verb);
+                return;
             }
             println!("Search done. Not found.");            
         }
@@ -1,116 +1,51 @@
+mod intcode_machine;
 mod util;
 
+use intcode_machine::*;
 use std::io::BufRead;
 use util::error_exit;
 
-const TENS: [i32; 3] = [100, 1000, 10000];
+/// Prints each executed instruction to stderr the way the original interpreter traced it.
+struct Trace;
 
-const ADD: i32 = 1;
-const MULTIPLY: i32 = 2;
-const INPUT: i32 = 3;
-const PRINT: i32 = 4;
-const JMP_IF: i32 = 5;
-const JMP_IF_NOT: i32 = 6;
-const CMP_LT: i32 = 7;
-const CMP_EQ: i32 = 8;
-const HALT: i32 = 99;
-
-macro_rules! def_param {
-    (Value $var:ident = $mem:expr, $pc:expr, $i:expr) => {
-        let $var: i32 = match $mem[$pc] / TENS[$i - 1] % 10 {
-            0 => $mem[$mem[$pc + $i] as usize],
-            1 => $mem[$pc + $i],
-            _ => error_exit("Invalid mode"),
-        };
-    };
-    (OutAddr $var:ident = $mem: expr, $pc: expr, $i:expr) => {
-        let $var: usize = $mem[$pc + $i] as usize;
-    };
+impl Observer<i32> for Trace {
+    fn instruction(&mut self, executed: &Executed<i32>) {
+        let value = |index: usize| executed.operands[index].value;
+        let cell = |index: usize| executed.operands[index].addr.unwrap_or_default();
+        eprint!("[{:03}] Op = {:05} | ", executed.addr, executed.words[0]);
+        match executed.opcode {
+            ADD => eprintln!("ADD {} {} mem[{}] = {}", value(0), value(1), cell(2), value(2)),
+            MULTIPLY => eprintln!("MULTIPLY {} {} mem[{}] = {}", value(0), value(1), cell(2), value(2)),
+            INPUT => eprintln!("INPUT mem[{}] = {}", cell(0), value(0)),
+            OUTPUT => eprintln!("PRINT {}", value(0)),
+            JMP_IF_NON_ZERO => eprintln!("JMP_IF cond={}, to={}", value(0), value(1) as usize),
+            JMP_IF_ZERO => eprintln!("JMP_IF_NOT cond={}, to={}", value(0), value(1) as usize),
+            CMP_LT => eprintln!("CMP_LT {} {} mem[{}]={}", value(0), value(1), cell(2), value(2)),
+            CMP_EQ => eprintln!("CMP_EQ {} {} mem[{}]={}", value(0), value(1), cell(2), value(2)),
+            _ => (),
+        }
+    }
 }
 
-fn process<T>(memory: &mut [i32], cursor: usize, mut input: T)
-where
-    T: Iterator<Item = i32>,
-{
-    eprint!("[{:03}] Op = {:05} | ", cursor, memory[cursor]);
-
-    let cursor: usize = match memory[cursor as usize] % 100 {
-        ADD => {
-            def_param!(Value v1 = memory, cursor, 1);
-            def_param!(Value v2 = memory, cursor, 2);
-            def_param!(OutAddr p_out = memory, cursor, 3);
-            memory[p_out] = v1 + v2;
-            eprintln!("ADD {} {} mem[{}] = {}", v1, v2, p_out, memory[p_out]);
-            cursor + 4
-        }
-        MULTIPLY => {
-            def_param!(Value v1 = memory, cursor, 1);
-            def_param!(Value v2 = memory, cursor, 2);
-            def_param!(OutAddr p_out = memory, cursor, 3);
-            memory[p_out] = v1 * v2;
-            eprintln!("MULTIPLY {} {} mem[{}] = {}", v1, v2, p_out, memory[p_out]);
-            cursor + 4
-        }
-        INPUT => {
-            def_param!(OutAddr p_out = memory, cursor, 1);
-            memory[p_out] = input.next().unwrap();
-            eprintln!("INPUT mem[{}] = {}", p_out, memory[p_out]);
-            cursor + 2
+/// Steps the shared machine at day5 level, printing outputs as they come.
+fn process(machine: &mut Machine<i32>) {
+    loop {
+        let cursor = machine.cursor();
+        machine.check_instruction().unwrap_or_else(|e| error_exit(&e));
+        match step(machine) {
+            State::Running => (),
+            State::Halted => return,
+            State::InputBlock => error_exit(&format!("Out of input at {}", cursor)),
+            state => error_exit(&format!("Stopped with {:?} at {}", state, cursor)),
         }
-        PRINT => {
-            def_param!(Value v = memory, cursor, 1);
-            eprintln!("PRINT {}", v);
+        for v in machine.drain_output() {
             println!("-> {}", v);
-            cursor + 2
         }
-        JMP_IF => {
-            def_param!(Value v = memory, cursor, 1);
-            def_param!(Value addr = memory, cursor, 2);
-            eprintln!("JMP_IF cond={}, to={}", v, addr as usize);
-            match v {
-                0 => cursor + 3,
-                _ => addr as usize,
-            }
-        }
-        JMP_IF_NOT => {
-            def_param!(Value v = memory, cursor, 1);
-            def_param!(Value addr = memory, cursor, 2);
-            eprintln!("JMP_IF_NOT cond={}, to={}", v, addr as usize);
-            match v {
-                0 => addr as usize,
-                _ => cursor + 3,
-            }
-        }
-        CMP_LT => {
-            def_param!(Value v1 = memory, cursor, 1);
-            def_param!(Value v2 = memory, cursor, 2);
-            def_param!(OutAddr p_out = memory, cursor, 3);
-            memory[p_out] = match v1 < v2 {
-                true => 1,
-                false => 0,
-            };
-            eprintln!("CMP_LT {} {} mem[{}]={}", v1, v2, p_out, memory[p_out]);
-            cursor + 4
-        }
-        CMP_EQ => {
-            def_param!(Value v1 = memory, cursor, 1);
-            def_param!(Value v2 = memory, cursor, 2);
-            def_param!(OutAddr p_out = memory, cursor, 3);
-            memory[p_out] = match v1 == v2 {
-                true => 1,
-                false => 0,
-            };
-            eprintln!("CMP_EQ {} {} mem[{}]={}", v1, v2, p_out, memory[p_out]);
-            cursor + 4
-        }
-        HALT => return,
-        _ => error_exit(&format!("Invalid command {} at {}", memory[cursor], cursor)),
-    };
-    process(memory, cursor, input);
+    }
 }
 
 fn main() {
-    let mut program: Vec<i32> = lines_from_stdin!()
+    let program: Vec<i32> = lines_from_stdin!()
         .nth(0)
         .unwrap()
         .split(',')
@@ -122,10 +57,14 @@ fn main() {
         })
         .collect();
 
-    let user_inputs = match util::part_id_from_cli() {
-        util::PartID::One => [1],
-        util::PartID::Two => [5],
+    let user_input = match util::part_id_from_cli() {
+        util::PartID::One => 1,
+        util::PartID::Two => 5,
     };
 
-    process(&mut program, 0, user_inputs.into_iter().map(|&x| x));
+    let mut machine = Machine::new(&program);
+    machine.set_level(FeatureLevel::Day5);
+    machine.add_observer(Box::new(Trace));
+    machine.push_input(user_input);
+    process(&mut machine);
 }
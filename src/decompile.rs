@@ -0,0 +1,38 @@
+extern crate clap;
+mod intcode_decompiler;
+mod intcode_disasm;
+mod intcode_machine;
+mod intcode_symbols;
+mod util;
+
+use clap::{App, Arg};
+use intcode_machine::load_program;
+use intcode_symbols::Symbols;
+use util::error_exit;
+
+fn main() {
+    let args = App::new("intcode-decompile")
+        .arg(Arg::with_name("program").required(true))
+        .arg(
+            Arg::with_name("disasm")
+                .long("disasm")
+                .help("Print a flat disassembly instead of pseudo-code"),
+        )
+        .arg(
+            Arg::with_name("symbols")
+                .long("symbols")
+                .takes_value(true)
+                .help("Symbol file for the disassembly, defaults to the program's .sym sidecar"),
+        )
+        .get_matches();
+
+    let path = args.value_of("program").unwrap();
+    let program = load_program(path).unwrap_or_else(|e| error_exit(&e));
+    let symbols = Symbols::for_program(path, args.value_of("symbols")).unwrap_or_else(|e| error_exit(&e));
+
+    match (args.is_present("disasm"), symbols) {
+        (true, Some(symbols)) => print!("{}", intcode_symbols::disassemble(&program, &symbols)),
+        (true, None) => print!("{}", intcode_disasm::disassemble(&program)),
+        (false, _) => print!("{}", intcode_decompiler::decompile(&program)),
+    }
+}
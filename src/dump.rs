@@ -0,0 +1,151 @@
+extern crate clap;
+mod intcode_disasm;
+mod intcode_dump;
+mod intcode_machine;
+mod intcode_symbols;
+mod util;
+
+use clap::{App, AppSettings, Arg};
+use intcode_disasm::reachable;
+use intcode_dump::{diff, dump, Layout};
+use intcode_machine::*;
+use intcode_symbols::Symbols;
+use std::collections::BTreeMap;
+use util::{error_exit, parse_arg, parse_patch, write_program};
+
+fn main() {
+    let args = App::new("intcode-dump")
+        .about("Print machine memory after a run, or how it changed")
+        .setting(AppSettings::AllowNegativeNumbers)
+        .arg(Arg::with_name("program").required(true))
+        .arg(
+            Arg::with_name("input")
+                .long("input")
+                .short("i")
+                .takes_value(true)
+                .help("Comma-separated input values"),
+        )
+        .arg(
+            Arg::with_name("set")
+                .long("set")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Memory patch applied before running, as addr=value"),
+        )
+        .arg(
+            Arg::with_name("max-steps")
+                .long("max-steps")
+                .takes_value(true)
+                .help("Take the snapshot after this many steps"),
+        )
+        .arg(
+            Arg::with_name("no-run")
+                .long("no-run")
+                .help("Show the memory as loaded, e.g. to view a saved snapshot"),
+        )
+        .arg(
+            Arg::with_name("diff")
+                .long("diff")
+                .help("Show the cells the run changed instead of the whole memory"),
+        )
+        .arg(
+            Arg::with_name("compare")
+                .long("compare")
+                .takes_value(true)
+                .conflicts_with("diff")
+                .help("Show the cells that differ from this saved snapshot"),
+        )
+        .arg(
+            Arg::with_name("save")
+                .long("save")
+                .takes_value(true)
+                .help("Also write the snapshot in program format, for --compare or --no-run"),
+        )
+        .arg(
+            Arg::with_name("disasm")
+                .long("disasm")
+                .short("d")
+                .help("Annotate rows with the instructions that start in them"),
+        )
+        .arg(
+            Arg::with_name("symbols")
+                .long("symbols")
+                .takes_value(true)
+                .help("Symbol file for the annotations, defaults to the program's .sym sidecar"),
+        )
+        .arg(
+            Arg::with_name("columns")
+                .long("columns")
+                .short("c")
+                .takes_value(true)
+                .default_value("10")
+                .help("Cells per row"),
+        )
+        .arg(
+            Arg::with_name("color")
+                .long("color")
+                .help("Highlight changed cells with terminal colors instead of markers"),
+        )
+        .get_matches();
+
+    let path = args.value_of("program").unwrap();
+    let program: Vec<ValueType> = load_program(path).unwrap_or_else(|e| error_exit(&e));
+    let input: Vec<ValueType> = match args.value_of("input") {
+        Some(text) => parse_program(text).unwrap_or_else(|e| error_exit(&e)),
+        None => vec![],
+    };
+    let columns: usize = parse_arg(&args, "columns").unwrap();
+
+    let mut machine = Machine::new(&program);
+    for text in args.values_of("set").into_iter().flatten() {
+        let (addr, value) = parse_patch(text).unwrap_or_else(|e| error_exit(&e));
+        machine.memset(addr, value);
+    }
+    let initial = machine.memory_snapshot();
+    if !args.is_present("no-run") {
+        machine.set_limits(Limits {
+            max_steps: parse_arg(&args, "max-steps"),
+            max_memory: None,
+        });
+        let state = run_all(&mut machine, input.into_iter());
+        eprintln!("snapshot after {} steps ({:?}) at {}", machine.steps(), state, machine.cursor());
+    }
+    let memory = machine.memory_snapshot();
+    if let Some(out) = args.value_of("save") {
+        write_program(Some(out), &memory);
+    }
+
+    let before = match (args.is_present("diff"), args.value_of("compare")) {
+        (true, _) => Some(initial),
+        (_, Some(snapshot)) => Some(load_program(snapshot).unwrap_or_else(|e| error_exit(&e))),
+        _ => None,
+    };
+    let color = args.is_present("color");
+    match before {
+        Some(before) => {
+            let layout = Layout::new(&[&before, &memory], columns, color);
+            print!("{}", diff(&before, &memory, &layout));
+        }
+        None => {
+            let mut annotations = BTreeMap::new();
+            if args.is_present("disasm") {
+                let symbols =
+                    Symbols::for_program(path, args.value_of("symbols")).unwrap_or_else(|e| error_exit(&e));
+                let mut entries = vec![0, machine.cursor()];
+                if let Some(symbols) = &symbols {
+                    entries.extend(symbols.labels.keys());
+                }
+                for (addr, inst) in reachable(&memory, &entries) {
+                    let text = match &symbols {
+                        Some(symbols) => symbols.render(&inst),
+                        None => inst.to_string(),
+                    };
+                    annotations.insert(addr, text);
+                }
+            }
+            let layout = Layout::new(&[&memory], columns, color);
+            print!("{}", dump(&memory, &layout, &annotations));
+        }
+    }
+}
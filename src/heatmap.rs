@@ -0,0 +1,105 @@
+extern crate clap;
+mod intcode_heatmap;
+mod intcode_machine;
+mod util;
+
+use clap::{App, AppSettings, Arg};
+use intcode_heatmap::{to_png, to_ppm, Heatmap, Recorder};
+use intcode_machine::*;
+use std::sync::{Arc, Mutex};
+use util::{error_exit, parse_arg, parse_patch};
+
+fn main() {
+    let args = App::new("intcode-heatmap")
+        .about("Image of memory accesses: red for reads, green for writes, blue for execution")
+        .setting(AppSettings::AllowNegativeNumbers)
+        .arg(Arg::with_name("program").required(true))
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .short("o")
+                .takes_value(true)
+                .required(true)
+                .help("Image to write, PNG unless the name ends in .ppm"),
+        )
+        .arg(
+            Arg::with_name("input")
+                .long("input")
+                .short("i")
+                .takes_value(true)
+                .help("Comma-separated input values"),
+        )
+        .arg(
+            Arg::with_name("set")
+                .long("set")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Memory patch applied before running, as addr=value"),
+        )
+        .arg(
+            Arg::with_name("max-steps")
+                .long("max-steps")
+                .takes_value(true)
+                .help("Stop the run after this many steps"),
+        )
+        .arg(
+            Arg::with_name("width")
+                .long("width")
+                .short("w")
+                .takes_value(true)
+                .default_value("64")
+                .help("Cells per row"),
+        )
+        .arg(
+            Arg::with_name("scale")
+                .long("scale")
+                .short("s")
+                .takes_value(true)
+                .default_value("4")
+                .help("Pixels per cell along each side"),
+        )
+        .get_matches();
+
+    let program: Vec<ValueType> =
+        load_program(args.value_of("program").unwrap()).unwrap_or_else(|e| error_exit(&e));
+    let input: Vec<ValueType> = match args.value_of("input") {
+        Some(text) => parse_program(text).unwrap_or_else(|e| error_exit(&e)),
+        None => vec![],
+    };
+    let width = parse_arg::<usize>(&args, "width").unwrap().max(1);
+    let scale = parse_arg::<usize>(&args, "scale").unwrap().max(1);
+
+    let mut machine = Machine::new(&program);
+    for text in args.values_of("set").into_iter().flatten() {
+        let (addr, value) = parse_patch(text).unwrap_or_else(|e| error_exit(&e));
+        machine.memset(addr, value);
+    }
+    machine.set_limits(Limits {
+        max_steps: parse_arg(&args, "max-steps"),
+        max_memory: None,
+    });
+    let heatmap = Arc::new(Mutex::new(Heatmap::default()));
+    machine.add_observer(Box::new(Recorder(heatmap.clone())));
+    let state = run_all(&mut machine, input.into_iter());
+    eprintln!("stopped with {:?} after {} steps", state, machine.steps());
+
+    let heatmap = heatmap.lock().unwrap();
+    let (height, pixels) = heatmap.pixels(machine.memory_len(), width);
+    let out = args.value_of("output").unwrap();
+    let image = match out.ends_with(".ppm") {
+        true => to_ppm(width, height, &pixels, scale),
+        false => to_png(width, height, &pixels, scale),
+    };
+    std::fs::write(out, image).unwrap_or_else(|e| error_exit(&format!("Failed to write {}. Error = {:#}", out, e)));
+    let touched = |counts: &[u64]| counts.iter().filter(|&&count| count > 0).count();
+    eprintln!(
+        "{} cells in {}x{}: {} read, {} written, {} executed",
+        machine.memory_len(),
+        width,
+        height,
+        touched(&heatmap.reads),
+        touched(&heatmap.writes),
+        touched(&heatmap.executes)
+    );
+}
@@ -0,0 +1,268 @@
+extern crate clap;
+mod intcode_disasm;
+mod intcode_io;
+mod intcode_machine;
+mod intcode_symbols;
+mod intcode_trace;
+mod util;
+
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use intcode_io::{open_pair, parse_values, run_connected, Encoding, Endpoint, Input, Output};
+use intcode_machine::*;
+use intcode_symbols::Symbols;
+use intcode_trace::Tracer;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use util::{error_exit, parse_arg, parse_patch};
+
+fn ascii_line(text: &str) -> Vec<ValueType> {
+    text.bytes().map(ValueType::from).chain(Some(10)).collect()
+}
+
+/// Input values known before the run: `--input` first, then `--input-file`.
+fn initial_input(args: &ArgMatches, ascii: bool) -> Result<Vec<ValueType>, String> {
+    let mut values = vec![];
+    for text in args.values_of("input").into_iter().flatten() {
+        match ascii {
+            true => values.extend(ascii_line(text)),
+            false => values.extend(parse_values(text)?),
+        }
+    }
+    if let Some(path) = args.value_of("input-file") {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}. Error = {:#}", path, e))?;
+        match ascii {
+            true => values.extend(text.bytes().map(ValueType::from)),
+            false => values.extend(parse_values(&text)?),
+        }
+    }
+    Ok(values)
+}
+
+/// Watchpoints: reports writes to the given cells with the instruction that made them.
+struct Watch {
+    cells: BTreeSet<usize>,
+    pending: Vec<(usize, ValueType)>,
+}
+
+impl Observer for Watch {
+    fn write(&mut self, addr: usize, value: &ValueType) {
+        if self.cells.contains(&addr) {
+            self.pending.push((addr, *value));
+        }
+    }
+
+    fn instruction(&mut self, executed: &Executed) {
+        for (cell, value) in self.pending.drain(..) {
+            eprintln!("watch: [{}] = {} by the instruction at {}", cell, value, executed.addr);
+        }
+    }
+}
+
+fn run(args: &ArgMatches) {
+    let path = args.value_of("program").unwrap();
+    let program: Vec<ValueType> = load_program(path).unwrap_or_else(|e| error_exit(&e));
+    let ascii = args.is_present("ascii");
+    let input = initial_input(args, ascii).unwrap_or_else(|e| error_exit(&e));
+    let interactive = !args.is_present("no-prompt");
+    let encoding = match ascii {
+        true => Encoding::Ascii,
+        false => Encoding::Numbers,
+    };
+    let endpoint = |name: &str| {
+        args.value_of(name)
+            .map(|spec| Endpoint::parse(spec).unwrap_or_else(|e| error_exit(&e)))
+    };
+    let (mut source, mut out) = match (endpoint("in"), endpoint("out")) {
+        (Some(from), Some(to)) => open_pair(&from, &to, encoding).unwrap_or_else(|e| error_exit(&e)),
+        (from, to) => (
+            match from {
+                Some(from) => Input::open(&from, encoding),
+                None if interactive => Input::prompt(encoding),
+                None => Input::empty(encoding),
+            },
+            match to {
+                Some(to) => Output::open(&to, encoding),
+                None => Output::new(Box::new(std::io::stdout()), encoding),
+            },
+        ),
+    };
+    source.queue(input);
+
+    let mut machine = Machine::new(&program);
+    for text in args.values_of("set").into_iter().flatten() {
+        let (addr, value) = parse_patch(text).unwrap_or_else(|e| error_exit(&e));
+        machine.memset(addr, value);
+    }
+    if let Some(level) = args.value_of("level") {
+        machine.set_level(FeatureLevel::parse(level).unwrap_or_else(|e| error_exit(&e)));
+    }
+    machine.set_limits(Limits {
+        max_steps: parse_arg(args, "max-steps"),
+        max_memory: parse_arg(args, "max-memory"),
+    });
+    let symbols = Symbols::for_program(path, args.value_of("symbols")).unwrap_or_else(|e| error_exit(&e));
+    if let Some(symbols) = symbols {
+        machine.set_labels(Arc::new(symbols.labels));
+    }
+    machine.set_debug(args.is_present("trace"));
+    let calls = match args.is_present("profile") {
+        true => Some(CallStack::attach(&mut machine, args.is_present("trace"))),
+        false => None,
+    };
+    if let Some(cells) = args.values_of("watch") {
+        let cells = cells
+            .map(|text| {
+                text.parse()
+                    .unwrap_or_else(|e| error_exit(&format!("Bad --watch {:?}. Error = {:#}", text, e)))
+            })
+            .collect();
+        machine.add_observer(Box::new(Watch { cells, pending: vec![] }));
+    }
+    let trace = args.value_of("trace-file").map(|_| Tracer::attach(&mut machine));
+
+    let state = run_connected(&mut machine, &mut source, &mut out).unwrap_or_else(|e| error_exit(&e));
+
+    if state != State::Halted {
+        eprintln!("Machine stopped with {:?} at {} after {} steps", state, machine.cursor(), machine.steps());
+        if let Some(calls) = &calls {
+            for line in calls.lock().unwrap().backtrace(machine.cursor(), machine.labels()) {
+                eprintln!("    {}", line);
+            }
+        }
+    }
+    if args.is_present("stats") {
+        eprintln!("steps: {}, memory: {} cells", machine.steps(), machine.memory_len());
+    }
+    if let (Some(path), Some(trace)) = (args.value_of("trace-file"), &trace) {
+        trace.lock().unwrap().save(path).unwrap_or_else(|e| error_exit(&e));
+    }
+    if let (Some(profile), Some(calls)) = (args.value_of("profile"), &calls) {
+        let folded = calls.lock().unwrap().folded(machine.labels());
+        std::fs::write(profile, folded)
+            .unwrap_or_else(|e| error_exit(&format!("Failed to write {}. Error = {:#}", profile, e)));
+    }
+    if state != State::Halted {
+        std::process::exit(2);
+    }
+}
+
+fn main() {
+    let args = App::new("intcode")
+        .about("Intcode tools")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("run")
+                .about("Run a program, prompting for input when it runs out")
+                .setting(AppSettings::AllowNegativeNumbers)
+                .arg(Arg::with_name("program").required(true))
+                .arg(
+                    Arg::with_name("input")
+                        .long("input")
+                        .short("i")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("Input values, comma or space separated; a line of text with --ascii"),
+                )
+                .arg(
+                    Arg::with_name("input-file")
+                        .long("input-file")
+                        .short("f")
+                        .takes_value(true)
+                        .help("Read input values from this file after those given with --input"),
+                )
+                .arg(
+                    Arg::with_name("in")
+                        .long("in")
+                        .takes_value(true)
+                        .help("Read further input from -, fd:N, a file or named pipe, unix:PATH or unix-listen:PATH"),
+                )
+                .arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .takes_value(true)
+                        .help("Write outputs to -, fd:N, a file or named pipe, unix:PATH or unix-listen:PATH"),
+                )
+                .arg(
+                    Arg::with_name("no-prompt")
+                        .long("no-prompt")
+                        .help("Stop when input runs out instead of asking on the terminal; implied by --in"),
+                )
+                .arg(
+                    Arg::with_name("ascii")
+                        .long("ascii")
+                        .short("a")
+                        .help("Inputs are text lines and outputs below 128 are printed as characters"),
+                )
+                .arg(
+                    Arg::with_name("set")
+                        .long("set")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("Memory patch applied before running, as addr=value"),
+                )
+                .arg(
+                    Arg::with_name("level")
+                        .long("level")
+                        .takes_value(true)
+                        .help("Instruction set allowed: day2, day5 or day9 (default)"),
+                )
+                .arg(
+                    Arg::with_name("trace")
+                        .long("trace")
+                        .short("t")
+                        .help("Trace every instruction to stderr"),
+                )
+                .arg(
+                    Arg::with_name("watch")
+                        .long("watch")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("Report every write to this address on stderr"),
+                )
+                .arg(
+                    Arg::with_name("trace-file")
+                        .long("trace-file")
+                        .takes_value(true)
+                        .help("Write a structured trace of every instruction to this file, for intcode-tracediff"),
+                )
+                .arg(
+                    Arg::with_name("profile")
+                        .long("profile")
+                        .takes_value(true)
+                        .help("Track calls and write folded stacks to this file"),
+                )
+                .arg(
+                    Arg::with_name("symbols")
+                        .long("symbols")
+                        .takes_value(true)
+                        .help("Symbol file for traces and profiles, defaults to the program's .sym sidecar"),
+                )
+                .arg(
+                    Arg::with_name("max-steps")
+                        .long("max-steps")
+                        .takes_value(true)
+                        .help("Stop after this many instructions"),
+                )
+                .arg(
+                    Arg::with_name("max-memory")
+                        .long("max-memory")
+                        .takes_value(true)
+                        .help("Stop when memory grows beyond this many cells"),
+                )
+                .arg(
+                    Arg::with_name("stats")
+                        .long("stats")
+                        .help("Print the step count and memory size at the end"),
+                ),
+        )
+        .get_matches();
+
+    match args.subcommand() {
+        ("run", Some(sub)) => run(sub),
+        _ => unreachable!(),
+    }
+}
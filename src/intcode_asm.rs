@@ -0,0 +1,423 @@
+#![allow(dead_code)]
+
+use crate::intcode_disasm::{param_count, Instruction, Mode, Param};
+use crate::intcode_machine::*;
+use crate::intcode_object::Object;
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Number(ValueType),
+    Symbol(String, ValueType),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Position(Expr),
+    Immediate(Expr),
+    Relative(ValueType),
+}
+
+#[derive(Debug)]
+enum Item {
+    Instruction(ValueType, Vec<Operand>),
+    Data(Vec<Expr>),
+    Call(Expr, ValueType),
+    Ret,
+}
+
+impl Item {
+    fn len(&self) -> usize {
+        match self {
+            Item::Instruction(_, operands) => operands.len() + 1,
+            Item::Data(values) => values.len(),
+            // ARB #S; ADD #ret, #0, [rb+0]; JNZ #1, #target; ARB #-S
+            Item::Call(_, _) => 11,
+            Item::Ret => 3,
+        }
+    }
+}
+
+fn opcode_of(mnemonic: &str) -> Option<ValueType> {
+    match mnemonic {
+        "ADD" => Some(ADD),
+        "MUL" => Some(MULTIPLY),
+        "IN" => Some(INPUT),
+        "OUT" => Some(OUTPUT),
+        "JNZ" => Some(JMP_IF_NON_ZERO),
+        "JZ" => Some(JMP_IF_ZERO),
+        "LT" => Some(CMP_LT),
+        "EQ" => Some(CMP_EQ),
+        "ARB" => Some(MOVE_RBASE),
+        "HALT" => Some(HALT),
+        _ => None,
+    }
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+fn parse_number(text: &str) -> Result<ValueType, String> {
+    text.trim()
+        .parse()
+        .map_err(|e| format!("Bad number {:?}. Error = {:#}", text, e))
+}
+
+fn parse_expr(text: &str) -> Result<Expr, String> {
+    let text = text.trim();
+    if text.starts_with('-') || text.starts_with(|c: char| c.is_ascii_digit()) {
+        return parse_number(text).map(Expr::Number);
+    }
+    let split = text.find(['+', '-']);
+    let (name, offset) = match split {
+        Some(index) => (text[..index].trim(), parse_number(&text[index..].replace(' ', ""))?),
+        None => (text, 0),
+    };
+    match is_identifier(name) {
+        true => Ok(Expr::Symbol(String::from(name), offset)),
+        false => Err(format!("Bad expression {:?}", text)),
+    }
+}
+
+fn parse_operand(text: &str) -> Result<Operand, String> {
+    let text = text.trim();
+    if let Some(value) = text.strip_prefix('#') {
+        return parse_expr(value).map(Operand::Immediate);
+    }
+    let inner = text
+        .strip_prefix('[')
+        .and_then(|rest| rest.strip_suffix(']'))
+        .ok_or_else(|| format!("Bad operand {:?}, expected [addr], #value or [rb+offset]", text))?
+        .trim();
+    match inner.strip_prefix("rb") {
+        Some("") => Ok(Operand::Relative(0)),
+        Some(offset) if offset.trim_start().starts_with(['+', '-']) => {
+            parse_number(&offset.replace(' ', "")).map(Operand::Relative)
+        }
+        _ => parse_expr(inner).map(Operand::Position),
+    }
+}
+
+/// Splits on commas outside string literals.
+fn split_operands(text: &str) -> Vec<String> {
+    let mut parts = vec![];
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in text.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                current.push(c);
+            }
+            ',' if !quoted => parts.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    if !current.trim().is_empty() || !parts.is_empty() {
+        parts.push(current);
+    }
+    parts.into_iter().map(|part| String::from(part.trim())).collect()
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &line[..index],
+            _ => (),
+        }
+    }
+    line
+}
+
+fn parse_item(mnemonic: &str, rest: &str) -> Result<Item, String> {
+    let operands = split_operands(rest);
+    match mnemonic.to_ascii_uppercase().as_str() {
+        "DATA" => {
+            let mut values = vec![];
+            for operand in &operands {
+                match operand.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+                    Some(text) => values.extend(text.bytes().map(|b| Expr::Number(b as ValueType))),
+                    None => values.push(parse_expr(operand)?),
+                }
+            }
+            Ok(Item::Data(values))
+        }
+        "CALL" => match operands.as_slice() {
+            [target] => Ok(Item::Call(parse_expr(target)?, 0)),
+            [target, frame] => Ok(Item::Call(parse_expr(target)?, parse_number(frame)?)),
+            _ => Err(String::from("call takes a target and an optional frame size")),
+        },
+        "RET" if operands.is_empty() => Ok(Item::Ret),
+        name => {
+            let opcode = opcode_of(name).ok_or_else(|| format!("Unknown instruction {:?}", mnemonic))?;
+            let count = param_count(opcode).unwrap();
+            if operands.len() != count {
+                return Err(format!("{} takes {} operands, got {}", name, count, operands.len()));
+            }
+            let operands = operands
+                .iter()
+                .map(|text| parse_operand(text))
+                .collect::<Result<Vec<Operand>, String>>()?;
+            let writes = match opcode {
+                ADD | MULTIPLY | CMP_LT | CMP_EQ => Some(2),
+                INPUT => Some(0),
+                _ => None,
+            };
+            if let Some(index) = writes {
+                if let Operand::Immediate(_) = operands[index] {
+                    return Err(format!("{} cannot write to an immediate operand", name));
+                }
+            }
+            Ok(Item::Instruction(opcode, operands))
+        }
+    }
+}
+
+struct Emitter<'a> {
+    object: Object,
+    labels: &'a BTreeMap<String, usize>,
+}
+
+impl<'a> Emitter<'a> {
+    fn value(&mut self, expr: &Expr) -> ValueType {
+        let at = self.object.code.len();
+        match expr {
+            Expr::Number(value) => *value,
+            Expr::Symbol(name, offset) => match self.labels.get(name) {
+                Some(&addr) => {
+                    self.object.relocs.push(at);
+                    addr as ValueType + offset
+                }
+                None => {
+                    self.object.externs.push((at, name.clone()));
+                    *offset
+                }
+            },
+        }
+    }
+
+    fn instruction(&mut self, opcode: ValueType, operands: &[Operand]) {
+        let start = self.object.code.len();
+        let params: Vec<Param> = operands
+            .iter()
+            .map(|operand| match operand {
+                Operand::Position(_) => Param {
+                    mode: Mode::Position,
+                    value: 0,
+                },
+                Operand::Immediate(_) => Param {
+                    mode: Mode::Immediate,
+                    value: 0,
+                },
+                Operand::Relative(offset) => Param {
+                    mode: Mode::Relative,
+                    value: *offset,
+                },
+            })
+            .collect();
+        let words = Instruction {
+            addr: start,
+            opcode,
+            params,
+        }
+        .encode();
+        self.object.code.push(words[0]);
+        for (operand, &word) in operands.iter().zip(words[1..].iter()) {
+            let value = match operand {
+                Operand::Position(expr) | Operand::Immediate(expr) => self.value(expr),
+                Operand::Relative(_) => word,
+            };
+            self.object.code.push(value);
+        }
+    }
+
+    fn call(&mut self, target: &Expr, frame: ValueType) {
+        let return_addr = (self.object.code.len() + 9) as ValueType;
+        self.instruction(MOVE_RBASE, &[Operand::Immediate(Expr::Number(frame))]);
+        let at = self.object.code.len();
+        self.instruction(
+            ADD,
+            &[
+                Operand::Immediate(Expr::Number(return_addr)),
+                Operand::Immediate(Expr::Number(0)),
+                Operand::Relative(0),
+            ],
+        );
+        self.object.relocs.push(at + 1);
+        self.instruction(
+            JMP_IF_NON_ZERO,
+            &[Operand::Immediate(Expr::Number(1)), Operand::Immediate(target.clone())],
+        );
+        self.instruction(MOVE_RBASE, &[Operand::Immediate(Expr::Number(-frame))]);
+    }
+}
+
+/// Assembles one module. `source` is recorded for the symbol file.
+///
+/// Assembly source reads like the disassembler's output:
+///
+/// ```text
+/// .global main
+/// main:   ARB #__end          ; stack after the program
+///         IN [n]
+///         ADD [n], #0, [rb+1]
+///         call print_number, 0
+///         OUT #10
+///         HALT
+/// n:      DATA 0
+/// ```
+///
+/// Operands are `[addr]`, `#value` or `[rb+offset]`; addresses and values may be a label,
+/// optionally `+` or `-` a number. Labels not defined in the module are resolved by the
+/// linker. `DATA` takes numbers, labels and "strings" (emitted as ASCII codes).
+///
+/// Calling convention, built on the relative base: a caller whose frame uses `[rb+0]` to
+/// `[rb+S-1]` puts arguments in `[rb+S+1]`, `[rb+S+2]`, ... and writes `call target, S`.
+/// That moves the relative base up by `S`, stores the return address in the callee's
+/// `[rb+0]`, jumps, and moves the base back after the return. The callee finds its
+/// arguments at `[rb+1]`, ..., leaves results in the same cells, and returns with `ret`.
+pub fn assemble(name: &str, source: &str, text: &str) -> Result<Object, String> {
+    let mut items: Vec<(usize, Item)> = vec![];
+    let mut labels: BTreeMap<String, usize> = BTreeMap::new();
+    let mut label_lines: BTreeMap<usize, String> = BTreeMap::new();
+    let mut data_labels: BTreeSet<String> = BTreeSet::new();
+    let mut globals: Vec<(usize, String)> = vec![];
+    let mut addr = 0;
+
+    for (number, line) in text.lines().enumerate() {
+        let number = number + 1;
+        let located = |e: String| format!("{}:{}: {}", source, number, e);
+        let mut line = strip_comment(line).trim();
+        if let Some(names) = line.strip_prefix(".global") {
+            for name in names.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+                globals.push((number, String::from(name)));
+            }
+            continue;
+        }
+        let mut pending = vec![];
+        while let Some(colon) = line.find(':') {
+            let label = line[..colon].trim();
+            if !is_identifier(label) || line[..colon].contains('"') {
+                break;
+            }
+            if labels.insert(String::from(label), addr).is_some() {
+                return Err(located(format!("label {} defined twice", label)));
+            }
+            label_lines.insert(addr, String::from(label));
+            pending.push(String::from(label));
+            line = line[colon + 1..].trim();
+        }
+        if line.is_empty() {
+            continue;
+        }
+        let (mnemonic, rest) = match line.find(char::is_whitespace) {
+            Some(index) => (&line[..index], &line[index..]),
+            None => (line, ""),
+        };
+        let item = parse_item(mnemonic, rest).map_err(located)?;
+        if let Item::Data(_) = item {
+            data_labels.extend(pending);
+        }
+        addr += item.len();
+        items.push((number, item));
+    }
+
+    let mut emitter = Emitter {
+        object: Object {
+            name: String::from(name),
+            source: String::from(source),
+            ..Object::default()
+        },
+        labels: &labels,
+    };
+    for (number, item) in &items {
+        let at = emitter.object.code.len();
+        match item {
+            Item::Instruction(opcode, operands) => emitter.instruction(*opcode, operands),
+            Item::Data(values) => {
+                for value in values {
+                    let value = emitter.value(value);
+                    emitter.object.code.push(value);
+                }
+            }
+            Item::Call(target, frame) => emitter.call(target, *frame),
+            Item::Ret => emitter.instruction(
+                JMP_IF_ZERO,
+                &[Operand::Immediate(Expr::Number(0)), Operand::Relative(0)],
+            ),
+        }
+        if let Item::Data(_) = item {
+            continue;
+        }
+        emitter.object.lines.insert(at, *number);
+    }
+
+    let mut object = emitter.object;
+    for (number, name) in globals {
+        let addr = labels
+            .get(&name)
+            .ok_or_else(|| format!("{}:{}: global {} is not defined", source, number, name))?;
+        object.globals.insert(name, *addr);
+    }
+    for (addr, label) in label_lines {
+        match data_labels.contains(&label) {
+            true => object.data.insert(addr, label),
+            false => object.labels.insert(addr, label),
+        };
+    }
+    Ok(object)
+}
+
+pub fn assemble_file(path: &str) -> Result<Object, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}. Error = {:#}", path, e))?;
+    let file = std::path::Path::new(path);
+    let name = file.file_stem().map_or(String::from("module"), |s| s.to_string_lossy().into_owned());
+    let source = file.file_name().map_or(String::from(path), |s| s.to_string_lossy().into_owned());
+    assemble(&name, &source, &text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn call_and_ret_expand_to_the_calling_convention() {
+        let object = assemble("m", "m.ics", "f: call f, 2\n   ret\n").unwrap();
+        assert_eq!(object.code, vec![109, 2, 21101, 9, 0, 0, 1105, 1, 0, 109, -2, 2106, 0, 0]);
+        assert_eq!(object.relocs, vec![3, 8]);
+        assert!(object.externs.is_empty());
+        assert_eq!(object.lines.keys().cloned().collect::<Vec<_>>(), vec![0, 11]);
+    }
+
+    #[test]
+    fn operands_and_data_encode_modes_and_symbols() {
+        let text = ".global start\nstart: ADD [x], #-3, [rb-1]\n OUT [ext+2]\nx: DATA 5, \"hi\", x\n";
+        let object = assemble("m", "m.ics", text).unwrap();
+        assert_eq!(object.code, vec![21001, 6, -3, -1, 4, 2, 5, 104, 105, 6]);
+        assert_eq!(object.globals.get("start"), Some(&0));
+        assert_eq!(object.relocs, vec![1, 9]);
+        assert_eq!(object.externs, vec![(5, String::from("ext"))]);
+        assert_eq!(object.data.get(&6).map(String::as_str), Some("x"));
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let cases = [
+            ("HALT\nFOO [1]\n", "m.ics:2: Unknown instruction \"FOO\""),
+            ("ADD #1, #2, #3\n", "m.ics:1: ADD cannot write to an immediate operand"),
+            ("OUT [1], [2]\n", "m.ics:1: OUT takes 1 operands, got 2"),
+            ("a: HALT\na: HALT\n", "m.ics:2: label a defined twice"),
+            (".global nowhere\nHALT\n", "m.ics:1: global nowhere is not defined"),
+            ("OUT 5\n", "m.ics:1: Bad operand \"5\", expected [addr], #value or [rb+offset]"),
+        ];
+        for (text, message) in cases.iter() {
+            assert_eq!(assemble("m", "m.ics", text).unwrap_err(), *message);
+        }
+    }
+}
@@ -0,0 +1,251 @@
+#![allow(dead_code)]
+
+use crate::intcode_machine::Word;
+use std::cmp::Ordering;
+use std::fmt;
+
+const BASE: u64 = 1_000_000_000;
+
+/// Arbitrary-precision integer, sign and magnitude in base 10^9 limbs, least significant first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigInt {
+    negative: bool,
+    limbs: Vec<u32>,
+}
+
+fn trim(limbs: &mut Vec<u32>) {
+    while limbs.last() == Some(&0) {
+        limbs.pop();
+    }
+}
+
+fn cmp_magnitude(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0;
+    for i in 0..a.len().max(b.len()) {
+        let sum = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        result.push((sum % BASE) as u32);
+        carry = sum / BASE;
+    }
+    if carry > 0 {
+        result.push(carry as u32);
+    }
+    result
+}
+
+/// |a| - |b|, requires |a| >= |b|.
+fn sub_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len());
+    let mut borrow = 0;
+    for (i, &limb) in a.iter().enumerate() {
+        let mut diff = limb as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = 0;
+        if diff < 0 {
+            diff += BASE as i64;
+            borrow = 1;
+        }
+        result.push(diff as u32);
+    }
+    trim(&mut result);
+    result
+}
+
+fn mul_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = vec![0u64; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0;
+        for (j, &y) in b.iter().enumerate() {
+            let cell = result[i + j] + x as u64 * y as u64 + carry;
+            result[i + j] = cell % BASE;
+            carry = cell / BASE;
+        }
+        result[i + b.len()] += carry;
+    }
+    let mut result: Vec<u32> = result.into_iter().map(|limb| limb as u32).collect();
+    trim(&mut result);
+    result
+}
+
+impl BigInt {
+    fn new(negative: bool, mut limbs: Vec<u32>) -> BigInt {
+        trim(&mut limbs);
+        BigInt {
+            negative: negative && !limbs.is_empty(),
+            limbs,
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_magnitude(&self.limbs, &other.limbs),
+            (true, true) => cmp_magnitude(&other.limbs, &self.limbs),
+        }
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut text = String::new();
+        if self.negative {
+            text.push('-');
+        }
+        match self.limbs.split_last() {
+            None => text.push('0'),
+            Some((head, rest)) => {
+                text.push_str(&head.to_string());
+                for limb in rest.iter().rev() {
+                    text.push_str(&format!("{:09}", limb));
+                }
+            }
+        }
+        f.pad(&text)
+    }
+}
+
+impl Word for BigInt {
+    fn from_i64(value: i64) -> BigInt {
+        let mut magnitude = value.unsigned_abs();
+        let mut limbs = vec![];
+        while magnitude > 0 {
+            limbs.push((magnitude % BASE) as u32);
+            magnitude /= BASE;
+        }
+        BigInt::new(value < 0, limbs)
+    }
+
+    /// Truncates to the low 64 bits, like a cast between fixed-width integers.
+    fn to_i64(&self) -> i64 {
+        let magnitude = self
+            .limbs
+            .iter()
+            .rev()
+            .fold(0u64, |acc, &limb| acc.wrapping_mul(BASE).wrapping_add(limb as u64));
+        match self.negative {
+            true => (magnitude as i64).wrapping_neg(),
+            false => magnitude as i64,
+        }
+    }
+
+    fn add_word(&self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::new(self.negative, add_magnitude(&self.limbs, &other.limbs));
+        }
+        match cmp_magnitude(&self.limbs, &other.limbs) {
+            Ordering::Less => BigInt::new(other.negative, sub_magnitude(&other.limbs, &self.limbs)),
+            _ => BigInt::new(self.negative, sub_magnitude(&self.limbs, &other.limbs)),
+        }
+    }
+
+    fn mul_word(&self, other: &BigInt) -> BigInt {
+        BigInt::new(
+            self.negative != other.negative,
+            mul_magnitude(&self.limbs, &other.limbs),
+        )
+    }
+
+    fn parse_word(text: &str) -> Result<BigInt, String> {
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(format!("Failed to parse {}. Error = invalid digit", text));
+        }
+        let bytes = digits.as_bytes();
+        let limbs = (0..bytes.len())
+            .rev()
+            .step_by(9)
+            .map(|end| {
+                let start = (end + 1).saturating_sub(9);
+                std::str::from_utf8(&bytes[start..=end]).unwrap().parse().unwrap()
+            })
+            .collect();
+        Ok(BigInt::new(negative, limbs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(text: &str) -> BigInt {
+        BigInt::parse_word(text).unwrap()
+    }
+
+    #[test]
+    fn carries_and_borrows_across_limbs() {
+        assert_eq!(big("999999999999999999").add_word(&big("1")), big("1000000000000000000"));
+        assert_eq!(big("1000000000000000000").add_word(&big("-1")), big("999999999999999999"));
+        assert_eq!(big("-1").add_word(&big("1000000000000000000")), big("999999999999999999"));
+        assert_eq!(
+            big("999999999999999999").mul_word(&big("999999999999999999")),
+            big("999999999999999998000000000000000001")
+        );
+    }
+
+    #[test]
+    fn signs_of_products_and_sums() {
+        assert_eq!(big("-123456789012").mul_word(&big("-1000000000")), big("123456789012000000000"));
+        assert_eq!(big("-123456789012").mul_word(&big("1000000000")), big("-123456789012000000000"));
+        assert_eq!(big("-5").add_word(&big("3")), big("-2"));
+        assert_eq!(big("5").add_word(&big("-3000000000")), big("-2999999995"));
+    }
+
+    #[test]
+    fn zero_has_no_sign() {
+        let zero = BigInt::from_i64(0);
+        assert_eq!(big("5").add_word(&big("-5")), zero);
+        assert_eq!(big("-1000000000").add_word(&big("1000000000")), zero);
+        assert_eq!(big("-3").mul_word(&zero), zero);
+        assert_eq!(big("-0"), zero);
+        assert_eq!(big("-000").to_string(), "0");
+        assert!(big("-5").add_word(&big("5")).is_zero());
+    }
+
+    #[test]
+    fn parse_and_print_round_trip() {
+        for text in ["0", "7", "-7", "1000000000", "-999999999", "123456789012345678901234567890123456789"] {
+            assert_eq!(big(text).to_string(), text);
+        }
+        assert_eq!(big("+42").to_string(), "42");
+        assert_eq!(big("000000000000123").to_string(), "123");
+        assert_eq!(big("-1000000000000000000000").to_string(), "-1000000000000000000000");
+        assert_eq!(format!("{:>6}", big("-12")), "   -12");
+        for text in ["", "-", "12a", "1.5", " 1"] {
+            assert!(BigInt::parse_word(text).is_err(), "{:?} parsed", text);
+        }
+    }
+
+    #[test]
+    fn to_i64_truncates_like_a_cast() {
+        let values = vec![0i128, 1, -1, i64::MAX as i128, i64::MIN as i128, 1 << 63, (1 << 64) + 5, 1 << 100];
+        for value in values.into_iter().flat_map(|value| vec![value, -value]) {
+            assert_eq!(big(&value.to_string()).to_i64(), value as i64, "{}", value);
+        }
+        assert_eq!(BigInt::from_i64(i64::MIN).to_string(), i64::MIN.to_string());
+    }
+
+    #[test]
+    fn orders_by_value() {
+        let mut values = [big("10"), big("-1000000000000"), big("0"), big("-3"), big("999999999999")];
+        values.sort();
+        let texts: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+        assert_eq!(texts, vec!["-1000000000000", "-3", "0", "10", "999999999999"]);
+    }
+}
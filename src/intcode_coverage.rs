@@ -0,0 +1,131 @@
+#![allow(dead_code)]
+
+use crate::intcode_disasm::{decode, reachable};
+use crate::intcode_machine::{Coverage, ValueType};
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+/// Instruction addresses worth reporting: everything statically reachable plus anything
+/// that actually ran (e.g. targets of computed jumps).
+fn instruction_addrs(memory: &[ValueType], coverage: &Coverage) -> BTreeSet<usize> {
+    let mut addrs: BTreeSet<usize> = reachable(memory, &[0]).keys().cloned().collect();
+    addrs.extend(coverage.executed.keys());
+    addrs
+}
+
+fn is_branch(memory: &[ValueType], addr: usize) -> bool {
+    match decode(memory, addr) {
+        Ok(inst) => inst.is_jump() && inst.constant_condition().is_none(),
+        Err(_) => false,
+    }
+}
+
+/// Disassembly with an execution count column, gcov style: `#####` marks code never run.
+pub fn annotate(memory: &[ValueType], coverage: &Coverage) -> String {
+    let mut text = String::new();
+    let addrs = instruction_addrs(memory, coverage);
+    for &addr in &addrs {
+        let count = match coverage.executed.get(&addr) {
+            Some(count) => count.to_string(),
+            None => String::from("#####"),
+        };
+        let inst = match decode(memory, addr) {
+            Ok(inst) => inst.to_string(),
+            Err(_) => String::from("<invalid>"),
+        };
+        write!(text, "{:>9}: {:>6}: {}", count, addr, inst).unwrap();
+        if is_branch(memory, addr) {
+            let (taken, not_taken) = coverage.branches.get(&addr).cloned().unwrap_or((0, 0));
+            write!(text, "    [taken {}, not taken {}]", taken, not_taken).unwrap();
+        }
+        text.push('\n');
+    }
+
+    let hit = addrs.iter().filter(|a| coverage.executed.contains_key(a)).count();
+    let branches: Vec<usize> = addrs
+        .iter()
+        .cloned()
+        .filter(|&a| is_branch(memory, a))
+        .collect();
+    let directions = branches
+        .iter()
+        .filter_map(|a| coverage.branches.get(a))
+        .map(|&(t, n)| (t > 0) as usize + (n > 0) as usize)
+        .sum::<usize>();
+    writeln!(
+        text,
+        "\ninstructions: {}/{} executed, branch directions: {}/{} exercised",
+        hit,
+        addrs.len(),
+        directions,
+        branches.len() * 2
+    )
+    .unwrap();
+    text
+}
+
+/// lcov tracefile keyed by address: `DA:<addr>,<count>` and `BRDA:<addr>,0,<0 taken|1 not taken>,<count>`.
+pub fn to_lcov(source: &str, memory: &[ValueType], coverage: &Coverage) -> String {
+    let mut text = format!("TN:\nSF:{}\n", source);
+    let addrs = instruction_addrs(memory, coverage);
+    let mut branch_found = 0;
+    let mut branch_hit = 0;
+    for &addr in &addrs {
+        if is_branch(memory, addr) {
+            let (taken, not_taken) = coverage.branches.get(&addr).cloned().unwrap_or((0, 0));
+            for (index, count) in [taken, not_taken].iter().enumerate() {
+                branch_found += 1;
+                if *count > 0 {
+                    branch_hit += 1;
+                }
+                writeln!(text, "BRDA:{},0,{},{}", addr, index, count).unwrap();
+            }
+        }
+    }
+    for &addr in &addrs {
+        let count = coverage.executed.get(&addr).cloned().unwrap_or(0);
+        writeln!(text, "DA:{},{}", addr, count).unwrap();
+    }
+    let hit = addrs.iter().filter(|a| coverage.executed.contains_key(a)).count();
+    writeln!(text, "BRF:{}\nBRH:{}", branch_found, branch_hit).unwrap();
+    writeln!(text, "LF:{}\nLH:{}", addrs.len(), hit).unwrap();
+    text.push_str("end_of_record\n");
+    text
+}
+
+pub fn parse_lcov(text: &str) -> Result<Coverage, String> {
+    let mut coverage = Coverage::default();
+    for line in text.lines() {
+        let (tag, rest) = match line.find(':') {
+            Some(index) => (&line[..index], &line[index + 1..]),
+            None => continue,
+        };
+        let fields: Vec<u64> = match tag {
+            "DA" | "BRDA" => rest
+                .split(',')
+                .map(|f| match f {
+                    "-" => Ok(0),
+                    _ => f.parse::<u64>(),
+                })
+                .collect::<Result<_, _>>()
+                .map_err(|e| format!("Bad lcov line {:?}. Error = {:#}", line, e))?,
+            _ => continue,
+        };
+        match (tag, fields.as_slice()) {
+            ("DA", &[addr, count]) => {
+                if count > 0 {
+                    *coverage.executed.entry(addr as usize).or_insert(0) += count;
+                }
+            }
+            ("BRDA", &[addr, _, direction, count]) => {
+                let entry = coverage.branches.entry(addr as usize).or_insert((0, 0));
+                match direction {
+                    0 => entry.0 += count,
+                    _ => entry.1 += count,
+                }
+            }
+            _ => return Err(format!("Bad lcov line {:?}", line)),
+        }
+    }
+    Ok(coverage)
+}
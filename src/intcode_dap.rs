@@ -0,0 +1,724 @@
+#![allow(dead_code)]
+
+use crate::intcode_disasm::{decode, reachable};
+use crate::intcode_machine::*;
+use crate::intcode_symbols::Symbols;
+use crate::json::{object, Json};
+use std::collections::{BTreeMap, BTreeSet};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+const THREAD_ID: i64 = 1;
+const REGISTERS_REF: i64 = 1;
+const MEMORY_REF: i64 = 2;
+const LOCALS_REF: i64 = 1_000;
+const CHUNK_REF: i64 = 1_000_000;
+const CHUNK_SIZE: usize = 64;
+const LOCALS: std::ops::Range<i64> = -8..16;
+
+enum Run {
+    Continue,
+    /// Runs until the call depth drops to the given value, for step over and step out.
+    Depth(usize),
+}
+
+/// Debug Adapter Protocol session for a single machine. Requests go in through `handle`,
+/// execution advances in bounded slices through `resume`, and everything to send back to the
+/// client collects in an outbox.
+pub struct Session {
+    machine: Option<Machine>,
+    calls: Arc<Mutex<CallStack>>,
+    symbols: Option<Symbols>,
+    source_dir: PathBuf,
+    breakpoints: BTreeSet<usize>,
+    source_breakpoints: BTreeMap<String, BTreeSet<usize>>,
+    ascii: bool,
+    stop_on_entry: bool,
+    configured: bool,
+    run: Option<Run>,
+    seq: i64,
+    outbox: Vec<Json>,
+    done: bool,
+}
+
+fn message_of(cause: Box<dyn std::any::Any + Send>) -> String {
+    cause
+        .downcast_ref::<String>()
+        .cloned()
+        .or_else(|| cause.downcast_ref::<&str>().map(|s| String::from(*s)))
+        .unwrap_or_default()
+}
+
+/// Runs `f` with the panic hook silenced. Interpreter panics inside are caught by `step_once`
+/// and reported to the client as `exception` stops, so printing them too would only add noise.
+fn quietly<T>(f: impl FnOnce() -> T) -> T {
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| ()));
+    let result = f();
+    panic::set_hook(hook);
+    result
+}
+
+fn parse_addr(text: &str) -> Result<usize, String> {
+    text.trim()
+        .parse()
+        .map_err(|e| format!("Bad address {:?}. Error = {:#}", text, e))
+}
+
+impl Session {
+    pub fn new() -> Session {
+        Session {
+            machine: None,
+            calls: Arc::default(),
+            symbols: None,
+            source_dir: PathBuf::new(),
+            breakpoints: BTreeSet::new(),
+            source_breakpoints: BTreeMap::new(),
+            ascii: false,
+            stop_on_entry: false,
+            configured: false,
+            run: None,
+            seq: 0,
+            outbox: vec![],
+            done: false,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.run.is_some()
+    }
+
+    /// Set once the client disconnected; the server should exit after flushing the outbox.
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Drains the outbox, numbering messages in the order they go out.
+    pub fn take_messages(&mut self) -> Vec<Json> {
+        let mut messages = std::mem::take(&mut self.outbox);
+        for message in &mut messages {
+            self.seq += 1;
+            if let Json::Object(map) = message {
+                map.insert(String::from("seq"), Json::from(self.seq as usize));
+            }
+        }
+        messages
+    }
+
+    fn send(&mut self, kind: &str, mut fields: Vec<(&str, Json)>) {
+        fields.push(("type", Json::from(kind)));
+        self.outbox.push(object(fields));
+    }
+
+    fn event(&mut self, event: &str, body: Json) {
+        self.send("event", vec![("event", Json::from(event)), ("body", body)]);
+    }
+
+    fn stopped(&mut self, reason: &str, description: Option<&str>) {
+        self.run = None;
+        let mut body = vec![
+            ("reason", Json::from(reason)),
+            ("threadId", Json::Int(THREAD_ID)),
+            ("allThreadsStopped", Json::from(true)),
+        ];
+        if let Some(description) = description {
+            body.push(("description", Json::from(description)));
+        }
+        self.event("stopped", object(body));
+    }
+
+    fn output(&mut self, category: &str, text: String) {
+        self.event(
+            "output",
+            object(vec![("category", Json::from(category)), ("output", Json::from(text))]),
+        );
+    }
+
+    pub fn handle(&mut self, request: &Json) {
+        let command = request.get("command").as_str().unwrap_or("").to_string();
+        let pending = self.outbox.len();
+        let result = self.dispatch(&command, request.get("arguments"));
+        let mut fields = vec![
+            ("request_seq", request.get("seq").clone()),
+            ("command", Json::from(command.as_str())),
+        ];
+        match result {
+            Ok(body) => {
+                fields.push(("success", Json::from(true)));
+                fields.push(("body", body));
+            }
+            Err(message) => {
+                fields.push(("success", Json::from(false)));
+                fields.push(("message", Json::from(message)));
+            }
+        }
+        // Events raised while handling (e.g. `stopped` after a step) must follow the response.
+        let events = self.outbox.split_off(pending);
+        self.send("response", fields);
+        self.outbox.extend(events);
+        if command == "initialize" {
+            self.event("initialized", object(vec![]));
+        }
+    }
+
+    fn dispatch(&mut self, command: &str, args: &Json) -> Result<Json, String> {
+        match command {
+            "initialize" => Ok(object(vec![
+                ("supportsConfigurationDoneRequest", Json::from(true)),
+                ("supportsInstructionBreakpoints", Json::from(true)),
+                ("supportsDisassembleRequest", Json::from(true)),
+                ("supportsSetVariable", Json::from(true)),
+                ("supportsTerminateRequest", Json::from(true)),
+            ])),
+            "launch" => self.launch(args),
+            "setBreakpoints" => self.set_source_breakpoints(args),
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(args),
+            "setExceptionBreakpoints" => Ok(object(vec![])),
+            "configurationDone" => {
+                self.configured = true;
+                self.start();
+                Ok(object(vec![]))
+            }
+            "threads" => Ok(object(vec![(
+                "threads",
+                Json::from(vec![object(vec![
+                    ("id", Json::Int(THREAD_ID)),
+                    ("name", Json::from("intcode")),
+                ])]),
+            )])),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => self.scopes(args),
+            "variables" => self.variables(args),
+            "setVariable" => self.set_variable(args),
+            "evaluate" => self.evaluate(args),
+            "disassemble" => self.disassemble(args),
+            "continue" => {
+                self.machine()?;
+                self.run = Some(Run::Continue);
+                Ok(object(vec![("allThreadsContinued", Json::from(true))]))
+            }
+            "next" => quietly(|| self.step_over()),
+            "stepIn" => {
+                self.machine()?;
+                quietly(|| {
+                    if let Some(state) = self.step_once() {
+                        if !self.after_step(state, "step") {
+                            self.stopped("step", None);
+                        }
+                    }
+                });
+                Ok(object(vec![]))
+            }
+            "stepOut" => {
+                let depth = self.depth()?;
+                self.run = Some(Run::Depth(depth.saturating_sub(1)));
+                if depth == 0 {
+                    self.run = Some(Run::Continue);
+                }
+                Ok(object(vec![]))
+            }
+            "pause" => {
+                self.stopped("pause", None);
+                Ok(object(vec![]))
+            }
+            "terminate" => {
+                self.run = None;
+                self.event("terminated", object(vec![]));
+                Ok(object(vec![]))
+            }
+            "disconnect" => {
+                self.run = None;
+                self.done = true;
+                Ok(object(vec![]))
+            }
+            _ => Err(format!("Unsupported request {:?}", command)),
+        }
+    }
+
+    fn machine(&self) -> Result<&Machine, String> {
+        self.machine
+            .as_ref()
+            .ok_or_else(|| String::from("No program launched"))
+    }
+
+    fn machine_mut(&mut self) -> Result<&mut Machine, String> {
+        self.machine
+            .as_mut()
+            .ok_or_else(|| String::from("No program launched"))
+    }
+
+    fn depth(&self) -> Result<usize, String> {
+        self.machine()?;
+        Ok(self.calls.lock().unwrap().frames.len())
+    }
+
+    fn launch(&mut self, args: &Json) -> Result<Json, String> {
+        let path = args
+            .get("program")
+            .as_str()
+            .ok_or_else(|| String::from("launch needs a \"program\" path"))?;
+        let program: Vec<ValueType> = load_program(path)?;
+        let mut machine = Machine::new(&program);
+        self.symbols = Symbols::for_program(path, args.get("symbols").as_str())?;
+        self.source_dir = Path::new(path).parent().map(Path::to_path_buf).unwrap_or_default();
+        if let Some(symbols) = &self.symbols {
+            machine.set_labels(Arc::new(symbols.labels.clone()));
+        }
+        match args.get("input") {
+            Json::Str(text) => parse_program(text)?
+                .into_iter()
+                .for_each(|value| machine.push_input(value)),
+            Json::Array(items) => {
+                for item in items {
+                    let value = item
+                        .as_i64()
+                        .ok_or_else(|| format!("Bad input value {}", item))?;
+                    machine.push_input(value);
+                }
+            }
+            _ => (),
+        }
+        self.ascii = args.get("ascii").as_bool().unwrap_or(false);
+        self.stop_on_entry = args.get("stopOnEntry").as_bool().unwrap_or(false);
+        self.calls = CallStack::attach(&mut machine, false);
+        self.machine = Some(machine);
+        self.start();
+        Ok(object(vec![]))
+    }
+
+    /// Execution starts once the program is launched and the client has finished configuring.
+    fn start(&mut self) {
+        if !self.configured || self.machine.is_none() || self.run.is_some() {
+            return;
+        }
+        self.event(
+            "process",
+            object(vec![("name", Json::from("intcode")), ("startMethod", Json::from("launch"))]),
+        );
+        match self.stop_on_entry {
+            true => self.stopped("entry", None),
+            false => self.run = Some(Run::Continue),
+        }
+    }
+
+    /// Line breakpoints resolve through the symbol file to the first address of each line.
+    fn set_source_breakpoints(&mut self, args: &Json) -> Result<Json, String> {
+        let path = args.get("source").get("path").as_str().unwrap_or("").to_string();
+        let mut addrs = BTreeSet::new();
+        let mut results = vec![];
+        for breakpoint in args.get("breakpoints").as_array().cloned().unwrap_or_default() {
+            let line = breakpoint.get("line").as_i64().unwrap_or(0).max(0) as usize;
+            let found = match &self.symbols {
+                Some(symbols) => symbols.addrs_for_line(&path, line),
+                None => vec![],
+            };
+            let result = match (found.first(), &self.symbols) {
+                (Some(addr), _) => object(vec![
+                    ("verified", Json::from(true)),
+                    ("line", Json::from(line)),
+                    ("instructionReference", Json::from(addr.to_string())),
+                ]),
+                (None, Some(_)) => object(vec![
+                    ("verified", Json::from(false)),
+                    ("message", Json::from("No code for this line")),
+                ]),
+                (None, None) => object(vec![
+                    ("verified", Json::from(false)),
+                    ("message", Json::from("No symbols loaded, use instruction breakpoints")),
+                ]),
+            };
+            addrs.extend(found);
+            results.push(result);
+        }
+        self.source_breakpoints.insert(path, addrs);
+        Ok(object(vec![("breakpoints", Json::from(results))]))
+    }
+
+    /// Source files in the symbol file are relative to the program.
+    fn source(&self, file: &str) -> Json {
+        let path = self.source_dir.join(file);
+        object(vec![
+            ("name", Json::from(file)),
+            ("path", Json::from(path.to_string_lossy().into_owned())),
+        ])
+    }
+
+    fn is_breakpoint(&self, addr: usize) -> bool {
+        self.breakpoints.contains(&addr)
+            || self.source_breakpoints.values().any(|addrs| addrs.contains(&addr))
+    }
+
+    fn set_instruction_breakpoints(&mut self, args: &Json) -> Result<Json, String> {
+        self.breakpoints.clear();
+        let mut results = vec![];
+        for breakpoint in args.get("breakpoints").as_array().cloned().unwrap_or_default() {
+            let reference = breakpoint.get("instructionReference").as_str().unwrap_or("");
+            let offset = breakpoint.get("offset").as_i64().unwrap_or(0);
+            let result = match parse_addr(reference) {
+                Ok(addr) if addr as i64 + offset >= 0 => {
+                    let addr = (addr as i64 + offset) as usize;
+                    self.breakpoints.insert(addr);
+                    object(vec![
+                        ("verified", Json::from(true)),
+                        ("instructionReference", Json::from(addr.to_string())),
+                    ])
+                }
+                Ok(_) => object(vec![
+                    ("verified", Json::from(false)),
+                    ("message", Json::from("Negative address")),
+                ]),
+                Err(e) => object(vec![("verified", Json::from(false)), ("message", Json::from(e))]),
+            };
+            results.push(result);
+        }
+        Ok(object(vec![("breakpoints", Json::from(results))]))
+    }
+
+    /// Steps the machine once, turning an interpreter panic into an `exception` stop.
+    fn step_once(&mut self) -> Option<State> {
+        let machine = self.machine.as_mut()?;
+        let result = panic::catch_unwind(AssertUnwindSafe(|| step(machine)));
+        let outputs: Vec<ValueType> = machine.drain_output().collect();
+        if !outputs.is_empty() {
+            let text = self.format_outputs(&outputs);
+            self.output("stdout", text);
+        }
+        match result {
+            Ok(state) => Some(state),
+            Err(cause) => {
+                let message = message_of(cause);
+                self.output("stderr", format!("{}\n", message));
+                self.stopped("exception", Some(&message));
+                None
+            }
+        }
+    }
+
+    fn format_outputs(&self, outputs: &[ValueType]) -> String {
+        outputs
+            .iter()
+            .map(|&value| match self.ascii && (0..128).contains(&value) {
+                true => (value as u8 as char).to_string(),
+                false => format!("{}\n", value),
+            })
+            .collect()
+    }
+
+    /// Reports a stop if the step ended execution or hit something worth stopping for.
+    /// Returns true when the machine stopped.
+    fn after_step(&mut self, state: State, reason: &str) -> bool {
+        match state {
+            State::Halted => {
+                self.run = None;
+                let steps = self.machine.as_ref().map_or(0, |m| m.steps());
+                self.output("console", format!("Program halted after {} steps\n", steps));
+                self.event("exited", object(vec![("exitCode", Json::Int(0))]));
+                self.event("terminated", object(vec![]));
+                true
+            }
+            State::InputBlock => {
+                self.output(
+                    "console",
+                    String::from("Waiting for input, evaluate values in the debug console\n"),
+                );
+                self.stopped("pause", Some("Waiting for input"));
+                true
+            }
+            _ => {
+                let cursor = self.machine.as_ref().map_or(0, |m| m.cursor());
+                if self.is_breakpoint(cursor) {
+                    self.stopped("breakpoint", None);
+                    return true;
+                }
+                let depth = self.depth().unwrap_or(0);
+                if let Some(Run::Depth(target)) = self.run {
+                    if depth <= target {
+                        self.stopped(reason, None);
+                        return true;
+                    }
+                }
+                false
+            }
+        }
+    }
+
+    fn step_over(&mut self) -> Result<Json, String> {
+        let depth = self.depth()?;
+        if let Some(state) = self.step_once() {
+            if !self.after_step(state, "step") {
+                match self.depth()? > depth {
+                    true => self.run = Some(Run::Depth(depth)),
+                    false => self.stopped("step", None),
+                }
+            }
+        }
+        Ok(object(vec![]))
+    }
+
+    /// Runs at most `budget` steps of a `continue`, `next` or `stepOut`.
+    pub fn resume(&mut self, budget: u64) {
+        quietly(|| {
+            for _ in 0..budget {
+                if self.run.is_none() {
+                    return;
+                }
+                match self.step_once() {
+                    Some(state) => {
+                        if self.after_step(state, "step") {
+                            return;
+                        }
+                    }
+                    None => return,
+                }
+            }
+        })
+    }
+
+    /// Relative base of each frame, innermost first, alongside the frame's function and pc.
+    fn frames(&self) -> Result<Vec<(usize, usize, i64)>, String> {
+        let machine = self.machine()?;
+        let mut frames = vec![];
+        let mut at = machine.cursor();
+        let mut base = machine.relative_base();
+        for frame in self.calls.lock().unwrap().frames.iter().rev() {
+            frames.push((frame.function, at, base));
+            at = frame.call_site;
+            base = frame.caller_base;
+        }
+        frames.push((0, at, base));
+        Ok(frames)
+    }
+
+    fn stack_trace(&mut self) -> Result<Json, String> {
+        let labels = self.symbols.as_ref().map(|symbols| &symbols.labels);
+        let frames: Vec<Json> = self
+            .frames()?
+            .iter()
+            .enumerate()
+            .map(|(index, &(function, at, _))| {
+                let name = format!("{} @ {}", function_name(labels, function), describe_addr(labels, at));
+                let mut fields = vec![
+                    ("id", Json::from(index + 1)),
+                    ("name", Json::from(name)),
+                    ("instructionPointerReference", Json::from(at.to_string())),
+                    ("line", Json::Int(0)),
+                    ("column", Json::Int(0)),
+                ];
+                if let Some((file, line)) = self.symbols.as_ref().and_then(|s| s.source_line(at)) {
+                    fields.push(("source", self.source(file)));
+                    fields.push(("line", Json::from(line)));
+                }
+                object(fields)
+            })
+            .collect();
+        let total = frames.len();
+        Ok(object(vec![
+            ("stackFrames", Json::from(frames)),
+            ("totalFrames", Json::from(total)),
+        ]))
+    }
+
+    fn scopes(&mut self, args: &Json) -> Result<Json, String> {
+        let frame = args.get("frameId").as_i64().unwrap_or(1);
+        let scope = |name: &str, reference: i64, expensive: bool| {
+            object(vec![
+                ("name", Json::from(name)),
+                ("variablesReference", Json::Int(reference)),
+                ("expensive", Json::from(expensive)),
+            ])
+        };
+        Ok(object(vec![(
+            "scopes",
+            Json::from(vec![
+                scope("Registers", REGISTERS_REF, false),
+                scope("Frame", LOCALS_REF + frame, false),
+                scope("Memory", MEMORY_REF, true),
+            ]),
+        )]))
+    }
+
+    fn variables(&mut self, args: &Json) -> Result<Json, String> {
+        let reference = args.get("variablesReference").as_i64().unwrap_or(0);
+        let machine = self.machine()?;
+        let variable = |name: String, value: String, reference: i64| {
+            object(vec![
+                ("name", Json::from(name)),
+                ("value", Json::from(value)),
+                ("variablesReference", Json::Int(reference)),
+            ])
+        };
+        let list = |values: Vec<ValueType>| {
+            let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+            format!("[{}]", values.join(", "))
+        };
+        let mut variables = vec![];
+        if reference == REGISTERS_REF {
+            variables.push(variable(String::from("pc"), machine.cursor().to_string(), 0));
+            variables.push(variable(String::from("rb"), machine.relative_base().to_string(), 0));
+            variables.push(variable(String::from("steps"), machine.steps().to_string(), 0));
+            variables.push(variable(
+                String::from("outputs"),
+                list(machine.outputs().cloned().collect()),
+                0,
+            ));
+            variables.push(variable(String::from("inputs queued"), machine.input_len().to_string(), 0));
+        } else if reference == MEMORY_REF {
+            for start in (0..machine.memory_len()).step_by(CHUNK_SIZE) {
+                let end = (start + CHUNK_SIZE).min(machine.memory_len()) - 1;
+                variables.push(variable(
+                    format!("{}..{}", start, end),
+                    String::new(),
+                    CHUNK_REF + (start / CHUNK_SIZE) as i64,
+                ));
+            }
+        } else if reference >= CHUNK_REF {
+            let start = (reference - CHUNK_REF) as usize * CHUNK_SIZE;
+            for addr in start..(start + CHUNK_SIZE).min(machine.memory_len()) {
+                let name = match self.symbols.as_ref().and_then(|s| s.name_of(addr)) {
+                    Some(name) => format!("[{}] {}", addr, name),
+                    None => format!("[{}]", addr),
+                };
+                variables.push(variable(name, machine.memget(addr).to_string(), 0));
+            }
+        } else if reference > LOCALS_REF {
+            let frames = self.frames()?;
+            let &(_, _, base) = frames
+                .get((reference - LOCALS_REF - 1) as usize)
+                .ok_or_else(|| String::from("No such frame"))?;
+            for offset in LOCALS {
+                let addr = base + offset;
+                if addr < 0 {
+                    continue;
+                }
+                variables.push(variable(
+                    format!("[rb{:+}]", offset),
+                    machine.memget(addr as usize).to_string(),
+                    0,
+                ));
+            }
+        }
+        Ok(object(vec![("variables", Json::from(variables))]))
+    }
+
+    fn set_variable(&mut self, args: &Json) -> Result<Json, String> {
+        let reference = args.get("variablesReference").as_i64().unwrap_or(0);
+        let name = args.get("name").as_str().unwrap_or("");
+        let value: ValueType = Word::parse_word(args.get("value").as_str().unwrap_or("").trim())?;
+        let inner = name.trim_start_matches('[').split(']').next().unwrap_or("");
+        let addr = if reference >= CHUNK_REF {
+            parse_addr(inner)?
+        } else if reference > LOCALS_REF {
+            let frames = self.frames()?;
+            let &(_, _, base) = frames
+                .get((reference - LOCALS_REF - 1) as usize)
+                .ok_or_else(|| String::from("No such frame"))?;
+            let offset: i64 = inner
+                .trim_start_matches("rb")
+                .trim_start_matches('+')
+                .parse()
+                .map_err(|_| format!("Bad variable {:?}", name))?;
+            match base + offset {
+                addr if addr >= 0 => addr as usize,
+                _ => return Err(format!("Bad variable {:?}", name)),
+            }
+        } else {
+            return Err(format!("{} is read-only", name));
+        };
+        self.machine_mut()?.memset(addr, value);
+        Ok(object(vec![("value", Json::from(value.to_string()))]))
+    }
+
+    /// In the debug console numbers (or, for ASCII programs, any text line) are queued as
+    /// input; `pc`, `rb`, `bt` and `[addr]` or `[name]` inspect the machine.
+    fn evaluate(&mut self, args: &Json) -> Result<Json, String> {
+        let expression = args.get("expression").as_str().unwrap_or("").trim().to_string();
+        let repl = args.get("context").as_str() == Some("repl");
+        let machine = self.machine()?;
+        let result = match expression.as_str() {
+            "pc" => machine.cursor().to_string(),
+            "rb" => machine.relative_base().to_string(),
+            "bt" => self.calls.lock().unwrap().backtrace(machine.cursor(), machine.labels()).join("\n"),
+            text if text.starts_with('[') && text.ends_with(']') => {
+                let inner = &text[1..text.len() - 1];
+                let addr = match self.symbols.as_ref().and_then(|s| s.lookup(inner.trim())) {
+                    Some(addr) => addr,
+                    None => parse_addr(inner)?,
+                };
+                machine.memget(addr).to_string()
+            }
+            text if repl => {
+                let values: Vec<ValueType> = match parse_program(text) {
+                    Ok(values) => values,
+                    Err(_) if self.ascii => text.bytes().chain(Some(b'\n')).map(|b| b as ValueType).collect(),
+                    Err(e) => return Err(e),
+                };
+                let machine = self.machine_mut()?;
+                for &value in &values {
+                    machine.push_input(value);
+                }
+                format!("queued {} input values", values.len())
+            }
+            _ => return Err(format!("Unknown expression {:?}", expression)),
+        };
+        Ok(object(vec![
+            ("result", Json::from(result)),
+            ("variablesReference", Json::Int(0)),
+        ]))
+    }
+
+    fn disassemble(&mut self, args: &Json) -> Result<Json, String> {
+        let machine = self.machine()?;
+        let reference = parse_addr(args.get("memoryReference").as_str().unwrap_or(""))?;
+        let start = reference as i64 + args.get("offset").as_i64().unwrap_or(0);
+        let offset = args.get("instructionOffset").as_i64().unwrap_or(0);
+        let count = args.get("instructionCount").as_i64().unwrap_or(0).max(0);
+
+        let memory = machine.memory_snapshot();
+        let start = start.max(0) as usize;
+        let addrs: Vec<usize> = reachable(&memory, &[0, machine.cursor(), start])
+            .keys()
+            .cloned()
+            .collect();
+        let position = addrs.iter().position(|&addr| addr == start).unwrap_or(0) as i64;
+        let instructions = (position + offset..position + offset + count)
+            .map(|index| {
+                match (index >= 0).then(|| addrs.get(index as usize)).flatten() {
+                    Some(&addr) => {
+                        let text = match (decode(&memory, addr), &self.symbols) {
+                            (Ok(inst), Some(symbols)) => symbols.render(&inst),
+                            (Ok(inst), None) => inst.to_string(),
+                            (Err(_), _) => String::from("<invalid>"),
+                        };
+                        let mut fields = vec![
+                            ("address", Json::from(addr.to_string())),
+                            ("instruction", Json::from(text)),
+                        ];
+                        if let Some(symbols) = &self.symbols {
+                            if let Some(label) = symbols.labels.get(&addr) {
+                                fields.push(("symbol", Json::from(label.as_str())));
+                            }
+                            if let Some((file, line)) = symbols.source_line(addr) {
+                                fields.push(("location", self.source(file)));
+                                fields.push(("line", Json::from(line)));
+                            }
+                        }
+                        object(fields)
+                    }
+                    None => object(vec![
+                        ("address", Json::from("-1")),
+                        ("instruction", Json::from("??")),
+                        ("presentationHint", Json::from("invalid")),
+                    ]),
+                }
+            })
+            .collect::<Vec<Json>>();
+        Ok(object(vec![("instructions", Json::from(instructions))]))
+    }
+}
+
+impl Default for Session {
+    fn default() -> Session {
+        Session::new()
+    }
+}
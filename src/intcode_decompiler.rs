@@ -0,0 +1,417 @@
+#![allow(dead_code)]
+
+use crate::intcode_disasm::{call_setup, code_cells, decode, Instruction, Mode, Param};
+use crate::intcode_machine::*;
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Flow {
+    Plain,
+    CallSetup,
+    Call { target: usize },
+    Return,
+    Goto(usize),
+    Branch { target: usize },
+    IndirectJump,
+    Halt,
+}
+
+struct Function {
+    entry: usize,
+    frame: ValueType,
+    body: BTreeMap<usize, (Instruction, Flow)>,
+}
+
+pub struct Decompiler<'a> {
+    memory: &'a [ValueType],
+    functions: BTreeMap<usize, Function>,
+    code: BTreeSet<usize>,
+}
+
+struct Emitter<'a, 'b> {
+    decompiler: &'b Decompiler<'a>,
+    function: &'b Function,
+    addrs: Vec<usize>,
+    lines: Vec<(usize, Option<usize>, String)>,
+    gotos: BTreeSet<usize>,
+    loops: Vec<(usize, usize)>,
+}
+
+fn classify(memory: &[ValueType], inst: &Instruction) -> Flow {
+    if inst.opcode == HALT {
+        return Flow::Halt;
+    }
+    if !inst.is_jump() {
+        return Flow::Plain;
+    }
+    if inst.constant_condition() == Some(false) {
+        return Flow::Plain;
+    }
+    match (inst.static_target(), inst.params[1].mode) {
+        (Some(target), _) => match inst.constant_condition() {
+            Some(true) if call_setup(memory, inst).is_some() => Flow::Call { target },
+            Some(true) => Flow::Goto(target),
+            _ => Flow::Branch { target },
+        },
+        (None, Mode::Relative) if inst.constant_condition() == Some(true) => Flow::Return,
+        _ => Flow::IndirectJump,
+    }
+}
+
+fn explore(memory: &[ValueType], entry: usize) -> (Function, Vec<usize>) {
+    let mut body = BTreeMap::new();
+    let mut callees = vec![];
+    let mut pending = vec![entry];
+    while let Some(addr) = pending.pop() {
+        if body.contains_key(&addr) {
+            continue;
+        }
+        let inst = match decode(memory, addr) {
+            Ok(inst) => inst,
+            Err(_) => continue,
+        };
+        let flow = classify(memory, &inst);
+        match &flow {
+            Flow::Plain | Flow::CallSetup => pending.push(inst.next_addr()),
+            Flow::Call { target } => {
+                callees.push(*target);
+                pending.push(inst.next_addr());
+            }
+            Flow::Goto(target) => pending.push(*target),
+            Flow::Branch { target } => {
+                pending.push(inst.next_addr());
+                pending.push(*target);
+            }
+            Flow::IndirectJump if inst.constant_condition().is_none() => {
+                pending.push(inst.next_addr())
+            }
+            Flow::Return | Flow::IndirectJump | Flow::Halt => (),
+        }
+        body.insert(addr, (inst, flow));
+    }
+
+    let setups: Vec<usize> = body
+        .values()
+        .filter(|(_, flow)| matches!(flow, Flow::Call { .. }))
+        .filter_map(|(inst, _)| call_setup(memory, inst))
+        .collect();
+    for addr in setups {
+        if let Some(entry) = body.get_mut(&addr) {
+            entry.1 = Flow::CallSetup;
+        }
+    }
+
+    // An ARB at the entry is a frame prologue only if no loop comes back to run it again.
+    let loops_to_entry = body.values().any(|(_, flow)| match flow {
+        Flow::Goto(target) | Flow::Branch { target } => *target == entry,
+        _ => false,
+    });
+    let frame = match body.get(&entry) {
+        Some((inst, _))
+            if inst.opcode == MOVE_RBASE && inst.params[0].mode == Mode::Immediate && !loops_to_entry =>
+        {
+            inst.params[0].value
+        }
+        _ => 0,
+    };
+    (Function { entry, frame, body }, callees)
+}
+
+impl<'a> Decompiler<'a> {
+    pub fn new(memory: &'a [ValueType]) -> Decompiler<'a> {
+        let mut functions = BTreeMap::new();
+        let mut pending = vec![0];
+        while let Some(entry) = pending.pop() {
+            if functions.contains_key(&entry) {
+                continue;
+            }
+            let (function, callees) = explore(memory, entry);
+            pending.extend(callees);
+            functions.insert(entry, function);
+        }
+
+        let all: BTreeMap<usize, Instruction> = functions
+            .values()
+            .flat_map(|f| f.body.values().map(|(inst, _)| (inst.addr, inst.clone())))
+            .collect();
+        let code = code_cells(&all);
+
+        Decompiler {
+            memory,
+            functions,
+            code,
+        }
+    }
+
+    pub fn function_name(&self, entry: usize) -> String {
+        match entry {
+            0 => String::from("main"),
+            _ => format!("fn_{}", entry),
+        }
+    }
+
+    fn cell_name(&self, addr: ValueType) -> String {
+        match self.code.contains(&(addr as usize)) {
+            true => format!("code[{}]", addr),
+            false => format!("g_{}", addr),
+        }
+    }
+
+    fn operand(&self, param: &Param) -> String {
+        match param.mode {
+            Mode::Immediate => format!("{}", param.value),
+            Mode::Position => self.cell_name(param.value),
+            Mode::Relative if param.value < 0 => format!("arg_{}", -param.value),
+            Mode::Relative => format!("local_{}", param.value),
+        }
+    }
+
+    fn globals(&self) -> BTreeSet<ValueType> {
+        self.functions
+            .values()
+            .flat_map(|f| f.body.values())
+            .flat_map(|(inst, _)| inst.params.iter())
+            .filter(|p| p.mode == Mode::Position && !self.code.contains(&(p.value as usize)))
+            .map(|p| p.value)
+            .collect()
+    }
+
+    fn statement(&self, function: &Function, inst: &Instruction) -> Option<String> {
+        let p = |i: usize| self.operand(&inst.params[i]);
+        let imm = |i: usize| match inst.params[i].mode {
+            Mode::Immediate => Some(inst.params[i].value),
+            _ => None,
+        };
+        // The frame is only torn down by an ARB right before the return.
+        let returns_next = matches!(function.body.get(&inst.next_addr()), Some((_, Flow::Return)));
+        Some(match inst.opcode {
+            ADD if imm(1) == Some(0) => format!("{} = {};", p(2), p(0)),
+            ADD if imm(0) == Some(0) => format!("{} = {};", p(2), p(1)),
+            ADD => match imm(1) {
+                Some(v) if v < 0 => format!("{} = {} - {};", p(2), p(0), -v),
+                _ => format!("{} = {} + {};", p(2), p(0), p(1)),
+            },
+            MULTIPLY if imm(1) == Some(1) => format!("{} = {};", p(2), p(0)),
+            MULTIPLY if imm(1) == Some(-1) => format!("{} = -{};", p(2), p(0)),
+            MULTIPLY => format!("{} = {} * {};", p(2), p(0), p(1)),
+            CMP_LT => format!("{} = {} < {};", p(2), p(0), p(1)),
+            CMP_EQ => format!("{} = {} == {};", p(2), p(0), p(1)),
+            INPUT => format!("{} = input();", p(0)),
+            OUTPUT => format!("output({});", p(0)),
+            MOVE_RBASE if inst.addr == function.entry && function.frame != 0 => return None,
+            MOVE_RBASE if function.frame != 0 && imm(0) == Some(-function.frame) && returns_next => return None,
+            MOVE_RBASE => format!("rb += {};", p(0)),
+            HALT => String::from("halt();"),
+            _ => format!("/* {} */", inst),
+        })
+    }
+
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        for addr in self.globals() {
+            let init = self.memory.get(addr as usize).cloned().unwrap_or(0);
+            text.push_str(&format!("int g_{} = {};\n", addr, init));
+        }
+        for function in self.functions.values() {
+            text.push('\n');
+            text.push_str(&format!(
+                "void {}() {{ // @{}, frame {}\n",
+                self.function_name(function.entry),
+                function.entry,
+                function.frame
+            ));
+            let mut emitter = Emitter {
+                decompiler: self,
+                function,
+                addrs: function.body.keys().cloned().collect(),
+                lines: vec![],
+                gotos: BTreeSet::new(),
+                loops: vec![],
+            };
+            emitter.emit_entry();
+            for (addr, label, line) in emitter.lines.iter() {
+                match label {
+                    Some(target) if emitter.gotos.contains(target) => {
+                        text.push_str(&format!("L_{}:\n", target))
+                    }
+                    Some(_) => (),
+                    None => text.push_str(&format!("    {} // {}\n", line, addr)),
+                }
+            }
+            text.push_str("}\n");
+        }
+        text
+    }
+}
+
+impl<'a, 'b> Emitter<'a, 'b> {
+    fn emit_entry(&mut self) {
+        let entry = self.addrs.binary_search(&self.function.entry).unwrap_or(0);
+        self.emit_range(entry, self.addrs.len(), 0);
+        if entry > 0 {
+            // Code placed before the entry point is only reachable through jumps.
+            self.emit_range(0, entry, 0);
+        }
+    }
+
+    fn pos(&self, addr: usize) -> Option<usize> {
+        self.addrs.binary_search(&addr).ok()
+    }
+
+    fn inst(&self, index: usize) -> &'b (Instruction, Flow) {
+        &self.function.body[&self.addrs[index]]
+    }
+
+    fn addr_after(&self, index: usize) -> usize {
+        match self.addrs.get(index) {
+            Some(&addr) => addr,
+            None => usize::MAX,
+        }
+    }
+
+    fn push(&mut self, addr: usize, depth: usize, line: String) {
+        self.lines.push((addr, None, format!("{}{}", "    ".repeat(depth), line)));
+    }
+
+    fn jump_to(&mut self, target: usize) -> String {
+        for &(header, exit) in self.loops.iter().rev() {
+            if target == header {
+                return String::from("continue;");
+            }
+            if target == exit {
+                return String::from("break;");
+            }
+        }
+        self.gotos.insert(target);
+        format!("goto L_{};", target)
+    }
+
+    fn condition(&self, inst: &Instruction, taken: bool) -> String {
+        let value = self.decompiler.operand(&inst.params[0]);
+        match (inst.opcode == JMP_IF_NON_ZERO) == taken {
+            true => value,
+            false => format!("!{}", value),
+        }
+    }
+
+    /// Latest index in (lo, hi) whose instruction jumps back to `addrs[lo]`.
+    fn back_edge(&self, lo: usize, hi: usize) -> Option<usize> {
+        let header = self.addrs[lo];
+        (lo..hi).rev().find(|&j| match &self.inst(j).1 {
+            Flow::Goto(target) | Flow::Branch { target } => *target == header,
+            _ => false,
+        })
+    }
+
+    fn emit_range(&mut self, lo: usize, hi: usize, depth: usize) {
+        let mut i = lo;
+        while i < hi {
+            let (inst, flow) = self.inst(i);
+            let addr = inst.addr;
+            self.lines.push((addr, Some(addr), String::new()));
+
+            if let Some(j) = self.back_edge(i, hi) {
+                if self.loops.last().map(|l| l.0) != Some(addr) {
+                    let exit = self.addr_after(j + 1);
+                    let (tail, _) = self.inst(j);
+                    let tail_is_branch = tail.opcode != HALT && tail.constant_condition().is_none();
+                    self.loops.push((addr, exit));
+                    match tail_is_branch {
+                        true => {
+                            self.push(addr, depth, String::from("do {"));
+                            self.emit_range(i, j, depth + 1);
+                            let cond = self.condition(tail, true);
+                            self.push(tail.addr, depth, format!("}} while ({});", cond));
+                        }
+                        false => {
+                            self.push(addr, depth, String::from("while (1) {"));
+                            self.emit_range(i, j, depth + 1);
+                            self.push(tail.addr, depth, String::from("}"));
+                        }
+                    }
+                    self.loops.pop();
+                    i = j + 1;
+                    continue;
+                }
+            }
+
+            match flow {
+                Flow::CallSetup => (),
+                Flow::Plain => {
+                    if let Some(line) = self.decompiler.statement(self.function, inst) {
+                        self.push(addr, depth, line);
+                    }
+                }
+                Flow::Call { target } => {
+                    let name = self.decompiler.function_name(*target);
+                    self.push(addr, depth, format!("{}();", name));
+                }
+                Flow::Return => self.push(addr, depth, String::from("return;")),
+                Flow::Halt => self.push(addr, depth, String::from("halt();")),
+                Flow::IndirectJump => {
+                    let target = self.decompiler.operand(&inst.params[1]);
+                    let line = match inst.constant_condition() {
+                        Some(true) => format!("goto *{};", target),
+                        _ => format!("if ({}) goto *{};", self.condition(inst, true), target),
+                    };
+                    self.push(addr, depth, line);
+                }
+                Flow::Goto(target) => {
+                    if *target != self.addr_after(i + 1) || i + 1 == hi {
+                        let line = self.jump_to(*target);
+                        self.push(addr, depth, line);
+                    }
+                }
+                Flow::Branch { target } => {
+                    let skip_to = self.pos(*target).filter(|&t| t > i && t <= hi);
+                    let skip_to = match (skip_to, *target == self.addr_after(hi)) {
+                        (None, true) => Some(hi),
+                        (found, _) => found,
+                    };
+                    let exits_loop = self.loops.iter().any(|&(h, e)| *target == h || *target == e);
+                    match skip_to {
+                        Some(t) if !exits_loop || t < hi => {
+                            let cond = self.condition(inst, false);
+                            let else_end = match &self.inst(t - 1).1 {
+                                Flow::Goto(u) if t - 1 > i => self
+                                    .pos(*u)
+                                    .filter(|&u| u > t && u <= hi)
+                                    .or(match *u == self.addr_after(hi) {
+                                        true => Some(hi),
+                                        false => None,
+                                    }),
+                                _ => None,
+                            };
+                            self.push(addr, depth, format!("if ({}) {{", cond));
+                            match else_end {
+                                Some(u) => {
+                                    self.emit_range(i + 1, t - 1, depth + 1);
+                                    self.push(addr, depth, String::from("} else {"));
+                                    self.emit_range(t, u, depth + 1);
+                                    self.push(addr, depth, String::from("}"));
+                                    i = u;
+                                }
+                                None => {
+                                    self.emit_range(i + 1, t, depth + 1);
+                                    self.push(addr, depth, String::from("}"));
+                                    i = t;
+                                }
+                            }
+                            continue;
+                        }
+                        _ => {
+                            let cond = self.condition(inst, true);
+                            let line = self.jump_to(*target);
+                            self.push(addr, depth, format!("if ({}) {}", cond, line));
+                        }
+                    }
+                }
+            }
+            i += 1;
+        }
+    }
+}
+
+pub fn decompile(memory: &[ValueType]) -> String {
+    Decompiler::new(memory).render()
+}
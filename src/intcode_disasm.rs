@@ -0,0 +1,258 @@
+#![allow(dead_code)]
+
+use crate::intcode_machine::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Position,
+    Immediate,
+    Relative,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Param {
+    pub mode: Mode,
+    pub value: ValueType,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    pub addr: usize,
+    pub opcode: ValueType,
+    pub params: Vec<Param>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DecodeError {
+    OutOfBounds(usize),
+    InvalidOpcode(usize, ValueType),
+    InvalidMode(usize, ValueType),
+}
+
+pub fn param_count(opcode: ValueType) -> Option<usize> {
+    match opcode {
+        ADD | MULTIPLY | CMP_LT | CMP_EQ => Some(3),
+        JMP_IF_NON_ZERO | JMP_IF_ZERO => Some(2),
+        INPUT | OUTPUT | MOVE_RBASE => Some(1),
+        HALT => Some(0),
+        _ => None,
+    }
+}
+
+pub fn mnemonic(opcode: ValueType) -> &'static str {
+    match opcode {
+        ADD => "ADD",
+        MULTIPLY => "MUL",
+        INPUT => "IN",
+        OUTPUT => "OUT",
+        JMP_IF_NON_ZERO => "JNZ",
+        JMP_IF_ZERO => "JZ",
+        CMP_LT => "LT",
+        CMP_EQ => "EQ",
+        MOVE_RBASE => "ARB",
+        HALT => "HALT",
+        _ => "???",
+    }
+}
+
+pub fn decode(memory: &[ValueType], addr: usize) -> Result<Instruction, DecodeError> {
+    let code = *memory.get(addr).ok_or(DecodeError::OutOfBounds(addr))?;
+    let opcode = code % 100;
+    let count = param_count(opcode).ok_or(DecodeError::InvalidOpcode(addr, code))?;
+    let mut params = Vec::with_capacity(count);
+    for (index, ten) in TENS.iter().enumerate().take(count) {
+        let value = *memory
+            .get(addr + index + 1)
+            .ok_or(DecodeError::OutOfBounds(addr + index + 1))?;
+        let mode = match code / ten % 10 {
+            MODE_POSITION => Mode::Position,
+            MODE_IMMEDIATE => Mode::Immediate,
+            MODE_RELATIVE => Mode::Relative,
+            _ => return Err(DecodeError::InvalidMode(addr, code)),
+        };
+        params.push(Param { mode, value });
+    }
+    Ok(Instruction {
+        addr,
+        opcode,
+        params,
+    })
+}
+
+impl Instruction {
+    pub fn len(&self) -> usize {
+        self.params.len() + 1
+    }
+
+    pub fn next_addr(&self) -> usize {
+        self.addr + self.len()
+    }
+
+    pub fn is_jump(&self) -> bool {
+        self.opcode == JMP_IF_NON_ZERO || self.opcode == JMP_IF_ZERO
+    }
+
+    /// Some(true) if the jump is always taken, Some(false) if never, None if it depends on memory.
+    pub fn constant_condition(&self) -> Option<bool> {
+        if !self.is_jump() || self.params[0].mode != Mode::Immediate {
+            return None;
+        }
+        let non_zero = self.params[0].value != 0;
+        Some(match self.opcode {
+            JMP_IF_NON_ZERO => non_zero,
+            _ => !non_zero,
+        })
+    }
+
+    /// Target of a jump when it is encoded as an immediate value.
+    pub fn static_target(&self) -> Option<usize> {
+        match self.is_jump() && self.params[1].mode == Mode::Immediate {
+            true => Some(self.params[1].value as usize),
+            false => None,
+        }
+    }
+
+    /// Static successors. Jumps through memory contribute only their fall-through.
+    /// Calls are assumed to return to the instruction after the jump.
+    pub fn successors(&self, memory: &[ValueType]) -> Vec<usize> {
+        if self.opcode == HALT {
+            return vec![];
+        }
+        if !self.is_jump() {
+            return vec![self.next_addr()];
+        }
+        let mut result = vec![];
+        if self.constant_condition() != Some(true) || call_setup(memory, self).is_some() {
+            result.push(self.next_addr());
+        }
+        if self.constant_condition() != Some(false) {
+            if let Some(target) = self.static_target() {
+                result.push(target);
+            }
+        }
+        result
+    }
+
+    pub fn out_param(&self) -> Option<&Param> {
+        match self.opcode {
+            ADD | MULTIPLY | CMP_LT | CMP_EQ => Some(&self.params[2]),
+            INPUT => Some(&self.params[0]),
+            _ => None,
+        }
+    }
+
+    /// Memory words for this instruction, the inverse of `decode`.
+    pub fn encode(&self) -> Vec<ValueType> {
+        let code = self
+            .params
+            .iter()
+            .zip(TENS.iter())
+            .fold(self.opcode, |code, (param, ten)| {
+                code + ten
+                    * match param.mode {
+                        Mode::Position => MODE_POSITION,
+                        Mode::Immediate => MODE_IMMEDIATE,
+                        Mode::Relative => MODE_RELATIVE,
+                    }
+            });
+        std::iter::once(code)
+            .chain(self.params.iter().map(|param| param.value))
+            .collect()
+    }
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.mode {
+            Mode::Position => write!(f, "[{}]", self.value),
+            Mode::Immediate => write!(f, "#{}", self.value),
+            Mode::Relative if self.value < 0 => write!(f, "[rb{}]", self.value),
+            Mode::Relative => write!(f, "[rb+{}]", self.value),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", mnemonic(self.opcode))?;
+        for (index, param) in self.params.iter().enumerate() {
+            let sep = match index {
+                0 => " ",
+                _ => ", ",
+            };
+            write!(f, "{}{}", sep, param)?;
+        }
+        Ok(())
+    }
+}
+
+/// A call is a store of the return address into the stack frame followed by an
+/// unconditional jump; the callee returns through the stored address. Returns the
+/// address of the store.
+pub fn call_setup(memory: &[ValueType], jump: &Instruction) -> Option<usize> {
+    if jump.addr < 4 || jump.constant_condition() != Some(true) {
+        return None;
+    }
+    let setup = decode(memory, jump.addr - 4).ok()?;
+    let ret = jump.next_addr() as ValueType;
+    let out = setup.out_param()?;
+    if out.mode != Mode::Relative || setup.params[..2].iter().any(|p| p.mode != Mode::Immediate) {
+        return None;
+    }
+    let (a, b) = (setup.params[0].value, setup.params[1].value);
+    let stores_ret = match setup.opcode {
+        ADD => a + b == ret,
+        MULTIPLY => a * b == ret,
+        _ => false,
+    };
+    match stores_ret {
+        true => Some(setup.addr),
+        false => None,
+    }
+}
+
+/// Decodes every instruction reachable from `entries` by following static control flow.
+pub fn reachable(memory: &[ValueType], entries: &[usize]) -> BTreeMap<usize, Instruction> {
+    let mut found = BTreeMap::new();
+    let mut pending: Vec<usize> = entries.to_vec();
+    while let Some(addr) = pending.pop() {
+        if found.contains_key(&addr) {
+            continue;
+        }
+        if let Ok(inst) = decode(memory, addr) {
+            pending.extend(inst.successors(memory));
+            found.insert(addr, inst);
+        }
+    }
+    found
+}
+
+/// Addresses occupied by the given instructions, opcode and operands alike.
+pub fn code_cells(instructions: &BTreeMap<usize, Instruction>) -> BTreeSet<usize> {
+    instructions
+        .values()
+        .flat_map(|inst| inst.addr..inst.next_addr())
+        .collect()
+}
+
+/// Linear disassembly: reachable instructions are decoded, everything else is shown as data.
+pub fn disassemble(memory: &[ValueType]) -> String {
+    let instructions = reachable(memory, &[0]);
+    let mut text = String::new();
+    let mut addr = 0;
+    while addr < memory.len() {
+        match instructions.get(&addr) {
+            Some(inst) => {
+                text.push_str(&format!("{:>6}: {}\n", addr, inst));
+                addr = inst.next_addr();
+            }
+            None => {
+                text.push_str(&format!("{:>6}: DATA {}\n", addr, memory[addr]));
+                addr += 1;
+            }
+        }
+    }
+    text
+}
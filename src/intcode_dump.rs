@@ -0,0 +1,127 @@
+#![allow(dead_code)]
+
+use crate::intcode_machine::*;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+const HIGHLIGHT: &str = "\x1b[1;31m";
+const RESET: &str = "\x1b[0m";
+
+/// Row layout shared by every memory shown together, so their columns line up.
+#[derive(Debug, Clone, Copy)]
+pub struct Layout {
+    pub columns: usize,
+    pub width: usize,
+    pub addr_width: usize,
+    pub color: bool,
+}
+
+impl Layout {
+    pub fn new(memories: &[&[ValueType]], columns: usize, color: bool) -> Layout {
+        let width = memories
+            .iter()
+            .flat_map(|memory| memory.iter())
+            .map(|value| value.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let len = memories.iter().map(|memory| memory.len()).max().unwrap_or(0);
+        Layout {
+            columns: columns.max(1),
+            width,
+            addr_width: len.saturating_sub(1).to_string().len(),
+            color,
+        }
+    }
+
+    fn row(&self, memory: &[ValueType], start: usize, prefix: &str, changed: &dyn Fn(usize) -> bool) -> String {
+        let mut text = format!("{}{:>width$}:", prefix, start, width = self.addr_width);
+        for addr in start..start + self.columns {
+            let cell = match memory.get(addr) {
+                Some(value) => format!("{:>width$}", value, width = self.width),
+                None => format!("{:>width$}", ".", width = self.width),
+            };
+            match self.color && changed(addr) {
+                true => write!(text, " {}{}{}", HIGHLIGHT, cell, RESET).unwrap(),
+                false => write!(text, " {}", cell).unwrap(),
+            }
+        }
+        text
+    }
+
+    /// `^` under the changed cells of a row, for output without color.
+    fn markers(&self, start: usize, prefix: &str, changed: &dyn Fn(usize) -> bool) -> Option<String> {
+        if self.color || !(start..start + self.columns).any(changed) {
+            return None;
+        }
+        let mut text = " ".repeat(prefix.len() + self.addr_width + 1);
+        for addr in start..start + self.columns {
+            let mark = match changed(addr) {
+                true => "^",
+                false => " ",
+            };
+            write!(text, " {}", mark.repeat(self.width)).unwrap();
+        }
+        Some(String::from(text.trim_end()))
+    }
+}
+
+/// Memory in rows of `layout.columns` cells. `annotations` maps addresses, usually those of
+/// instructions, to text printed after the row they fall in. Runs of rows that are all zero
+/// and unannotated are shown as a single `*` line.
+pub fn dump(memory: &[ValueType], layout: &Layout, annotations: &BTreeMap<usize, String>) -> String {
+    let mut text = String::new();
+    let mut skipping = false;
+    for start in (0..memory.len()).step_by(layout.columns) {
+        let end = (start + layout.columns).min(memory.len());
+        let notes: Vec<String> = annotations
+            .range(start..end)
+            .map(|(addr, note)| format!("{}: {}", addr, note))
+            .collect();
+        let zero = memory[start..end].iter().all(|&value| value == 0);
+        if zero && notes.is_empty() && start > 0 && end < memory.len() {
+            if !skipping {
+                text.push_str("*\n");
+                skipping = true;
+            }
+            continue;
+        }
+        skipping = false;
+        text.push_str(&layout.row(memory, start, "", &|_| false));
+        if !notes.is_empty() {
+            write!(text, "    ; {}", notes.join("; ")).unwrap();
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// Cells that differ between two snapshots; cells past the end of the shorter one count as
+/// zero, like the machine's own memory.
+pub fn changed_cells(before: &[ValueType], after: &[ValueType]) -> Vec<usize> {
+    let len = before.len().max(after.len());
+    (0..len)
+        .filter(|&addr| before.get(addr).unwrap_or(&0) != after.get(addr).unwrap_or(&0))
+        .collect()
+}
+
+/// Rows with changes, each shown as a `-` line from `before` and a `+` line from `after`,
+/// with the changed cells highlighted or marked. Cells a snapshot does not have show as `.`.
+pub fn diff(before: &[ValueType], after: &[ValueType], layout: &Layout) -> String {
+    let changed = changed_cells(before, after);
+    let mut text = String::new();
+    writeln!(text, "{} cells changed", changed.len()).unwrap();
+    if after.len() != before.len() {
+        writeln!(text, "memory size {} -> {}", before.len(), after.len()).unwrap();
+    }
+    let is_changed = |addr: usize| changed.binary_search(&addr).is_ok();
+    let mut rows: Vec<usize> = changed.iter().map(|addr| addr / layout.columns * layout.columns).collect();
+    rows.dedup();
+    for start in rows {
+        writeln!(text, "{}", layout.row(before, start, "- ", &is_changed)).unwrap();
+        writeln!(text, "{}", layout.row(after, start, "+ ", &is_changed)).unwrap();
+        if let Some(markers) = layout.markers(start, "+ ", &is_changed) {
+            writeln!(text, "{}", markers).unwrap();
+        }
+    }
+    text
+}
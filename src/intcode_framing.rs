@@ -0,0 +1,100 @@
+#![allow(dead_code)]
+
+use crate::intcode_machine::{Machine, ValueType};
+use std::fmt;
+use std::marker::PhantomData;
+
+/// Schema of a fixed-width output record.
+pub trait Record: Sized {
+    const WIDTH: usize;
+
+    fn decode(values: &[ValueType]) -> Result<Self, FrameError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FrameError {
+    Truncated { values: Vec<ValueType>, width: usize },
+    Invalid { values: Vec<ValueType>, reason: String },
+}
+
+impl FrameError {
+    pub fn invalid(values: &[ValueType], reason: &str) -> FrameError {
+        FrameError::Invalid {
+            values: values.to_vec(),
+            reason: String::from(reason),
+        }
+    }
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FrameError::Truncated { values, width } => write!(
+                f,
+                "Output ended inside a frame: got {:?}, expected {} values",
+                values, width
+            ),
+            FrameError::Invalid { values, reason } => {
+                write!(f, "Invalid frame {:?}: {}", values, reason)
+            }
+        }
+    }
+}
+
+/// Groups machine output into records, keeping incomplete frames buffered until the
+/// rest of the values arrive, e.g. after the machine is resumed from `InputBlock`.
+pub struct Framed<R: Record> {
+    pending: Vec<ValueType>,
+    record: PhantomData<R>,
+}
+
+impl<R: Record> Framed<R> {
+    pub fn new() -> Framed<R> {
+        Framed {
+            pending: Vec::with_capacity(R::WIDTH),
+            record: PhantomData,
+        }
+    }
+
+    pub fn next_frame(&mut self, m: &mut Machine) -> Option<Result<R, FrameError>> {
+        while self.pending.len() < R::WIDTH {
+            if !m.has_output() {
+                return None;
+            }
+            self.pending.push(m.pop_output());
+        }
+        let result = R::decode(&self.pending);
+        self.pending.clear();
+        Some(result)
+    }
+
+    /// Decodes every complete frame currently available.
+    pub fn read(&mut self, m: &mut Machine) -> Vec<Result<R, FrameError>> {
+        let mut frames = vec![];
+        while let Some(frame) = self.next_frame(m) {
+            frames.push(frame);
+        }
+        frames
+    }
+
+    pub fn has_partial(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Call once the machine has halted: leftover values mean the last frame was cut short.
+    pub fn finish(self) -> Result<(), FrameError> {
+        match self.pending.is_empty() {
+            true => Ok(()),
+            false => Err(FrameError::Truncated {
+                values: self.pending,
+                width: R::WIDTH,
+            }),
+        }
+    }
+}
+
+impl<R: Record> Default for Framed<R> {
+    fn default() -> Framed<R> {
+        Framed::new()
+    }
+}
@@ -0,0 +1,303 @@
+#![allow(dead_code)]
+
+use crate::intcode_machine::*;
+use std::sync::{Arc, Mutex};
+
+/// Per-cell access counts. Executing an instruction counts for its operand cells as well
+/// as its opcode, so code shows up as solid runs; the final HALT counts too.
+#[derive(Debug, Clone, Default)]
+pub struct Heatmap {
+    pub reads: Vec<u64>,
+    pub writes: Vec<u64>,
+    pub executes: Vec<u64>,
+}
+
+fn bump(counts: &mut Vec<u64>, addr: usize) {
+    if counts.len() <= addr {
+        counts.resize(addr + 1, 0);
+    }
+    counts[addr] += 1;
+}
+
+fn get(counts: &[u64], addr: usize) -> u64 {
+    counts.get(addr).cloned().unwrap_or(0)
+}
+
+/// 0 for cells never touched, otherwise 64 to 255 on a log scale up to `max`, so rarely
+/// used cells remain visible next to hot loops.
+fn intensity(count: u64, max: u64) -> u8 {
+    match count {
+        0 => 0,
+        _ if max <= 1 => 255,
+        _ => 64 + (191.0 * (count as f64).ln() / (max as f64).ln()).round() as u8,
+    }
+}
+
+impl Heatmap {
+    pub fn len(&self) -> usize {
+        self.reads.len().max(self.writes.len()).max(self.executes.len())
+    }
+
+    /// Pixels in RGB order, one per cell in rows of `width`: red for reads, green for
+    /// writes, blue for execution. Cells from `len` up to the end of the last row are black.
+    pub fn pixels(&self, len: usize, width: usize) -> (usize, Vec<[u8; 3]>) {
+        let width = width.max(1);
+        let len = len.max(self.len());
+        let height = len.div_ceil(width);
+        let max = |counts: &[u64]| counts.iter().cloned().max().unwrap_or(0);
+        let maxima = [max(&self.reads), max(&self.writes), max(&self.executes)];
+        let pixels = (0..width * height)
+            .map(|addr| {
+                [
+                    intensity(get(&self.reads, addr), maxima[0]),
+                    intensity(get(&self.writes, addr), maxima[1]),
+                    intensity(get(&self.executes, addr), maxima[2]),
+                ]
+            })
+            .collect();
+        (height, pixels)
+    }
+}
+
+/// Observer feeding a shared heatmap; keep a clone of the handle to read it after the run.
+pub struct Recorder(pub Arc<Mutex<Heatmap>>);
+
+impl Observer for Recorder {
+    fn instruction(&mut self, executed: &Executed) {
+        let mut heatmap = self.0.lock().unwrap();
+        for cell in executed.addr..executed.addr + executed.words.len() {
+            bump(&mut heatmap.executes, cell);
+        }
+    }
+
+    fn read(&mut self, addr: usize, _value: &ValueType) {
+        bump(&mut self.0.lock().unwrap().reads, addr);
+    }
+
+    fn write(&mut self, addr: usize, _value: &ValueType) {
+        bump(&mut self.0.lock().unwrap().writes, addr);
+    }
+}
+
+/// Each pixel becomes a `scale` by `scale` square.
+fn scaled(width: usize, height: usize, pixels: &[[u8; 3]], scale: usize) -> Vec<Vec<u8>> {
+    let mut rows = Vec::with_capacity(height * scale);
+    for y in 0..height {
+        let row: Vec<u8> = pixels[y * width..(y + 1) * width]
+            .iter()
+            .flat_map(|pixel| std::iter::repeat_n(pixel, scale))
+            .flat_map(|pixel| pixel.iter().cloned())
+            .collect();
+        for _ in 0..scale {
+            rows.push(row.clone());
+        }
+    }
+    rows
+}
+
+pub fn to_ppm(width: usize, height: usize, pixels: &[[u8; 3]], scale: usize) -> Vec<u8> {
+    let mut out = format!("P6\n{} {}\n255\n", width * scale, height * scale).into_bytes();
+    for row in scaled(width, height, pixels, scale) {
+        out.extend(row);
+    }
+    out
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ 0xedb8_8320,
+                _ => crc >> 1,
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in bytes {
+        a = (a + u32::from(byte)) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+/// zlib stream of uncompressed deflate blocks; no compressor needed, and heatmaps are small.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(65535).peekable();
+    if blocks.peek().is_none() {
+        out.extend([1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none() as u8;
+        let len = block.len() as u16;
+        out.push(last);
+        out.extend(len.to_le_bytes());
+        out.extend((!len).to_le_bytes());
+        out.extend(block);
+    }
+    out.extend(adler32(data).to_be_bytes());
+    out
+}
+
+fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend((data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend(kind);
+    out.extend(data);
+    let crc = crc32(&out[start..]);
+    out.extend(crc.to_be_bytes());
+}
+
+pub fn to_png(width: usize, height: usize, pixels: &[[u8; 3]], scale: usize) -> Vec<u8> {
+    let mut out = vec![0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+    let mut header = Vec::with_capacity(13);
+    header.extend(((width * scale) as u32).to_be_bytes());
+    header.extend(((height * scale) as u32).to_be_bytes());
+    // 8 bits per channel, RGB, deflate, no filtering, not interlaced.
+    header.extend([8, 2, 0, 0, 0]);
+    chunk(&mut out, b"IHDR", &header);
+    let mut raw = vec![];
+    for row in scaled(width, height, pixels, scale) {
+        raw.push(0);
+        raw.extend(row);
+    }
+    chunk(&mut out, b"IDAT", &zlib_stored(&raw));
+    chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    /// Splits a PNG into its chunks, checking the signature and every CRC on the way.
+    fn chunks(png: &[u8]) -> Vec<(String, Vec<u8>)> {
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        let mut chunks = vec![];
+        let mut at = 8;
+        while at < png.len() {
+            let len = u32::from_be_bytes(png[at..at + 4].try_into().unwrap()) as usize;
+            let body = &png[at + 4..at + 8 + len];
+            let crc = u32::from_be_bytes(png[at + 8 + len..at + 12 + len].try_into().unwrap());
+            assert_eq!(crc32(body), crc);
+            chunks.push((String::from_utf8(body[..4].to_vec()).unwrap(), body[4..].to_vec()));
+            at += 12 + len;
+        }
+        assert_eq!(at, png.len());
+        chunks
+    }
+
+    /// Inflates a zlib stream of stored blocks, checking the header, block lengths and checksum.
+    fn inflate_stored(zlib: &[u8]) -> Vec<u8> {
+        assert_eq!((u16::from(zlib[0]) << 8 | u16::from(zlib[1])) % 31, 0);
+        let mut data = vec![];
+        let mut at = 2;
+        loop {
+            let header = zlib[at];
+            assert_eq!(header >> 1, 0, "stored block");
+            let len = u16::from_le_bytes([zlib[at + 1], zlib[at + 2]]);
+            let nlen = u16::from_le_bytes([zlib[at + 3], zlib[at + 4]]);
+            assert_eq!(nlen, !len);
+            data.extend(&zlib[at + 5..at + 5 + len as usize]);
+            at += 5 + len as usize;
+            if header & 1 == 1 {
+                break;
+            }
+        }
+        assert_eq!(&zlib[at..], &adler32(&data).to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn checksums_match_reference_values() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+        assert_eq!(adler32(b""), 1);
+    }
+
+    #[test]
+    fn png_has_valid_chunks_and_scaled_rows() {
+        let pixels = [[255, 0, 0], [0, 255, 0], [0, 0, 255], [1, 2, 3]];
+        let png = to_png(2, 2, &pixels, 2);
+        let chunks = chunks(&png);
+        let kinds: Vec<&str> = chunks.iter().map(|(kind, _)| kind.as_str()).collect();
+        assert_eq!(kinds, vec!["IHDR", "IDAT", "IEND"]);
+        assert_eq!(chunks[0].1, vec![0, 0, 0, 4, 0, 0, 0, 4, 8, 2, 0, 0, 0]);
+        let raw = inflate_stored(&chunks[1].1);
+        let top = [0, 255, 0, 0, 255, 0, 0, 0, 255, 0, 0, 255, 0];
+        let bottom = [0, 0, 0, 255, 0, 0, 255, 1, 2, 3, 1, 2, 3];
+        assert_eq!(raw, [top, top, bottom, bottom].concat());
+        assert!(chunks[2].1.is_empty());
+    }
+
+    #[test]
+    fn stored_blocks_split_at_65535_bytes() {
+        for &len in &[0usize, 1, 65535, 65536, 2 * 65535 + 7] {
+            let data: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            let zlib = zlib_stored(&data);
+            let blocks = len.max(1).div_ceil(65535);
+            assert_eq!(zlib.len(), 2 + 5 * blocks + len + 4, "{} bytes", len);
+            assert_eq!(inflate_stored(&zlib), data);
+        }
+    }
+
+    #[test]
+    fn large_png_spans_several_blocks() {
+        // 120 rows of 1 + 3 * 200 bytes: 72120 bytes of image data.
+        let pixels: Vec<[u8; 3]> = (0..200 * 120).map(|i| [(i % 256) as u8, (i / 256) as u8, 7]).collect();
+        let chunks = chunks(&to_png(200, 120, &pixels, 1));
+        assert_eq!(chunks[0].1[..8], [0, 0, 0, 200, 0, 0, 0, 120]);
+        let raw = inflate_stored(&chunks[1].1);
+        assert_eq!(raw.len(), 120 * 601);
+        assert_eq!(chunks[1].1[2], 0, "first block is not the last");
+        for (y, row) in raw.chunks(601).enumerate() {
+            assert_eq!(row[0], 0);
+            let expected: Vec<u8> = pixels[y * 200..(y + 1) * 200].iter().flatten().cloned().collect();
+            assert_eq!(&row[1..], &expected[..]);
+        }
+    }
+
+    #[test]
+    fn ppm_has_header_and_scaled_pixels() {
+        let ppm = to_ppm(2, 1, &[[1, 2, 3], [4, 5, 6]], 2);
+        let header = b"P6\n4 2\n255\n";
+        assert_eq!(&ppm[..header.len()], header);
+        let row = [1, 2, 3, 1, 2, 3, 4, 5, 6, 4, 5, 6];
+        assert_eq!(&ppm[header.len()..], &[row, row].concat()[..]);
+    }
+
+    #[test]
+    fn intensity_is_logarithmic_between_64_and_255() {
+        assert_eq!(intensity(0, 0), 0);
+        assert_eq!(intensity(0, 1000), 0);
+        assert_eq!(intensity(1, 1), 255);
+        assert_eq!(intensity(1, 1000), 64);
+        assert_eq!(intensity(1000, 1000), 255);
+        assert_eq!(intensity(100, 10000), 160);
+        let levels: Vec<u8> = (1..=1000).map(|count| intensity(count, 1000)).collect();
+        assert!(levels.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[test]
+    fn pixels_color_each_kind_of_access_and_pad_the_last_row() {
+        let heatmap = Heatmap {
+            reads: vec![1],
+            writes: vec![0, 2, 1],
+            executes: vec![0, 0, 0, 5],
+        };
+        let (height, pixels) = heatmap.pixels(5, 3);
+        assert_eq!(height, 2);
+        assert_eq!(
+            pixels,
+            vec![[255, 0, 0], [0, 255, 0], [0, 64, 0], [0, 0, 255], [0, 0, 0], [0, 0, 0]]
+        );
+    }
+}
@@ -0,0 +1,313 @@
+#![allow(dead_code)]
+
+use crate::intcode_machine::*;
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::os::unix::io::BorrowedFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+
+/// How values travel as text: one number per line (commas and spaces also separate values
+/// on input), or characters with values outside ASCII written as numbers on their own line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Numbers,
+    Ascii,
+}
+
+/// Where values come from or go to:
+///
+/// - `-`: stdin or stdout
+/// - `fd:N`: an inherited file descriptor, e.g. `3<file` in the shell
+/// - `unix:PATH`: connect to a Unix domain socket
+/// - `unix-listen:PATH`: create the socket and wait for one connection
+/// - anything else: a file or named pipe
+#[derive(Debug, Clone, PartialEq)]
+pub enum Endpoint {
+    Stdio,
+    Fd(i32),
+    Unix(String),
+    UnixListen(String),
+    Path(String),
+}
+
+impl Endpoint {
+    pub fn parse(spec: &str) -> Result<Endpoint, String> {
+        if spec == "-" {
+            return Ok(Endpoint::Stdio);
+        }
+        if let Some(fd) = spec.strip_prefix("fd:") {
+            return match fd.parse() {
+                Ok(n) if n >= 0 => Ok(Endpoint::Fd(n)),
+                Ok(_) => Err(format!("Bad file descriptor {:?}, expected a number from 0", fd)),
+                Err(e) => Err(format!("Bad file descriptor {:?}. Error = {:#}", fd, e)),
+            };
+        }
+        if let Some(path) = spec.strip_prefix("unix:") {
+            return Ok(Endpoint::Unix(String::from(path)));
+        }
+        if let Some(path) = spec.strip_prefix("unix-listen:") {
+            return Ok(Endpoint::UnixListen(String::from(path)));
+        }
+        Ok(Endpoint::Path(String::from(spec)))
+    }
+
+    fn is_socket(&self) -> bool {
+        matches!(self, Endpoint::Unix(_) | Endpoint::UnixListen(_))
+    }
+}
+
+fn connect(endpoint: &Endpoint) -> Result<UnixStream, String> {
+    match endpoint {
+        Endpoint::Unix(path) => UnixStream::connect(path)
+            .map_err(|e| format!("Failed to connect to {}. Error = {:#}", path, e)),
+        Endpoint::UnixListen(path) => {
+            let listener = UnixListener::bind(path)
+                .map_err(|e| format!("Failed to listen on {}. Error = {:#}", path, e))?;
+            let accepted = listener.accept();
+            let _ = std::fs::remove_file(path);
+            accepted
+                .map(|(stream, _)| stream)
+                .map_err(|e| format!("Failed to accept on {}. Error = {:#}", path, e))
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// A duplicate of an inherited descriptor, so dropping the endpoint leaves the original open
+/// for whoever else holds it, e.g. the same `fd:N` given for input and output.
+fn dup_fd(fd: i32) -> Result<File, String> {
+    // Negative descriptors are rejected by `Endpoint::parse`; an invalid one fails to dup.
+    let borrowed = unsafe { BorrowedFd::borrow_raw(fd) };
+    borrowed
+        .try_clone_to_owned()
+        .map(File::from)
+        .map_err(|e| format!("Failed to use file descriptor {}. Error = {:#}", fd, e))
+}
+
+fn open_read(endpoint: &Endpoint) -> Result<Box<dyn Read + Send>, String> {
+    Ok(match endpoint {
+        Endpoint::Stdio => Box::new(std::io::stdin()),
+        Endpoint::Fd(fd) => Box::new(dup_fd(*fd)?),
+        Endpoint::Path(path) => Box::new(
+            File::open(path).map_err(|e| format!("Failed to open {}. Error = {:#}", path, e))?,
+        ),
+        socket => Box::new(connect(socket)?),
+    })
+}
+
+fn open_write(endpoint: &Endpoint) -> Result<Box<dyn Write + Send>, String> {
+    Ok(match endpoint {
+        Endpoint::Stdio => Box::new(std::io::stdout()),
+        Endpoint::Fd(fd) => Box::new(dup_fd(*fd)?),
+        Endpoint::Path(path) => Box::new(
+            OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(path)
+                .map_err(|e| format!("Failed to open {}. Error = {:#}", path, e))?,
+        ),
+        socket => Box::new(connect(socket)?),
+    })
+}
+
+/// Values separated by commas or whitespace.
+pub fn parse_values(text: &str) -> Result<Vec<ValueType>, String> {
+    text.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|field| !field.is_empty())
+        .map(|field| {
+            field
+                .parse()
+                .map_err(|e| format!("Bad input value {:?}. Error = {:#}", field, e))
+        })
+        .collect()
+}
+
+/// Opening a named pipe blocks until the other side opens it too, so endpoints given by
+/// address are opened on first use. That lets a ring of programs connected through pipes,
+/// like day7's feedback loop, start in any order.
+pub struct Input {
+    endpoint: Option<Endpoint>,
+    reader: Option<BufReader<Box<dyn Read + Send>>>,
+    encoding: Encoding,
+    pending: VecDeque<ValueType>,
+    prompt: bool,
+}
+
+impl Input {
+    pub fn new(reader: Box<dyn Read + Send>, encoding: Encoding) -> Input {
+        Input {
+            endpoint: None,
+            reader: Some(BufReader::new(reader)),
+            encoding,
+            pending: VecDeque::new(),
+            prompt: false,
+        }
+    }
+
+    pub fn open(endpoint: &Endpoint, encoding: Encoding) -> Input {
+        Input {
+            endpoint: Some(endpoint.clone()),
+            reader: None,
+            encoding,
+            pending: VecDeque::new(),
+            prompt: false,
+        }
+    }
+
+    /// Asks on stderr for each line from stdin; a line that does not parse is asked again.
+    pub fn prompt(encoding: Encoding) -> Input {
+        Input {
+            prompt: true,
+            ..Input::new(Box::new(std::io::stdin()), encoding)
+        }
+    }
+
+    /// No values beyond those queued.
+    pub fn empty(encoding: Encoding) -> Input {
+        Input {
+            endpoint: None,
+            reader: None,
+            encoding,
+            pending: VecDeque::new(),
+            prompt: false,
+        }
+    }
+
+    /// Values given before the run, read ahead of the stream.
+    pub fn queue(&mut self, values: impl IntoIterator<Item = ValueType>) {
+        self.pending.extend(values);
+    }
+
+    /// Next value, reading another line when needed; None at end of stream.
+    pub fn next_value(&mut self) -> Result<Option<ValueType>, String> {
+        while self.pending.is_empty() {
+            if self.reader.is_none() {
+                let endpoint = match &self.endpoint {
+                    Some(endpoint) => endpoint,
+                    None => return Ok(None),
+                };
+                self.reader = Some(BufReader::new(open_read(endpoint)?));
+            }
+            if self.prompt {
+                eprint!("input> ");
+            }
+            let mut line = String::new();
+            // A socket peer that hangs up without shutting down resets the connection.
+            match self.reader.as_mut().unwrap().read_line(&mut line) {
+                Ok(0) => return Ok(None),
+                Ok(_) => (),
+                Err(e) if e.kind() == ErrorKind::ConnectionReset => return Ok(None),
+                Err(e) => return Err(format!("Failed to read input. Error = {:#}", e)),
+            }
+            match self.encoding {
+                Encoding::Ascii => self.pending.extend(line.bytes().map(ValueType::from)),
+                Encoding::Numbers => match parse_values(&line) {
+                    Ok(values) => self.pending.extend(values),
+                    Err(e) if self.prompt => eprintln!("{}", e),
+                    Err(e) => return Err(e),
+                },
+            }
+        }
+        Ok(self.pending.pop_front())
+    }
+}
+
+/// Output side of an endpoint, opened on the first value like `Input`.
+pub struct Output {
+    endpoint: Option<Endpoint>,
+    writer: Option<Box<dyn Write + Send>>,
+    encoding: Encoding,
+}
+
+impl Output {
+    pub fn new(writer: Box<dyn Write + Send>, encoding: Encoding) -> Output {
+        Output {
+            endpoint: None,
+            writer: Some(writer),
+            encoding,
+        }
+    }
+
+    pub fn open(endpoint: &Endpoint, encoding: Encoding) -> Output {
+        Output {
+            endpoint: Some(endpoint.clone()),
+            writer: None,
+            encoding,
+        }
+    }
+
+    /// Writes one value. Lines are flushed as they end, so a program reading the other end
+    /// of a pipe sees each value as soon as it is produced.
+    pub fn write_value(&mut self, value: ValueType) -> Result<(), String> {
+        if self.writer.is_none() {
+            self.writer = Some(open_write(self.endpoint.as_ref().unwrap())?);
+        }
+        let writer = self.writer.as_mut().unwrap();
+        let result = match self.encoding {
+            Encoding::Ascii if (0..128).contains(&value) => {
+                writer.write_all(&[value as u8]).and_then(|_| match value {
+                    10 => writer.flush(),
+                    _ => Ok(()),
+                })
+            }
+            _ => writeln!(writer, "{}", value).and_then(|_| writer.flush()),
+        };
+        result.map_err(|e| format!("Failed to write output. Error = {:#}", e))
+    }
+
+    pub fn flush(&mut self) -> Result<(), String> {
+        match &mut self.writer {
+            Some(writer) => writer
+                .flush()
+                .map_err(|e| format!("Failed to write output. Error = {:#}", e)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Opens both directions; the same socket given for both is connected only once.
+pub fn open_pair(
+    input: &Endpoint,
+    output: &Endpoint,
+    encoding: Encoding,
+) -> Result<(Input, Output), String> {
+    if input == output && input.is_socket() {
+        let stream = connect(input)?;
+        let reader = stream
+            .try_clone()
+            .map_err(|e| format!("Failed to share the socket. Error = {:#}", e))?;
+        return Ok((Input::new(Box::new(reader), encoding), Output::new(Box::new(stream), encoding)));
+    }
+    Ok((Input::open(input, encoding), Output::open(output, encoding)))
+}
+
+/// Runs until the machine halts or stops for another reason than input, feeding it from
+/// `input` and passing every output on. Output is flushed before waiting for input, so a
+/// prompt is seen before it is answered. Returns `InputBlock` once the input stream ends.
+pub fn run_connected<W: Word>(
+    machine: &mut Machine<W>,
+    input: &mut Input,
+    output: &mut Output,
+) -> Result<State, String> {
+    loop {
+        let state = step(machine);
+        for value in machine.drain_output() {
+            output.write_value(value.to_i64())?;
+        }
+        match state {
+            State::Running => (),
+            State::InputBlock => match output.flush().and_then(|_| input.next_value())? {
+                Some(value) => machine.push_input(W::from_i64(value)),
+                None => break,
+            },
+            state => {
+                output.flush()?;
+                return Ok(state);
+            }
+        }
+    }
+    output.flush()?;
+    Ok(State::InputBlock)
+}
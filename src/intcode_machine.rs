@@ -1,34 +1,209 @@
 #![allow(dead_code)]
 
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex};
 
 pub type ValueType = i64;
 
-const TENS: [ValueType; 3] = [100, 1000, 10000];
+pub const TENS: [ValueType; 3] = [100, 1000, 10000];
 
-const ADD: ValueType = 1;
-const MULTIPLY: ValueType = 2;
-const INPUT: ValueType = 3;
-const OUTPUT: ValueType = 4;
-const JMP_IF_NON_ZERO: ValueType = 5;
-const JMP_IF_ZERO: ValueType = 6;
-const CMP_LT: ValueType = 7;
-const CMP_EQ: ValueType = 8;
-const MOVE_RBASE: ValueType = 9;
-const HALT: ValueType = 99;
+pub const ADD: ValueType = 1;
+pub const MULTIPLY: ValueType = 2;
+pub const INPUT: ValueType = 3;
+pub const OUTPUT: ValueType = 4;
+pub const JMP_IF_NON_ZERO: ValueType = 5;
+pub const JMP_IF_ZERO: ValueType = 6;
+pub const CMP_LT: ValueType = 7;
+pub const CMP_EQ: ValueType = 8;
+pub const MOVE_RBASE: ValueType = 9;
+pub const HALT: ValueType = 99;
 
-const MODE_POSITION: ValueType = 0;
-const MODE_IMMEDIATE: ValueType = 1;
-const MODE_RELATIVE: ValueType = 2;
+pub const MODE_POSITION: ValueType = 0;
+pub const MODE_IMMEDIATE: ValueType = 1;
+pub const MODE_RELATIVE: ValueType = 2;
+
+const PAGE_SIZE: usize = 256;
+
+/// Integer type a machine computes with. Fixed-width words wrap on overflow.
+pub trait Word:
+    Clone + PartialEq + PartialOrd + fmt::Debug + fmt::Display + Send + Sync + 'static
+{
+    fn from_i64(value: i64) -> Self;
+    fn to_i64(&self) -> i64;
+    fn add_word(&self, other: &Self) -> Self;
+    fn mul_word(&self, other: &Self) -> Self;
+    fn parse_word(text: &str) -> Result<Self, String>;
+
+    fn is_zero(&self) -> bool {
+        *self == Self::from_i64(0)
+    }
+}
+
+macro_rules! impl_word {
+    ($($t:ty),*) => {
+        $(
+            impl Word for $t {
+                fn from_i64(value: i64) -> $t {
+                    value as $t
+                }
+
+                fn to_i64(&self) -> i64 {
+                    *self as i64
+                }
+
+                fn add_word(&self, other: &$t) -> $t {
+                    self.wrapping_add(*other)
+                }
+
+                fn mul_word(&self, other: &$t) -> $t {
+                    self.wrapping_mul(*other)
+                }
+
+                fn parse_word(text: &str) -> Result<$t, String> {
+                    text.parse().map_err(|e| format!("Failed to parse {}. Error = {:#}", text, e))
+                }
+            }
+        )*
+    };
+}
+
+impl_word!(i32, i64, i128);
+
+/// Program image shared between machines, plus the pages this machine has written to.
+#[derive(Debug, Clone)]
+struct Memory<W> {
+    image: Arc<Vec<W>>,
+    pages: BTreeMap<usize, Box<[W]>>,
+    len: usize,
+}
+
+impl<W: Word> Memory<W> {
+    fn new(image: Arc<Vec<W>>) -> Memory<W> {
+        Memory {
+            len: image.len(),
+            image,
+            pages: BTreeMap::new(),
+        }
+    }
+
+    fn get(&self, addr: usize) -> W {
+        match self.pages.get(&(addr / PAGE_SIZE)) {
+            Some(page) => page[addr % PAGE_SIZE].clone(),
+            None => self.image.get(addr).cloned().unwrap_or_else(|| W::from_i64(0)),
+        }
+    }
+
+    fn set(&mut self, addr: usize, value: W) {
+        let page_id = addr / PAGE_SIZE;
+        let image = &self.image;
+        let page = self.pages.entry(page_id).or_insert_with(|| {
+            let start = page_id * PAGE_SIZE;
+            (start..start + PAGE_SIZE)
+                .map(|a| image.get(a).cloned().unwrap_or_else(|| W::from_i64(0)))
+                .collect()
+        });
+        page[addr % PAGE_SIZE] = value;
+        self.len = self.len.max(addr + 1);
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn grow(&mut self, len: usize) {
+        self.len = self.len.max(len);
+    }
+
+    fn reset(&mut self) {
+        self.pages.clear();
+        self.len = self.image.len();
+    }
+
+    fn snapshot(&self) -> Vec<W> {
+        (0..self.len).map(|addr| self.get(addr)).collect()
+    }
+}
 
 #[derive(Debug)]
-pub struct Machine {
-    memory: Vec<ValueType>,
+pub struct Machine<W = ValueType> {
+    memory: Memory<W>,
     cursor: usize,
-    relative_base: ValueType,
-    in_queue: VecDeque<ValueType>,
-    out_queue: VecDeque<ValueType>,
+    relative_base: W,
+    in_queue: VecDeque<W>,
+    out_queue: VecDeque<W>,
     debug_mode: bool,
+    steps: u64,
+    last_write: Option<(usize, W)>,
+    limits: Limits,
+    labels: Option<Arc<Labels>>,
+    level: FeatureLevel,
+    observers: Observers<W>,
+    /// Parameters of the running instruction, collected only while observers are attached.
+    operands: Vec<Operand<W>>,
+}
+
+/// A parameter as the instruction resolved it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Operand<W = ValueType> {
+    pub mode: ValueType,
+    /// Cell the value was loaded from or written to; None in immediate mode.
+    pub addr: Option<usize>,
+    /// Value the instruction used, or for its output parameter the value it wrote.
+    pub value: W,
+}
+
+/// An instruction that has just run, with the state it ran in.
+#[derive(Debug)]
+pub struct Executed<'a, W = ValueType> {
+    /// Step number of the instruction, counting from 0.
+    pub step: u64,
+    pub addr: usize,
+    pub opcode: ValueType,
+    /// Opcode word and parameters as they were before the instruction ran, which matters
+    /// when it overwrites itself.
+    pub words: &'a [W],
+    /// Parameters in order, resolved against the memory and relative base of that moment.
+    pub operands: &'a [Operand<W>],
+    /// Relative base the parameters were resolved with.
+    pub relative_base: &'a W,
+    /// Where execution continues; `addr` again after a halt.
+    pub next: usize,
+}
+
+/// Callbacks from the interpreter loop. Every method defaults to doing nothing, so an
+/// observer implements only the events it needs. Reads are the operand loads of position
+/// and relative parameters; writes include inputs stored to memory. The reads, writes, input
+/// and outputs of an instruction are reported before the instruction itself.
+pub trait Observer<W = ValueType> {
+    /// After an instruction has run, including the HALT that stops the machine.
+    fn instruction(&mut self, _executed: &Executed<W>) {}
+    fn read(&mut self, _addr: usize, _value: &W) {}
+    fn write(&mut self, _addr: usize, _value: &W) {}
+    fn input(&mut self, _value: &W) {}
+    fn output(&mut self, _value: &W) {}
+    fn relative_base(&mut self, _old: &W, _new: &W) {}
+    fn halt(&mut self, _addr: usize, _steps: u64) {}
+    /// Memory set from outside through `memset`, between instructions.
+    fn patch(&mut self, _addr: usize, _value: &W) {}
+}
+
+struct Observers<W>(Vec<Box<dyn Observer<W> + Send>>);
+
+impl<W> Observers<W> {
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn notify<F: FnMut(&mut Box<dyn Observer<W> + Send>)>(&mut self, event: F) {
+        self.0.iter_mut().for_each(event);
+    }
+}
+
+impl<W> fmt::Debug for Observers<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} observers", self.0.len())
+    }
 }
 
 #[derive(Debug)]
@@ -37,83 +212,765 @@ pub enum State {
     Halted,
     Running,
     InputBlock,
+    OutputReady,
+    AddressReached,
+    StepsDone,
+    StepLimit,
+    MemoryLimit,
+}
+
+/// Execution count per instruction address and, for conditional jumps, how often
+/// the jump was taken and not taken.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Coverage {
+    pub executed: BTreeMap<usize, u64>,
+    pub branches: BTreeMap<usize, (u64, u64)>,
+}
+
+impl Coverage {
+    /// Counts what the machine executes from now on. Keep the returned handle to read the
+    /// coverage after the run.
+    pub fn attach<W: Word>(machine: &mut Machine<W>) -> Arc<Mutex<Coverage>> {
+        let coverage = Arc::new(Mutex::new(Coverage::default()));
+        machine.add_observer(Box::new(coverage.clone()));
+        coverage
+    }
+
+    fn observe<W: Word>(&mut self, executed: &Executed<W>) {
+        *self.executed.entry(executed.addr).or_insert(0) += 1;
+        let taken = match executed.opcode {
+            JMP_IF_NON_ZERO => !executed.operands[0].value.is_zero(),
+            JMP_IF_ZERO => executed.operands[0].value.is_zero(),
+            _ => return,
+        };
+        let entry = self.branches.entry(executed.addr).or_insert((0, 0));
+        match taken {
+            true => entry.0 += 1,
+            false => entry.1 += 1,
+        }
+    }
+
+    pub fn merge(&mut self, other: &Coverage) {
+        for (&addr, &count) in &other.executed {
+            *self.executed.entry(addr).or_insert(0) += count;
+        }
+        for (&addr, &(taken, not_taken)) in &other.branches {
+            let entry = self.branches.entry(addr).or_insert((0, 0));
+            entry.0 += taken;
+            entry.1 += not_taken;
+        }
+    }
+}
+
+impl<W: Word> Observer<W> for Arc<Mutex<Coverage>> {
+    fn instruction(&mut self, executed: &Executed<W>) {
+        self.lock().unwrap().observe(executed);
+    }
+}
+
+/// An active call on the shadow stack. `relative_base` follows the callee's ARB adjustments,
+/// so once its prologue has run it points at the callee's frame; `caller_base` is the relative
+/// base the caller had when it made the call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub function: usize,
+    pub call_site: usize,
+    pub return_addr: usize,
+    pub relative_base: i64,
+    pub caller_base: i64,
 }
 
-impl Machine {
-    pub fn new(init_mem: &Vec<ValueType>) -> Machine {
+/// Shadow call stack, rebuilt from the convention compiled programs follow: the caller stores
+/// the address after its jump (usually into `[rb+k]`) and jumps; the callee moves the relative
+/// base for its frame and later jumps back through the stored address.
+#[derive(Debug, Clone)]
+pub struct CallStack {
+    pub frames: Vec<Frame>,
+    /// Every stack seen so far as a trie, node 0 being the empty stack. Steps are counted per
+    /// node and only turned into stacks of entry addresses when reported.
+    nodes: Vec<StackNode>,
+    children: HashMap<(usize, usize), usize>,
+    /// Node of each active frame, innermost last.
+    path: Vec<usize>,
+    stored: Option<i64>,
+}
+
+#[derive(Debug, Clone)]
+struct StackNode {
+    parent: usize,
+    function: usize,
+    steps: u64,
+}
+
+impl Default for CallStack {
+    fn default() -> CallStack {
+        CallStack {
+            frames: vec![],
+            nodes: vec![StackNode {
+                parent: 0,
+                function: 0,
+                steps: 0,
+            }],
+            children: HashMap::new(),
+            path: vec![],
+            stored: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transfer {
+    Call,
+    Return,
+}
+
+/// Names for code addresses, usually loaded from a symbol file.
+pub type Labels = BTreeMap<usize, String>;
+
+pub fn function_name(labels: Option<&Labels>, entry: usize) -> String {
+    match (labels.and_then(|labels| labels.get(&entry)), entry) {
+        (Some(label), _) => label.clone(),
+        (None, 0) => String::from("main"),
+        (None, _) => format!("fn_{}", entry),
+    }
+}
+
+/// `label+offset` from the nearest label at or before `addr`, or just the address.
+pub fn describe_addr(labels: Option<&Labels>, addr: usize) -> String {
+    match labels.and_then(|labels| labels.range(..=addr).next_back()) {
+        Some((&start, label)) if start == addr => label.clone(),
+        Some((&start, label)) => format!("{}+{}", label, addr - start),
+        None => addr.to_string(),
+    }
+}
+
+impl CallStack {
+    /// Follows calls and returns from now on. With `verbose`, each one is printed to stderr
+    /// with the resulting backtrace, using the labels the machine has at this point.
+    pub fn attach<W: Word>(machine: &mut Machine<W>, verbose: bool) -> Arc<Mutex<CallStack>> {
+        let calls = Arc::new(Mutex::new(CallStack::default()));
+        machine.add_observer(Box::new(CallTracker {
+            calls: calls.clone(),
+            labels: machine.labels.clone(),
+            verbose,
+            written: None,
+        }));
+        calls
+    }
+
+    fn node(&self) -> usize {
+        self.path.last().cloned().unwrap_or(0)
+    }
+
+    fn sample(&mut self) {
+        let node = self.node();
+        self.nodes[node].steps += 1;
+    }
+
+    fn push(&mut self, frame: Frame) {
+        let parent = self.node();
+        let nodes = &mut self.nodes;
+        let node = *self.children.entry((parent, frame.function)).or_insert_with(|| {
+            nodes.push(StackNode {
+                parent,
+                function: frame.function,
+                steps: 0,
+            });
+            nodes.len() - 1
+        });
+        self.path.push(node);
+        self.frames.push(frame);
+    }
+
+    /// Steps executed per stack, keyed by the entry addresses of the active functions.
+    pub fn samples(&self) -> HashMap<Vec<usize>, u64> {
+        let mut samples = HashMap::new();
+        for (id, node) in self.nodes.iter().enumerate().filter(|(_, node)| node.steps > 0) {
+            let mut stack = vec![];
+            let mut at = id;
+            while at != 0 {
+                stack.push(self.nodes[at].function);
+                at = self.nodes[at].parent;
+            }
+            stack.reverse();
+            samples.insert(stack, node.steps);
+        }
+        samples
+    }
+
+    /// Called after every executed instruction with the address it ran at, its opcode, the
+    /// next cursor and the value it wrote, if any.
+    fn observe(
+        &mut self,
+        at: usize,
+        opcode: ValueType,
+        next: usize,
+        written: Option<i64>,
+        relative_base: i64,
+    ) -> Option<Transfer> {
+        self.sample();
+        let stored = std::mem::replace(&mut self.stored, written);
+        let jumped = (opcode == JMP_IF_NON_ZERO || opcode == JMP_IF_ZERO) && next != at + 3;
+        if opcode == MOVE_RBASE {
+            if let Some(frame) = self.frames.last_mut() {
+                frame.relative_base = relative_base;
+            }
+        }
+        if !jumped {
+            return None;
+        }
+        // Returning past several frames at once happens with tail calls and early exits.
+        if let Some(depth) = self.frames.iter().rposition(|f| f.return_addr == next) {
+            self.frames.truncate(depth);
+            self.path.truncate(depth);
+            return Some(Transfer::Return);
+        }
+        if stored == Some(at as i64 + 3) {
+            self.push(Frame {
+                function: next,
+                call_site: at,
+                return_addr: at + 3,
+                relative_base,
+                caller_base: relative_base,
+            });
+            return Some(Transfer::Call);
+        }
+        None
+    }
+
+    /// Innermost frame first, like a debugger's `bt`.
+    pub fn backtrace(&self, cursor: usize, labels: Option<&Labels>) -> Vec<String> {
+        let mut lines = vec![];
+        let mut at = cursor;
+        for frame in self.frames.iter().rev() {
+            lines.push(format!(
+                "{} at {} (rb {})",
+                function_name(labels, frame.function),
+                describe_addr(labels, at),
+                frame.relative_base
+            ));
+            at = frame.call_site;
+        }
+        lines.push(format!("{} at {}", function_name(labels, 0), describe_addr(labels, at)));
+        lines
+    }
+
+    /// One `main;fn_a;fn_b <steps>` line per stack, the input format of flamegraph tools.
+    pub fn folded(&self, labels: Option<&Labels>) -> String {
+        let mut lines: Vec<String> = self
+            .samples()
+            .iter()
+            .map(|(stack, count)| {
+                let names: Vec<String> = std::iter::once(0)
+                    .chain(stack.iter().cloned())
+                    .map(|entry| function_name(labels, entry))
+                    .collect();
+                format!("{} {}\n", names.join(";"), count)
+            })
+            .collect();
+        lines.sort();
+        lines.concat()
+    }
+}
+
+struct CallTracker {
+    calls: Arc<Mutex<CallStack>>,
+    labels: Option<Arc<Labels>>,
+    verbose: bool,
+    written: Option<i64>,
+}
+
+impl<W: Word> Observer<W> for CallTracker {
+    fn write(&mut self, _addr: usize, value: &W) {
+        self.written = Some(value.to_i64());
+    }
+
+    fn instruction(&mut self, executed: &Executed<W>) {
+        let written = self.written.take();
+        if executed.opcode == HALT {
+            return;
+        }
+        let relative_base = match executed.opcode {
+            MOVE_RBASE => executed.relative_base.add_word(&executed.operands[0].value),
+            _ => executed.relative_base.clone(),
+        };
+        let mut calls = self.calls.lock().unwrap();
+        let transfer = calls.observe(executed.addr, executed.opcode, executed.next, written, relative_base.to_i64());
+        if let (true, Some(transfer)) = (self.verbose, transfer) {
+            let backtrace = calls.backtrace(executed.next, self.labels.as_deref()).join(" <- ");
+            eprintln!("DEBUG {:?} : {}", transfer, backtrace);
+        }
+    }
+}
+
+/// Input indices each value derives from. Data flows through ADD/MUL/LT/EQ, through the
+/// address of any operand (self-modified code, tainted relative base), and through control:
+/// once a jump's condition or target is tainted, everything computed afterwards carries
+/// that taint too, so the sets are supersets of the true dependencies.
+///
+/// Control taint is deliberately never cleared, not even where the branches meet again:
+/// finding that point needs the program's control flow graph, and with computed jumps and
+/// self-modifying code the machine cannot know it while running.
+#[derive(Debug, Clone, Default)]
+pub struct Taint {
+    pub cells: HashMap<usize, BTreeSet<usize>>,
+    pub relative_base: BTreeSet<usize>,
+    pub control: BTreeSet<usize>,
+    /// Values read by opcode 3, indexed by input number.
+    pub inputs: Vec<i64>,
+    /// Dependencies of each output, in output order.
+    pub outputs: Vec<(i64, BTreeSet<usize>)>,
+}
+
+impl Taint {
+    /// Tracks taint from the machine's next input on. Keep the returned handle to read the
+    /// dependencies after the run.
+    pub fn attach<W: Word>(machine: &mut Machine<W>) -> Arc<Mutex<Taint>> {
+        let taint = Arc::new(Mutex::new(Taint::default()));
+        machine.add_observer(Box::new(taint.clone()));
+        taint
+    }
+
+    /// Taint of operand `index` of an executed instruction, and of its address.
+    fn operand<W: Word>(&self, executed: &Executed<W>, index: usize) -> (BTreeSet<usize>, BTreeSet<usize>) {
+        let operand = &executed.operands[index];
+        let mut address = self.cell(executed.addr + index + 1);
+        let target = match operand.addr {
+            None => return (address, BTreeSet::new()),
+            Some(target) => target,
+        };
+        if operand.mode == MODE_RELATIVE {
+            address.extend(self.relative_base.iter().cloned());
+        }
+        (self.cell(target), address)
+    }
+
+    fn observe<W: Word>(&mut self, executed: &Executed<W>) {
+        let opcode = executed.opcode;
+        let reads = match opcode {
+            ADD | MULTIPLY | CMP_LT | CMP_EQ | JMP_IF_NON_ZERO | JMP_IF_ZERO => 2,
+            OUTPUT | MOVE_RBASE => 1,
+            _ => 0,
+        };
+        let mut all: BTreeSet<usize> = BTreeSet::new();
+        for index in 0..reads {
+            let (value, address) = self.operand(executed, index);
+            all.extend(value);
+            all.extend(address);
+        }
+        let written = match opcode {
+            ADD | MULTIPLY | CMP_LT | CMP_EQ => Some(2),
+            INPUT => Some(0),
+            _ => None,
+        };
+        let written = written.map(|index| {
+            all.extend(self.operand(executed, index).1);
+            let operand = &executed.operands[index];
+            (operand.addr.unwrap(), operand.value.to_i64())
+        });
+        match (opcode, written) {
+            (INPUT, Some((addr, value))) => {
+                let mut tags = all;
+                tags.insert(self.inputs.len());
+                self.inputs.push(value);
+                self.set_cell(addr, tags);
+            }
+            (_, Some((addr, _))) => self.set_cell(addr, all),
+            (OUTPUT, _) => {
+                let mut tags = all;
+                tags.extend(self.control.iter().cloned());
+                self.outputs.push((executed.operands[0].value.to_i64(), tags));
+            }
+            (JMP_IF_NON_ZERO, _) | (JMP_IF_ZERO, _) => self.control.extend(all),
+            (MOVE_RBASE, _) => {
+                let control = self.control.clone();
+                self.relative_base.extend(all.into_iter().chain(control));
+            }
+            _ => (),
+        }
+    }
+
+    fn cell(&self, addr: usize) -> BTreeSet<usize> {
+        self.cells.get(&addr).cloned().unwrap_or_default()
+    }
+
+    fn set_cell(&mut self, addr: usize, mut tags: BTreeSet<usize>) {
+        tags.extend(self.control.iter().cloned());
+        match tags.is_empty() {
+            true => self.cells.remove(&addr),
+            false => self.cells.insert(addr, tags),
+        };
+    }
+}
+
+impl<W: Word> Observer<W> for Arc<Mutex<Taint>> {
+    fn instruction(&mut self, executed: &Executed<W>) {
+        self.lock().unwrap().observe(executed);
+    }
+}
+
+/// Instruction set as the puzzles introduce it: day2 has ADD, MUL and HALT with position
+/// operands, day5 adds I/O, immediate mode, jumps and comparisons, day9 relative mode and ARB.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FeatureLevel {
+    Day2,
+    Day5,
+    #[default]
+    Day9,
+}
+
+impl FeatureLevel {
+    pub fn parse(text: &str) -> Result<FeatureLevel, String> {
+        match text.to_ascii_lowercase().as_str() {
+            "day2" | "2" => Ok(FeatureLevel::Day2),
+            "day5" | "5" => Ok(FeatureLevel::Day5),
+            "day9" | "9" => Ok(FeatureLevel::Day9),
+            _ => Err(format!("Unknown feature level {:?}, expected day2, day5 or day9", text)),
+        }
+    }
+
+    /// First level with this opcode, if any has it.
+    pub fn of_opcode(opcode: ValueType) -> Option<FeatureLevel> {
+        match opcode {
+            ADD | MULTIPLY | HALT => Some(FeatureLevel::Day2),
+            INPUT | OUTPUT | JMP_IF_NON_ZERO | JMP_IF_ZERO | CMP_LT | CMP_EQ => Some(FeatureLevel::Day5),
+            MOVE_RBASE => Some(FeatureLevel::Day9),
+            _ => None,
+        }
+    }
+
+    /// First level with this parameter mode, if any has it.
+    pub fn of_mode(mode: ValueType) -> Option<FeatureLevel> {
+        match mode {
+            MODE_POSITION => Some(FeatureLevel::Day2),
+            MODE_IMMEDIATE => Some(FeatureLevel::Day5),
+            MODE_RELATIVE => Some(FeatureLevel::Day9),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for FeatureLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FeatureLevel::Day2 => write!(f, "day2"),
+            FeatureLevel::Day5 => write!(f, "day5"),
+            FeatureLevel::Day9 => write!(f, "day9"),
+        }
+    }
+}
+
+/// Resource caps; a machine that hits one stops with `StepLimit` or `MemoryLimit`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Limits {
+    pub max_steps: Option<u64>,
+    pub max_memory: Option<usize>,
+}
+
+/// Interaction with the outside world, stamped with the number of steps executed before it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IoEvent<W = ValueType> {
+    Input(u64, W),
+    Output(u64, W),
+    Patch(u64, usize, W),
+}
+
+impl<W: Word> Machine<W> {
+    pub fn new(init_mem: &Vec<W>) -> Machine<W> {
+        Machine::from_image(Arc::new(init_mem.clone()))
+    }
+
+    /// Machine over a program image shared with other machines; nothing is copied until written.
+    pub fn from_image(image: Arc<Vec<W>>) -> Machine<W> {
         Machine {
-            memory: init_mem.clone(),
+            memory: Memory::new(image),
             cursor: 0,
-            relative_base: 0,
+            relative_base: W::from_i64(0),
             in_queue: VecDeque::new(),
             out_queue: VecDeque::new(),
             debug_mode: false,
+            steps: 0,
+            last_write: None,
+            limits: Limits::default(),
+            labels: None,
+            level: FeatureLevel::default(),
+            observers: Observers(Vec::new()),
+            operands: Vec::new(),
+        }
+    }
+
+    /// Rebuilds a machine from a state captured elsewhere, e.g. by transpiled code
+    /// that falls back to the interpreter.
+    pub fn resume(
+        memory: Vec<W>,
+        cursor: usize,
+        relative_base: W,
+        in_queue: VecDeque<W>,
+        out_queue: VecDeque<W>,
+    ) -> Machine<W> {
+        Machine {
+            cursor,
+            relative_base,
+            in_queue,
+            out_queue,
+            ..Machine::from_image(Arc::new(memory))
         }
     }
 
+    pub fn push_input(&mut self, value: W) {
+        self.in_queue.push_back(value);
+    }
+
     pub fn has_output(&self) -> bool {
         !self.out_queue.is_empty()
     }
 
-    pub fn pop_output(&mut self) -> ValueType {
+    pub fn pop_output(&mut self) -> W {
         self.out_queue.pop_front().unwrap()
     }
 
-    pub fn memset(&mut self, addr: usize, value: ValueType) {
-        self.memory[addr] = value;
+    pub fn peek_output(&self) -> Option<W> {
+        self.out_queue.front().cloned()
     }
 
-    fn param_val(&mut self, index: usize) -> ValueType {
-        let immediate_val = self.memory[self.cursor + index + 1];
+    pub fn outputs(&self) -> impl Iterator<Item = &W> {
+        self.out_queue.iter()
+    }
+
+    pub fn drain_output(&mut self) -> impl Iterator<Item = W> + '_ {
+        self.out_queue.drain(..)
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn relative_base(&self) -> W {
+        self.relative_base.clone()
+    }
+
+    pub fn input_len(&self) -> usize {
+        self.in_queue.len()
+    }
+
+    pub fn output_len(&self) -> usize {
+        self.out_queue.len()
+    }
+
+    pub fn memset(&mut self, addr: usize, value: W) {
+        self.observers.notify(|o| o.patch(addr, &value));
+        self.memory.set(addr, value);
+    }
+
+    pub fn memget(&self, addr: usize) -> W {
+        self.memory.get(addr)
+    }
+
+    /// Address and value of the memory write made by the last executed instruction.
+    pub fn last_write(&self) -> Option<&(usize, W)> {
+        self.last_write.as_ref()
+    }
+
+    pub fn image(&self) -> Arc<Vec<W>> {
+        self.memory.image.clone()
+    }
+
+    pub fn memory_len(&self) -> usize {
+        self.memory.len()
+    }
+
+    pub fn memory_snapshot(&self) -> Vec<W> {
+        self.memory.snapshot()
+    }
+
+    /// Copy of this machine sharing the program image; costs one copy per page written so far.
+    pub fn fork(&self) -> Machine<W> {
+        Machine {
+            memory: self.memory.clone(),
+            cursor: self.cursor,
+            relative_base: self.relative_base.clone(),
+            in_queue: self.in_queue.clone(),
+            out_queue: self.out_queue.clone(),
+            debug_mode: self.debug_mode,
+            steps: self.steps,
+            limits: self.limits,
+            labels: self.labels.clone(),
+            level: self.level,
+            ..Machine::from_image(self.memory.image.clone())
+        }
+    }
+
+    /// Back to the initial program image, dropping written pages and queued values.
+    pub fn reset(&mut self) {
+        self.memory.reset();
+        self.cursor = 0;
+        self.relative_base = W::from_i64(0);
+        self.last_write = None;
+        self.in_queue.clear();
+        self.out_queue.clear();
+        self.steps = 0;
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Traces every instruction to stderr, including calls and returns when they are tracked.
+    pub fn set_debug(&mut self, debug_mode: bool) {
+        self.debug_mode = debug_mode;
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Instructions and modes beyond `level` make `step` panic instead of executing.
+    pub fn set_level(&mut self, level: FeatureLevel) {
+        self.level = level;
+    }
+
+    pub fn level(&self) -> FeatureLevel {
+        self.level
+    }
+
+    /// Why `step` would panic on the instruction at the cursor: an unknown opcode or mode,
+    /// or one beyond the machine's level. Lets callers report it as an error instead.
+    pub fn check_instruction(&self) -> Result<(), String> {
+        let word = self.memory.get(self.cursor).to_i64();
+        let opcode = word % 100;
+        match FeatureLevel::of_opcode(opcode) {
+            None => return Err(format!("Invalid command {} at {}", opcode, self.cursor)),
+            Some(needed) if needed > self.level => {
+                return Err(format!(
+                    "Opcode {} at {} needs {}, machine runs {}",
+                    opcode, self.cursor, needed, self.level
+                ))
+            }
+            Some(_) => (),
+        }
+        for (index, tens) in TENS.iter().enumerate().take(instruction_len(opcode) - 1) {
+            let mode = word / tens % 10;
+            match FeatureLevel::of_mode(mode) {
+                None => return Err(format!("Invalid mode {} of parameter {} at {}", mode, index + 1, self.cursor)),
+                Some(needed) if needed > self.level => {
+                    return Err(format!(
+                        "Mode {} of parameter {} at {} needs {}, machine runs {}",
+                        mode,
+                        index + 1,
+                        self.cursor,
+                        needed,
+                        self.level
+                    ))
+                }
+                Some(_) => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// Observers are not carried over by `fork`.
+    pub fn add_observer(&mut self, observer: Box<dyn Observer<W> + Send>) {
+        self.observers.0.push(observer);
+    }
+
+    pub fn take_observers(&mut self) -> Vec<Box<dyn Observer<W> + Send>> {
+        std::mem::take(&mut self.observers.0)
+    }
+
+    /// Code labels to show in traces and backtraces instead of raw addresses.
+    pub fn set_labels(&mut self, labels: Arc<Labels>) {
+        self.labels = Some(labels);
+    }
+
+    pub fn labels(&self) -> Option<&Labels> {
+        self.labels.as_deref()
+    }
+
+    fn mode(&self, index: usize) -> ValueType {
+        self.memory.get(self.cursor).to_i64() / TENS[index] % 10
+    }
+
+    fn param_val(&mut self, index: usize) -> W {
+        let immediate_val = self.memory.get(self.cursor + index + 1);
         self.debug(&format!(
             "PARAM : immediate val = {}, mode = {}",
             immediate_val,
-            self.memory[self.cursor] / TENS[index] % 10
+            self.mode(index)
         ));
-        match self.memory[self.cursor] / TENS[index] % 10 {
-            MODE_POSITION => self.load(immediate_val),
-            MODE_RELATIVE => {
-                let addr = self.as_addr(self.relative_base + immediate_val);
-                self.memory[addr]
-            }
-            MODE_IMMEDIATE => immediate_val,
+        let mode = self.mode(index);
+        let addr = match mode {
+            MODE_POSITION => Some(self.as_addr(&immediate_val)),
+            MODE_RELATIVE => Some(self.as_addr(&self.relative_base.add_word(&immediate_val))),
+            MODE_IMMEDIATE => None,
             _ => panic!("Invalid mode code"),
+        };
+        let value = match addr {
+            Some(addr) => self.load(addr),
+            None => immediate_val,
+        };
+        if !self.observers.is_empty() {
+            self.operands.push(Operand {
+                mode,
+                addr,
+                value: value.clone(),
+            });
         }
+        value
     }
 
     fn param_out_addr(&mut self, index: usize) -> usize {
-        let immediate_val = self.memory[self.cursor + index + 1];
+        let immediate_val = self.memory.get(self.cursor + index + 1);
         self.debug(&format!(
             "OUT ADDR : immediate val = {}, mode = {}",
             immediate_val,
-            self.memory[self.cursor] / TENS[index] % 10
+            self.mode(index)
         ));
-        self.as_addr(match self.memory[self.cursor] / TENS[index] % 10 {
+        let mode = self.mode(index);
+        let addr = self.as_addr(&match mode {
             MODE_POSITION => immediate_val,
-            MODE_RELATIVE => self.relative_base + immediate_val,
+            MODE_RELATIVE => self.relative_base.add_word(&immediate_val),
             _ => panic!("Invalid mode code"),
-        })
+        });
+        if !self.observers.is_empty() {
+            // The value is filled in by `write`.
+            self.operands.push(Operand {
+                mode,
+                addr: Some(addr),
+                value: W::from_i64(0),
+            });
+        }
+        addr
     }
 
-    fn as_addr(&mut self, val: ValueType) -> usize {
-        let val = val as usize;
+    fn as_addr(&mut self, val: &W) -> usize {
+        let val = val.to_i64() as usize;
         if val >= self.memory.len() {
-            self.memory.resize(val + 1, 0);
+            self.memory.grow(val + 1);
         }
 
         val
     }
 
-    fn load(&mut self, addr: ValueType) -> ValueType {
-        let addr = self.as_addr(addr);
-        self.memory[addr]
+    fn load(&mut self, addr: usize) -> W {
+        let value = self.memory.get(addr);
+        self.observers.notify(|o| o.read(addr, &value));
+        value
+    }
+
+    fn write(&mut self, addr: usize, value: W) {
+        self.observers.notify(|o| o.write(addr, &value));
+        if let Some(operand) = self.operands.last_mut() {
+            operand.value = value.clone();
+        }
+        self.last_write = Some((addr, value.clone()));
+        self.memory.set(addr, value);
     }
 
     fn debug(&mut self, msg: &str) {
         match self.debug_mode {
             true => eprintln!(
                 "DEBUG [{} {} {} {}] {}",
-                self.cursor,
+                describe_addr(self.labels.as_deref(), self.cursor),
                 self.relative_base,
                 self.in_queue.len(),
                 self.out_queue.len(),
@@ -124,108 +981,142 @@ impl Machine {
     }
 }
 
-fn add(m: &mut Machine) -> State {
+fn add<W: Word>(m: &mut Machine<W>) -> State {
     let v1 = m.param_val(0);
     let v2 = m.param_val(1);
     let p_out = m.param_out_addr(2);
-    m.memory[p_out] = v1 + v2;
+    m.write(p_out, v1.add_word(&v2));
     m.debug(&format!(
         "ADD {} + {} => {} = {}",
-        v1, v2, p_out, m.memory[p_out]
+        v1, v2, p_out, m.memory.get(p_out)
     ));
     m.cursor += 4;
     State::Running
 }
 
-fn multiply(m: &mut Machine) -> State {
+fn multiply<W: Word>(m: &mut Machine<W>) -> State {
     let v1 = m.param_val(0);
     let v2 = m.param_val(1);
     let p_out = m.param_out_addr(2);
     m.debug(&format!("MULTI {} * {} => {}", v1, v2, p_out));
-    m.memory[p_out] = v1 * v2;
+    m.write(p_out, v1.mul_word(&v2));
     m.cursor += 4;
     State::Running
 }
 
-fn save(m: &mut Machine) -> State {
+fn save<W: Word>(m: &mut Machine<W>) -> State {
     match m.in_queue.pop_front() {
         None => State::InputBlock,
         Some(input_val) => {
             let p_out = m.param_out_addr(0);
-            m.memory[p_out] = input_val;
-            m.cursor += 2;
             m.debug(&format!("INPUT Save {} -> {}", input_val, p_out));
+            m.observers.notify(|o| o.input(&input_val));
+            m.write(p_out, input_val);
+            m.cursor += 2;
             State::Running
         }
     }
 }
 
-fn print(m: &mut Machine) -> State {
+fn print<W: Word>(m: &mut Machine<W>) -> State {
     let v = m.param_val(0);
-    m.out_queue.push_back(v);
     m.debug(&format!("PRINT {}", v));
+    m.observers.notify(|o| o.output(&v));
+    m.out_queue.push_back(v);
     m.cursor += 2;
     State::Running
 }
 
-fn jmp_if_non_zero(m: &mut Machine) -> State {
+fn jmp_if_non_zero<W: Word>(m: &mut Machine<W>) -> State {
     let v = m.param_val(0);
-    let destination = m.param_val(1) as usize;
+    let destination = m.param_val(1).to_i64() as usize;
     m.debug(&format!("JMP IF NON ZERO {} to {}", v, destination));
-    m.cursor = match v {
-        0 => m.cursor + 3,
-        _ => destination,
+    m.cursor = match v.is_zero() {
+        true => m.cursor + 3,
+        false => destination,
     };
     State::Running
 }
 
-fn jmp_if_zero(m: &mut Machine) -> State {
+fn jmp_if_zero<W: Word>(m: &mut Machine<W>) -> State {
     let v = m.param_val(0);
-    let destination = m.param_val(1) as usize;
+    let destination = m.param_val(1).to_i64() as usize;
     m.debug(&format!("JMP IF ZERO {} to {}", v, destination));
-    m.cursor = match v {
-        0 => destination,
-        _ => m.cursor + 3,
+    m.cursor = match v.is_zero() {
+        true => destination,
+        false => m.cursor + 3,
     };
     State::Running
 }
 
-fn cmp_lt(m: &mut Machine) -> State {
+fn cmp_lt<W: Word>(m: &mut Machine<W>) -> State {
     let v1 = m.param_val(0);
     let v2 = m.param_val(1);
     let p_out = m.param_out_addr(2);
     m.debug(&format!("CMP LT {} <=> {} -> {}", v1, v2, p_out));
-    m.memory[p_out] = match v1 < v2 {
+    let result = W::from_i64(match v1 < v2 {
         true => 1,
         false => 0,
-    };
+    });
+    m.write(p_out, result);
     m.cursor += 4;
     State::Running
 }
 
-fn cmp_eq(m: &mut Machine) -> State {
+fn cmp_eq<W: Word>(m: &mut Machine<W>) -> State {
     let v1 = m.param_val(0);
     let v2 = m.param_val(1);
     let p_out = m.param_out_addr(2);
     m.debug(&format!("CMP EQ {} <=> {} -> {}", v1, v2, p_out));
-    m.memory[p_out] = match v1 == v2 {
+    let result = W::from_i64(match v1 == v2 {
         true => 1,
         false => 0,
-    };
+    });
+    m.write(p_out, result);
     m.cursor += 4;
     State::Running
 }
 
-fn move_rbase(m: &mut Machine) -> State {
+/// Cells an instruction occupies, opcode word included; 1 for an invalid opcode.
+fn instruction_len(opcode: ValueType) -> usize {
+    match opcode {
+        ADD | MULTIPLY | CMP_LT | CMP_EQ => 4,
+        JMP_IF_NON_ZERO | JMP_IF_ZERO => 3,
+        INPUT | OUTPUT | MOVE_RBASE => 2,
+        _ => 1,
+    }
+}
+
+fn move_rbase<W: Word>(m: &mut Machine<W>) -> State {
     let v1 = m.param_val(0);
     m.debug(&format!("MOVE RBASE {} ", v1));
-    m.relative_base += v1;
+    let old = m.relative_base.clone();
+    m.relative_base = m.relative_base.add_word(&v1);
+    let new = m.relative_base.clone();
+    m.observers.notify(|o| o.relative_base(&old, &new));
     m.cursor += 2;
     State::Running
 }
 
-pub fn step(m: &mut Machine) -> State {
-    match m.memory[m.cursor] % 100 {
+pub fn step<W: Word>(m: &mut Machine<W>) -> State {
+    m.last_write = None;
+    m.operands.clear();
+    if m.limits.max_steps.is_some_and(|max| m.steps >= max) {
+        return State::StepLimit;
+    }
+    let cursor = m.cursor;
+    let opcode = m.memory.get(m.cursor).to_i64() % 100;
+    if let Err(e) = m.check_instruction() {
+        panic!("{}", e);
+    }
+    let before = match m.observers.is_empty() {
+        true => None,
+        false => {
+            let words: Vec<W> = (cursor..cursor + instruction_len(opcode)).map(|addr| m.memory.get(addr)).collect();
+            Some((words, m.relative_base.clone()))
+        }
+    };
+    let state = match opcode {
         ADD => add(m),
         MULTIPLY => multiply(m),
         INPUT => save(m),
@@ -236,13 +1127,47 @@ pub fn step(m: &mut Machine) -> State {
         CMP_EQ => cmp_eq(m),
         MOVE_RBASE => move_rbase(m),
         HALT => State::Halted,
-        invalid_code => panic!(format!("Invalid command {} at {}", invalid_code, m.cursor)),
+        _ => unreachable!(),
+    };
+    if let (Some((words, relative_base)), State::Running | State::Halted) = (&before, &state) {
+        let executed = Executed {
+            step: m.steps,
+            addr: cursor,
+            opcode,
+            words,
+            operands: &m.operands,
+            relative_base,
+            next: m.cursor,
+        };
+        m.observers.notify(|o| o.instruction(&executed));
     }
+    if state == State::Halted {
+        let steps = m.steps;
+        m.observers.notify(|o| o.halt(cursor, steps));
+    }
+    if state == State::Running {
+        m.steps += 1;
+        if m.limits.max_memory.is_some_and(|max| m.memory.len() > max) {
+            return State::MemoryLimit;
+        }
+    }
+    state
+}
+
+pub fn parse_program<W: Word>(text: &str) -> Result<Vec<W>, String> {
+    text.trim().split(',').map(|code| W::parse_word(code.trim())).collect()
+}
+
+pub fn load_program<W: Word>(path: &str) -> Result<Vec<W>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}. Error = {:#}", path, e))?;
+    parse_program(&text)
 }
 
-pub fn run_all<T>(m: &mut Machine, input: T) -> State
+pub fn run_all<W, T>(m: &mut Machine<W>, input: T) -> State
 where
-    T: Iterator<Item = ValueType>,
+    W: Word,
+    T: Iterator<Item = W>,
 {
     for v in input {
         m.in_queue.push_back(v);
@@ -250,8 +1175,41 @@ where
     loop {
         match step(m) {
             State::Running => (),
-            State::Halted => return State::Halted,
-            State::InputBlock => return State::InputBlock,
+            state => return state,
         }
     }
 }
+
+/// Runs until `done` holds after a step, or the machine halts or blocks on input.
+fn run_until<W, F>(m: &mut Machine<W>, reached: State, mut done: F) -> State
+where
+    W: Word,
+    F: FnMut(&Machine<W>) -> bool,
+{
+    loop {
+        match step(m) {
+            State::Running if done(m) => return reached,
+            State::Running => (),
+            state => return state,
+        }
+    }
+}
+
+pub fn run_until_outputs<W: Word>(m: &mut Machine<W>, count: usize) -> State {
+    if m.out_queue.len() >= count {
+        return State::OutputReady;
+    }
+    run_until(m, State::OutputReady, |m| m.out_queue.len() >= count)
+}
+
+pub fn run_until_addr<W: Word>(m: &mut Machine<W>, addr: usize) -> State {
+    run_until(m, State::AddressReached, |m| m.cursor == addr)
+}
+
+pub fn run_steps<W: Word>(m: &mut Machine<W>, count: u64) -> State {
+    if count == 0 {
+        return State::StepsDone;
+    }
+    let target = m.steps + count;
+    run_until(m, State::StepsDone, |m| m.steps >= target)
+}
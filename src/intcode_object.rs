@@ -0,0 +1,291 @@
+#![allow(dead_code)]
+
+use crate::intcode_machine::*;
+use crate::intcode_symbols::Symbols;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Address of the first cell after the linked program, defined by the linker so the entry
+/// module can put the stack there with `ARB #__end`.
+pub const END_SYMBOL: &str = "__end";
+
+/// An assembled module. Addresses in it start at 0; `relocs` lists words holding such
+/// addresses and `externs` words that get another module's global added at link time.
+///
+/// Stored as text, one entry per line:
+///
+/// ```text
+/// # intcode object
+/// module divmod
+/// source divmod.ics
+/// code 1007,...
+/// global divmod 0
+/// reloc 12
+/// extern 20 print_number
+/// label 14 dm_recurse
+/// data 40 table
+/// line 0 7
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Object {
+    pub name: String,
+    pub source: String,
+    pub code: Vec<ValueType>,
+    pub globals: BTreeMap<String, usize>,
+    pub relocs: Vec<usize>,
+    pub externs: Vec<(usize, String)>,
+    pub labels: BTreeMap<usize, String>,
+    pub data: BTreeMap<usize, String>,
+    pub lines: BTreeMap<usize, usize>,
+}
+
+impl Object {
+    /// Symbols referenced but not defined here.
+    pub fn undefined(&self) -> Vec<&str> {
+        self.externs.iter().map(|(_, name)| name.as_str()).collect()
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::from("# intcode object\n");
+        writeln!(text, "module {}", self.name).unwrap();
+        writeln!(text, "source {}", self.source).unwrap();
+        let code: Vec<String> = self.code.iter().map(|v| v.to_string()).collect();
+        writeln!(text, "code {}", code.join(",")).unwrap();
+        for (name, addr) in &self.globals {
+            writeln!(text, "global {} {}", name, addr).unwrap();
+        }
+        for addr in &self.relocs {
+            writeln!(text, "reloc {}", addr).unwrap();
+        }
+        for (addr, name) in &self.externs {
+            writeln!(text, "extern {} {}", addr, name).unwrap();
+        }
+        for (addr, name) in &self.labels {
+            writeln!(text, "label {} {}", addr, name).unwrap();
+        }
+        for (addr, name) in &self.data {
+            writeln!(text, "data {} {}", addr, name).unwrap();
+        }
+        for (addr, line) in &self.lines {
+            writeln!(text, "line {} {}", addr, line).unwrap();
+        }
+        text
+    }
+
+    pub fn parse(text: &str) -> Result<Object, String> {
+        let mut object = Object::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let located = |e: String| format!("line {}: {}", number + 1, e);
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let addr = |index: usize| -> Result<usize, String> {
+                let field = fields
+                    .get(index)
+                    .ok_or_else(|| located(format!("missing field in {:?}", line)))?;
+                let addr: usize = field
+                    .parse()
+                    .map_err(|e| located(format!("bad number {:?}. Error = {:#}", field, e)))?;
+                Ok(addr)
+            };
+            let name = |index: usize| -> Result<String, String> {
+                fields
+                    .get(index)
+                    .map(|name| String::from(*name))
+                    .ok_or_else(|| located(format!("missing name in {:?}", line)))
+            };
+            match fields[0] {
+                "module" => object.name = name(1)?,
+                "source" => object.source = fields[1..].join(" "),
+                "code" => object.code = parse_program(&fields[1..].concat()).map_err(located)?,
+                "global" => {
+                    object.globals.insert(name(1)?, addr(2)?);
+                }
+                "reloc" => object.relocs.push(addr(1)?),
+                "extern" => object.externs.push((addr(1)?, name(2)?)),
+                "label" => {
+                    object.labels.insert(addr(1)?, name(2)?);
+                }
+                "data" => {
+                    object.data.insert(addr(1)?, name(2)?);
+                }
+                "line" => {
+                    object.lines.insert(addr(1)?, addr(2)?);
+                }
+                kind => return Err(located(format!("unknown entry {:?}", kind))),
+            }
+        }
+        let words = object.code.len();
+        let mut patched = object.relocs.iter().chain(object.externs.iter().map(|(addr, _)| addr));
+        if let Some(addr) = patched.find(|&&addr| addr >= words) {
+            return Err(format!("Relocation at {} is outside the {} code words", addr, words));
+        }
+        Ok(object)
+    }
+
+    pub fn load(path: &str) -> Result<Object, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}. Error = {:#}", path, e))?;
+        Object::parse(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        std::fs::write(path, self.to_text())
+            .map_err(|e| format!("Failed to write {}. Error = {:#}", path, e))
+    }
+}
+
+/// Adds library modules until every extern is defined, in the order they are first needed.
+pub fn with_library(mut objects: Vec<Object>, library: &[Object]) -> Vec<Object> {
+    loop {
+        let defined = |name: &str, objects: &[Object]| {
+            name == END_SYMBOL || objects.iter().any(|o| o.globals.contains_key(name))
+        };
+        let missing: Option<String> = objects
+            .iter()
+            .flat_map(|o| o.undefined())
+            .find(|name| !defined(name, &objects))
+            .map(String::from);
+        let module = missing.and_then(|name| library.iter().find(|m| m.globals.contains_key(&name)));
+        match module {
+            Some(module) => objects.push(module.clone()),
+            None => return objects,
+        }
+    }
+}
+
+/// Lays the modules out in order, so the first one holds the entry point at address 0,
+/// and resolves every relocation. Globals keep their names in the symbols; other labels
+/// are qualified with their module, as in `print_number.pn_last`.
+pub fn link(objects: &[Object]) -> Result<(Vec<ValueType>, Symbols), String> {
+    let mut bases = vec![];
+    let mut end = 0;
+    for object in objects {
+        bases.push(end);
+        end += object.code.len();
+    }
+
+    let mut globals: BTreeMap<&str, usize> = BTreeMap::new();
+    globals.insert(END_SYMBOL, end);
+    for (object, &base) in objects.iter().zip(bases.iter()) {
+        for (name, &addr) in &object.globals {
+            if globals.insert(name, base + addr).is_some() {
+                return Err(format!("Symbol {} is defined twice (again in module {})", name, object.name));
+            }
+        }
+    }
+
+    let mut program = Vec::with_capacity(end);
+    let mut symbols = Symbols::default();
+    for (index, (object, &base)) in objects.iter().zip(bases.iter()).enumerate() {
+        let mut code = object.code.clone();
+        for &addr in &object.relocs {
+            code[addr] += base as ValueType;
+        }
+        for (addr, name) in &object.externs {
+            let target = globals.get(name.as_str()).ok_or_else(|| {
+                format!("Undefined symbol {} referenced in module {}", name, object.name)
+            })?;
+            code[*addr] += *target as ValueType;
+        }
+        program.extend(code);
+
+        symbols.files.push(object.source.clone());
+        for (&addr, label) in &object.labels {
+            let name = match object.globals.get(label) == Some(&addr) {
+                true => label.clone(),
+                false => format!("{}.{}", object.name, label),
+            };
+            symbols.labels.insert(base + addr, name);
+        }
+        for (&addr, name) in &object.data {
+            let name = match object.globals.get(name) == Some(&addr) {
+                true => name.clone(),
+                false => format!("{}.{}", object.name, name),
+            };
+            symbols.data.insert(base + addr, name);
+        }
+        for (&addr, &line) in &object.lines {
+            symbols.lines.insert(base + addr, (index, line));
+        }
+    }
+    Ok((program, symbols))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode_asm::assemble;
+
+    const MAIN: &str = "\
+.global main
+main:   ARB #__end
+        JNZ #1, #skip
+        HALT
+skip:   OUT [value]
+        call helper, 0
+        OUT [value]
+        HALT
+value:  DATA 7
+";
+
+    const HELPER: &str = "\
+.global helper
+helper: OUT [hv]
+        ret
+hv:     DATA 9
+";
+
+    fn module(name: &str, text: &str) -> Object {
+        assemble(name, &format!("{}.ics", name), text).unwrap()
+    }
+
+    fn run(program: &[ValueType]) -> Vec<ValueType> {
+        let mut machine = Machine::new(&program.to_vec());
+        assert_eq!(run_all(&mut machine, std::iter::empty()), State::Halted);
+        machine.drain_output().collect()
+    }
+
+    #[test]
+    fn links_modules_and_relocates_their_addresses() {
+        let main = module("main", MAIN);
+        let (program, symbols) = link(&[main.clone(), module("helper", HELPER)]).unwrap();
+        let base = main.code.len();
+        assert_eq!(program[1], program.len() as ValueType, "__end");
+        assert_eq!(program[base + 1], (base + 5) as ValueType, "[hv] moved with its module");
+        assert_eq!(run(&program), vec![7, 9, 7]);
+        assert_eq!(symbols.labels.get(&base).map(String::as_str), Some("helper"));
+        assert_eq!(symbols.data.get(&(base + 5)).map(String::as_str), Some("helper.hv"));
+        assert_eq!(symbols.lines.get(&base), Some(&(1, 2)));
+    }
+
+    #[test]
+    fn undefined_symbols_are_reported() {
+        let error = link(&[module("main", MAIN)]).unwrap_err();
+        assert_eq!(error, "Undefined symbol helper referenced in module main");
+    }
+
+    #[test]
+    fn globals_defined_twice_are_reported() {
+        let error = link(&[module("main", MAIN), module("a", HELPER), module("b", HELPER)]).unwrap_err();
+        assert_eq!(error, "Symbol helper is defined twice (again in module b)");
+    }
+
+    #[test]
+    fn library_modules_are_added_as_needed() {
+        let library = vec![module("unused", ".global unused\nunused: ret\n"), module("helper", HELPER)];
+        let objects = with_library(vec![module("main", MAIN)], &library);
+        let names: Vec<&str> = objects.iter().map(|o| o.name.as_str()).collect();
+        assert_eq!(names, vec!["main", "helper"]);
+    }
+
+    #[test]
+    fn objects_round_trip_through_text() {
+        let main = module("main", MAIN);
+        assert_eq!(Object::parse(&main.to_text()).unwrap(), main);
+        let error = Object::parse("module m\ncode 1,2\nreloc 5\n").unwrap_err();
+        assert_eq!(error, "Relocation at 5 is outside the 2 code words");
+    }
+}
@@ -0,0 +1,415 @@
+#![allow(dead_code)]
+
+use crate::intcode_disasm::{code_cells, reachable, Instruction, Mode, Param};
+use crate::intcode_machine::*;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+
+#[derive(Debug, Default)]
+pub struct Options {
+    pub compact: bool,
+    /// Relative-mode writes only touch a stack outside the program, as in compiled code.
+    /// Without this, a program that writes through `rb` could rewrite any of its code.
+    pub stack_writes_outside_code: bool,
+    /// Cells seen written while running the program, e.g. from `observe_writes`.
+    pub observed_writes: BTreeSet<usize>,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub propagated: usize,
+    pub folded: usize,
+    pub jumps: usize,
+    pub cleared: usize,
+    pub removed: usize,
+    pub compacted: bool,
+    pub notes: Vec<String>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "operands replaced by constants: {}", self.propagated)?;
+        writeln!(f, "instructions folded: {}", self.folded)?;
+        writeln!(f, "constant jumps resolved: {}", self.jumps)?;
+        writeln!(f, "unreachable cells cleared: {}", self.cleared)?;
+        match self.compacted {
+            true => writeln!(f, "cells removed by compaction: {}", self.removed)?,
+            false => writeln!(f, "not compacted")?,
+        }
+        for note in &self.notes {
+            writeln!(f, "note: {}", note)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs the program and returns its outputs and every cell it wrote.
+pub fn observe_writes(program: &Vec<ValueType>, input: &[ValueType]) -> (Vec<ValueType>, BTreeSet<usize>) {
+    let mut machine = Machine::new(program);
+    for &value in input {
+        machine.push_input(value);
+    }
+    let mut written = BTreeSet::new();
+    while step(&mut machine) == State::Running {
+        if let Some(&(addr, _)) = machine.last_write() {
+            written.insert(addr);
+        }
+    }
+    (machine.drain_output().collect(), written)
+}
+
+fn fold(opcode: ValueType, a: ValueType, b: ValueType) -> Option<ValueType> {
+    match opcode {
+        ADD => Some(a.wrapping_add(b)),
+        MULTIPLY => Some(a.wrapping_mul(b)),
+        CMP_LT => Some((a < b) as ValueType),
+        CMP_EQ => Some((a == b) as ValueType),
+        _ => None,
+    }
+}
+
+fn immediate(value: ValueType) -> Param {
+    Param {
+        mode: Mode::Immediate,
+        value,
+    }
+}
+
+/// Instructions reachable from a jump through memory, which may land on any value in the
+/// image that could be an address; empty when every jump has a constant target.
+fn indirectly_reachable(
+    memory: &[ValueType],
+    instructions: &BTreeMap<usize, Instruction>,
+) -> BTreeMap<usize, Instruction> {
+    let indirect = instructions
+        .values()
+        .any(|inst| inst.is_jump() && inst.static_target().is_none());
+    if !indirect {
+        return BTreeMap::new();
+    }
+    let candidates: Vec<usize> = memory
+        .iter()
+        .filter(|&&value| value >= 0 && (value as usize) < memory.len())
+        .map(|&value| value as usize)
+        .collect();
+    reachable(memory, &candidates)
+}
+
+/// Addresses where execution may enter other than by falling through: jump targets and
+/// the instructions after jumps. When the program jumps through memory, any value in the
+/// image that could be an address counts, along with code found from there.
+fn leaders(memory: &[ValueType], instructions: &BTreeMap<usize, Instruction>) -> BTreeSet<usize> {
+    let mut result: BTreeSet<usize> = instructions
+        .values()
+        .filter(|inst| inst.is_jump())
+        .flat_map(|inst| inst.static_target().into_iter().chain(Some(inst.next_addr())))
+        .collect();
+    result.insert(0);
+    for inst in indirectly_reachable(memory, instructions).values() {
+        result.insert(inst.addr);
+        result.extend(inst.successors(memory));
+    }
+    result
+}
+
+/// Peephole pass over straight-line code: operands read from cells with a value known from
+/// earlier in the block become immediates, arithmetic on immediates becomes `ADD #c, #0`,
+/// and jumps on constants become unconditional. Instructions in `pinned` are left alone.
+fn rewrite(
+    memory: &[ValueType],
+    instructions: &BTreeMap<usize, Instruction>,
+    pinned: &BTreeSet<usize>,
+    report: &mut Report,
+) -> Vec<ValueType> {
+    let mut out = memory.to_vec();
+    let starts = leaders(memory, instructions);
+    let mut known: HashMap<usize, ValueType> = HashMap::new();
+    let mut expected_next = 0;
+    for inst in instructions.values() {
+        if starts.contains(&inst.addr) || inst.addr != expected_next {
+            known.clear();
+        }
+        expected_next = inst.next_addr();
+        let mut new = inst.clone();
+        let reads = match inst.out_param() {
+            Some(_) if inst.opcode != INPUT => 2,
+            Some(_) => 0,
+            None if inst.opcode == HALT => 0,
+            None => inst.params.len(),
+        };
+        if !pinned.contains(&inst.addr) {
+            for param in new.params.iter_mut().take(reads) {
+                if param.mode != Mode::Position {
+                    continue;
+                }
+                if let Some(&value) = known.get(&(param.value as usize)) {
+                    *param = immediate(value);
+                    report.propagated += 1;
+                }
+            }
+        }
+        let constant = match new.params.iter().take(2).all(|p| p.mode == Mode::Immediate) {
+            true if new.out_param().is_some() && new.opcode != INPUT => {
+                fold(new.opcode, new.params[0].value, new.params[1].value)
+            }
+            _ => None,
+        };
+        if !pinned.contains(&inst.addr) {
+            if let Some(value) = constant {
+                let folded = Instruction {
+                    addr: inst.addr,
+                    opcode: ADD,
+                    params: vec![immediate(value), immediate(0), new.params[2]],
+                };
+                if folded != new {
+                    report.folded += 1;
+                    new = folded;
+                }
+            }
+            if new.constant_condition() == Some(true) && new.opcode == JMP_IF_ZERO {
+                new.opcode = JMP_IF_NON_ZERO;
+                new.params[0] = immediate(1);
+            }
+            if new.constant_condition().is_some() && inst.constant_condition().is_none() {
+                report.jumps += 1;
+            }
+            if new != *inst {
+                out[inst.addr..inst.next_addr()].copy_from_slice(&new.encode());
+            }
+        }
+
+        match new.out_param() {
+            Some(param) if param.mode == Mode::Position => {
+                let addr = param.value as usize;
+                match (new.opcode, constant) {
+                    (_, Some(value)) if new.opcode != INPUT && !pinned.contains(&inst.addr) => {
+                        known.insert(addr, value)
+                    }
+                    _ => known.remove(&addr),
+                };
+            }
+            Some(_) => known.clear(),
+            None => (),
+        }
+        if new.is_jump() {
+            known.clear();
+        }
+    }
+    out
+}
+
+/// Zeroes the cells of code the rewrite made unreachable, such as what follows a jump that
+/// became unconditional. Works in place, so it does not depend on compaction. Only cells that
+/// were code before the rewrite are cleared, never ones the program accesses as data.
+fn clear_unreachable(
+    memory: &[ValueType],
+    before: &BTreeMap<usize, Instruction>,
+    accessed: &BTreeSet<usize>,
+    report: &mut Report,
+) -> Vec<ValueType> {
+    let instructions = reachable(memory, &[0]);
+    let mut live = code_cells(&instructions);
+    live.extend(code_cells(&indirectly_reachable(memory, &instructions)));
+    let mut out = memory.to_vec();
+    for cell in code_cells(before) {
+        if !live.contains(&cell) && !accessed.contains(&cell) {
+            out[cell] = 0;
+            report.cleared += 1;
+        }
+    }
+    out
+}
+
+/// Reasons the program cannot be laid out again, if any. Moving code is only safe when every
+/// address it uses is visible in the instructions: no relative base, no jumps through memory,
+/// no reads or writes of code cells.
+fn compaction_blockers(instructions: &BTreeMap<usize, Instruction>, written: &BTreeSet<usize>) -> Vec<String> {
+    let cells = code_cells(instructions);
+    let mut blockers = vec![];
+    let relative = instructions
+        .values()
+        .any(|inst| inst.opcode == MOVE_RBASE || inst.params.iter().any(|p| p.mode == Mode::Relative));
+    if relative {
+        blockers.push(String::from("uses the relative base"));
+    }
+    if let Some(inst) = instructions
+        .values()
+        .find(|inst| inst.is_jump() && inst.static_target().is_none())
+    {
+        blockers.push(format!("jumps through memory at {}", inst.addr));
+    }
+    let touches_code = instructions.values().find(|inst| {
+        inst.params
+            .iter()
+            .any(|p| p.mode == Mode::Position && p.value >= 0 && cells.contains(&(p.value as usize)))
+    });
+    if let Some(inst) = touches_code {
+        blockers.push(format!("accesses code as data at {}", inst.addr));
+    }
+    if written.iter().any(|addr| cells.contains(addr)) {
+        blockers.push(String::from("writes to its own code"));
+    }
+    blockers
+}
+
+/// Drops unreachable code and resolved jumps, keeps referenced data cells, and renumbers
+/// every address operand. Cells past the end of the program keep their distance from it.
+fn compact(memory: &[ValueType], report: &mut Report) -> Vec<ValueType> {
+    let instructions = reachable(memory, &[0]);
+    let dropped = |inst: &Instruction| match inst.constant_condition() {
+        Some(false) => true,
+        Some(true) => inst.static_target() == Some(inst.next_addr()),
+        None => false,
+    };
+    let kept: BTreeMap<usize, &Instruction> = instructions
+        .iter()
+        .filter(|(_, inst)| !dropped(inst))
+        .map(|(&addr, inst)| (addr, inst))
+        .collect();
+    let mut cells: BTreeSet<usize> = kept
+        .values()
+        .flat_map(|inst| inst.addr..inst.next_addr())
+        .collect();
+    for inst in kept.values() {
+        for param in &inst.params {
+            if param.mode == Mode::Position && param.value >= 0 && (param.value as usize) < memory.len() {
+                cells.insert(param.value as usize);
+            }
+        }
+    }
+
+    let order: Vec<usize> = cells.iter().cloned().collect();
+    let new_len = order.len();
+    let code_addr = |addr: usize| order.iter().position(|&cell| cell >= addr).unwrap_or(new_len);
+    let data_addr = |addr: usize| match addr < memory.len() {
+        true => order.binary_search(&addr).unwrap(),
+        false => addr - memory.len() + new_len,
+    };
+
+    let mut out = Vec::with_capacity(new_len);
+    let mut skip_until = 0;
+    for &addr in &order {
+        if addr < skip_until {
+            continue;
+        }
+        match kept.get(&addr) {
+            Some(inst) => {
+                let mut moved = (*inst).clone();
+                let target = match moved.is_jump() {
+                    true => Some(1),
+                    false => None,
+                };
+                for (index, param) in moved.params.iter_mut().enumerate() {
+                    match param.mode {
+                        Mode::Position => param.value = data_addr(param.value as usize) as ValueType,
+                        Mode::Immediate if target == Some(index) => {
+                            param.value = code_addr(param.value as usize) as ValueType
+                        }
+                        _ => (),
+                    }
+                }
+                out.extend(moved.encode());
+                skip_until = inst.next_addr();
+            }
+            None => out.push(memory[addr]),
+        }
+    }
+    report.removed = memory.len() - out.len();
+    report.compacted = true;
+    out
+}
+
+pub fn optimize(memory: &[ValueType], options: &Options) -> Result<(Vec<ValueType>, Report), String> {
+    let mut report = Report::default();
+    let instructions = reachable(memory, &[0]);
+    let relative_writes = instructions
+        .values()
+        .any(|inst| inst.out_param().is_some_and(|p| p.mode == Mode::Relative));
+    if relative_writes && !options.stack_writes_outside_code {
+        return Err(String::from(
+            "Program writes through the relative base, so any code cell may change at runtime; \
+             refusing to optimize unless those writes are known to stay on the stack",
+        ));
+    }
+
+    let mut written = options.observed_writes.clone();
+    written.extend(
+        instructions
+            .values()
+            .filter_map(|inst| inst.out_param())
+            .filter(|p| p.mode == Mode::Position && p.value >= 0)
+            .map(|p| p.value as usize),
+    );
+    // Code that is read as data must keep its words, or the reads would see the rewrite.
+    let mut accessed = written.clone();
+    accessed.extend(
+        instructions
+            .values()
+            .flat_map(|inst| inst.params.iter())
+            .filter(|p| p.mode == Mode::Position && p.value >= 0)
+            .map(|p| p.value as usize),
+    );
+    let relative_reads = !options.stack_writes_outside_code
+        && instructions
+            .values()
+            .any(|inst| inst.params.iter().any(|p| p.mode == Mode::Relative));
+    let pinned: BTreeSet<usize> = instructions
+        .values()
+        .filter(|inst| relative_reads || (inst.addr..inst.next_addr()).any(|cell| accessed.contains(&cell)))
+        .map(|inst| inst.addr)
+        .collect();
+    if relative_reads && !pinned.is_empty() {
+        report
+            .notes
+            .push(String::from("program reads through the relative base, so every instruction is left as is"));
+    }
+    for inst in instructions.values().filter(|inst| !relative_reads && pinned.contains(&inst.addr)) {
+        let how = match (inst.addr..inst.next_addr()).any(|cell| written.contains(&cell)) {
+            true => "written",
+            false => "read as data",
+        };
+        report
+            .notes
+            .push(format!("instruction at {} is {} at runtime, left as is", inst.addr, how));
+    }
+
+    let out = rewrite(memory, &instructions, &pinned, &mut report);
+    let mut out = clear_unreachable(&out, &instructions, &accessed, &mut report);
+    if options.compact {
+        let blockers = compaction_blockers(&reachable(&out, &[0]), &written);
+        match blockers.is_empty() {
+            true => out = compact(&out, &mut report),
+            false => report.notes.push(format!("not compacted: program {}", blockers.join(", "))),
+        }
+    }
+    Ok((out, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code_read_as_data_is_not_folded() {
+        let program = vec![1101, 2, 3, 9, 4, 1, 4, 9, 99, 0];
+        let (optimized, _) = optimize(&program, &Options::default()).unwrap();
+        assert_eq!(observe_writes(&optimized, &[]).0, vec![2, 5]);
+    }
+
+    #[test]
+    fn code_after_a_resolved_jump_is_cleared_without_compaction() {
+        // [20] = 0; jz [20] -> 11 always jumps, so out 5 and its halt are dead. The ARB at
+        // the start keeps the program from being compacted.
+        let mut program = vec![109, 0, 1101, 0, 0, 20, 1006, 20, 13, 104, 5, 99, 0, 104, 6, 99];
+        program.resize(21, 0);
+        let options = Options {
+            compact: true,
+            ..Options::default()
+        };
+        let (optimized, report) = optimize(&program, &options).unwrap();
+        assert!(!report.compacted);
+        assert_eq!(&optimized[9..12], &[0, 0, 0]);
+        assert_eq!(report.cleared, 3);
+        assert_eq!(observe_writes(&optimized, &[]).0, vec![6]);
+    }
+}
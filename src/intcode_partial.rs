@@ -0,0 +1,584 @@
+#![allow(dead_code)]
+
+use crate::intcode_machine::*;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::fmt;
+
+/// Specialized copies of one address allowed before its next state is left to the original code.
+const MAX_VERSIONS: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Cell {
+    /// Known while specializing; `runtime` is what the residual program's memory holds
+    /// there, if that is known.
+    Known {
+        value: ValueType,
+        runtime: Option<ValueType>,
+    },
+    /// Only the residual program knows it, in its memory at the same address.
+    Dynamic,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Value {
+    Known(ValueType),
+    Dynamic(usize),
+}
+
+/// What the specializer knows at one point of the program. Cells not in `cells` hold the
+/// image's value, in the residual program's memory too except for cells 0..3, where the
+/// residual program starts with a jump to its code.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Env {
+    pc: usize,
+    relative_base: ValueType,
+    /// Relative base the residual program has set so far.
+    runtime_base: ValueType,
+    consumed: usize,
+    cells: BTreeMap<usize, Cell>,
+}
+
+struct Block {
+    env: Env,
+    at: Option<usize>,
+    abandoned: bool,
+}
+
+/// A program specialized for known leading inputs and memory patches.
+#[derive(Debug)]
+pub struct Residual {
+    pub program: Vec<ValueType>,
+    /// First cell of the residual code, which follows the original memory.
+    pub start: usize,
+    /// Instructions evaluated while specializing.
+    pub steps: u64,
+    pub instructions: usize,
+    pub blocks: usize,
+    /// Original addresses the residual code hands control back to.
+    pub fallbacks: BTreeSet<usize>,
+    /// Jumps to addresses computed at run time, which continue in the original code.
+    pub computed_jumps: usize,
+    /// Most known inputs read on any path.
+    pub consumed: usize,
+}
+
+impl fmt::Display for Residual {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "evaluated {} instructions ahead of time", self.steps)?;
+        writeln!(f, "known inputs consumed: {}", self.consumed)?;
+        writeln!(
+            f,
+            "residual code: {} instructions in {} blocks at {}..{}",
+            self.instructions,
+            self.blocks,
+            self.start,
+            self.program.len()
+        )?;
+        if !self.fallbacks.is_empty() {
+            let addrs: Vec<String> = self.fallbacks.iter().map(|a| a.to_string()).collect();
+            writeln!(f, "continues in the original code at {}", addrs.join(", "))?;
+        }
+        if self.computed_jumps > 0 {
+            writeln!(
+                f,
+                "computed jumps into the original code: {}",
+                self.computed_jumps
+            )?;
+        }
+        Ok(())
+    }
+}
+
+struct Specializer<'a> {
+    image: &'a [ValueType],
+    inputs: &'a [ValueType],
+    max_steps: u64,
+    steps: u64,
+    code: Vec<ValueType>,
+    /// Cells of `code` holding the address of a block, filled in once the code is placed.
+    fixups: Vec<(usize, usize)>,
+    blocks: Vec<Block>,
+    entries: HashMap<Env, usize>,
+    versions: HashMap<usize, usize>,
+    pending: VecDeque<usize>,
+    /// One past the highest address the specialized program touches.
+    end: usize,
+    instructions: usize,
+    fallbacks: BTreeSet<usize>,
+    computed_jumps: usize,
+    consumed: usize,
+}
+
+impl<'a> Specializer<'a> {
+    fn cell(&mut self, env: &Env, addr: usize) -> Cell {
+        self.end = self.end.max(addr + 1);
+        env.cells
+            .get(&addr)
+            .cloned()
+            .unwrap_or_else(|| self.initial(addr))
+    }
+
+    fn initial(&self, addr: usize) -> Cell {
+        let value = self.image.get(addr).cloned().unwrap_or(0);
+        Cell::Known {
+            value,
+            runtime: Some(value).filter(|_| addr >= 3),
+        }
+    }
+
+    fn set(&self, env: &mut Env, addr: usize, cell: Cell) {
+        match cell == self.initial(addr) {
+            true => env.cells.remove(&addr),
+            false => env.cells.insert(addr, cell),
+        };
+    }
+
+    fn write_known(&mut self, env: &mut Env, addr: usize, value: ValueType) {
+        let runtime = match self.cell(env, addr) {
+            Cell::Known { runtime, .. } => runtime,
+            Cell::Dynamic => None,
+        };
+        self.set(env, addr, Cell::Known { value, runtime });
+    }
+
+    fn addr(&self, env: &Env, word: ValueType, mode: ValueType) -> Option<usize> {
+        let addr = match mode {
+            MODE_RELATIVE => env.relative_base.wrapping_add(word),
+            _ => word,
+        };
+        match addr >= 0 {
+            true => Some(addr as usize),
+            false => None,
+        }
+    }
+
+    fn operand(&mut self, env: &Env, word: ValueType, mode: ValueType) -> Option<Value> {
+        if mode == MODE_IMMEDIATE {
+            return Some(Value::Known(word));
+        }
+        let addr = self.addr(env, word, mode)?;
+        match self.cell(env, addr) {
+            Cell::Known { value, .. } => Some(Value::Known(value)),
+            Cell::Dynamic => Some(Value::Dynamic(addr)),
+        }
+    }
+
+    fn emit(&mut self, opcode: ValueType, params: &[Value]) {
+        let mut word = opcode;
+        for (i, param) in params.iter().enumerate() {
+            if let Value::Known(_) = param {
+                word += TENS[i];
+            }
+        }
+        self.code.push(word);
+        for param in params {
+            self.code.push(match *param {
+                Value::Known(value) => value,
+                Value::Dynamic(addr) => addr as ValueType,
+            });
+        }
+        self.instructions += 1;
+    }
+
+    /// Emits a jump to block `id` taken when `cond` selects it, unconditionally for `None`.
+    fn jump(&mut self, opcode: ValueType, cond: Option<usize>, id: usize) {
+        let cond = cond.map_or(Value::Known(1), Value::Dynamic);
+        self.emit(opcode, &[cond, Value::Known(0)]);
+        self.fixups.push((self.code.len() - 1, id));
+    }
+
+    /// Makes the residual program's memory and relative base match what is known, so code
+    /// that was not specialized can run from here.
+    fn flush(&mut self, env: &mut Env) {
+        let addrs: BTreeSet<usize> = (0..3).chain(env.cells.keys().cloned()).collect();
+        for addr in addrs {
+            if let Cell::Known { value, runtime } = self.cell(env, addr) {
+                if runtime != Some(value) {
+                    self.emit(ADD, &[Value::Known(value), Value::Known(0), Value::Dynamic(addr)]);
+                    self.set(
+                        env,
+                        addr,
+                        Cell::Known {
+                            value,
+                            runtime: Some(value),
+                        },
+                    );
+                }
+            }
+        }
+        if env.relative_base != env.runtime_base {
+            self.emit(
+                MOVE_RBASE,
+                &[Value::Known(env.relative_base.wrapping_sub(env.runtime_base))],
+            );
+            env.runtime_base = env.relative_base;
+        }
+    }
+
+    fn fall_back(&mut self, mut env: Env) {
+        self.flush(&mut env);
+        self.emit(
+            JMP_IF_NON_ZERO,
+            &[Value::Known(1), Value::Known(env.pc as ValueType)],
+        );
+        self.fallbacks.insert(env.pc);
+    }
+
+    fn enter(&mut self, env: Env) -> usize {
+        if let Some(&id) = self.entries.get(&env) {
+            return id;
+        }
+        let versions = self.versions.entry(env.pc).or_insert(0);
+        *versions += 1;
+        let id = self.blocks.len();
+        self.blocks.push(Block {
+            env: env.clone(),
+            at: None,
+            abandoned: *versions > MAX_VERSIONS,
+        });
+        self.entries.insert(env, id);
+        self.pending.push_back(id);
+        id
+    }
+
+    /// Places block `id` and the blocks that fall through from it.
+    fn generate(&mut self, mut id: usize) {
+        loop {
+            self.blocks[id].at = Some(self.code.len());
+            let env = self.blocks[id].env.clone();
+            if self.blocks[id].abandoned {
+                return self.fall_back(env);
+            }
+            match self.specialize(env) {
+                Some(next) if self.blocks[next].at.is_none() => id = next,
+                Some(next) => return self.jump(JMP_IF_NON_ZERO, None, next),
+                None => return,
+            }
+        }
+    }
+
+    /// Evaluates from `env` until the program halts, leaves for the original code or
+    /// branches on an unknown value. Returns the block to continue with after a branch.
+    fn specialize(&mut self, mut env: Env) -> Option<usize> {
+        loop {
+            if self.steps >= self.max_steps {
+                self.fall_back(env);
+                return None;
+            }
+            let Some(instr) = self.fetch(&env) else {
+                self.fall_back(env);
+                return None;
+            };
+            let opcode = instr[0] % 100;
+            let modes: Vec<ValueType> = (0..3).map(|i| instr[0] / TENS[i] % 10).collect();
+            let mut values = vec![];
+            for i in 1..instr.len() {
+                match self.operand(&env, instr[i], modes[i - 1]) {
+                    Some(value) => values.push(value),
+                    None => {
+                        self.fall_back(env);
+                        return None;
+                    }
+                }
+            }
+            self.steps += 1;
+            match opcode {
+                ADD | MULTIPLY | CMP_LT | CMP_EQ => {
+                    let dest = self.addr(&env, instr[3], modes[2]).unwrap();
+                    match (values[0], values[1]) {
+                        (Value::Known(a), Value::Known(b)) => {
+                            let value = match opcode {
+                                ADD => a.wrapping_add(b),
+                                MULTIPLY => a.wrapping_mul(b),
+                                CMP_LT => (a < b) as ValueType,
+                                _ => (a == b) as ValueType,
+                            };
+                            self.write_known(&mut env, dest, value);
+                        }
+                        (a, b) => {
+                            self.emit(opcode, &[a, b, Value::Dynamic(dest)]);
+                            self.set(&mut env, dest, Cell::Dynamic);
+                        }
+                    }
+                    env.pc += 4;
+                }
+                INPUT => {
+                    let dest = self.addr(&env, instr[1], modes[0]).unwrap();
+                    match self.inputs.get(env.consumed) {
+                        Some(&value) => {
+                            self.write_known(&mut env, dest, value);
+                            env.consumed += 1;
+                            self.consumed = self.consumed.max(env.consumed);
+                        }
+                        None => {
+                            self.emit(INPUT, &[Value::Dynamic(dest)]);
+                            self.set(&mut env, dest, Cell::Dynamic);
+                        }
+                    }
+                    env.pc += 2;
+                }
+                OUTPUT => {
+                    self.emit(OUTPUT, &values[..1]);
+                    env.pc += 2;
+                }
+                JMP_IF_NON_ZERO | JMP_IF_ZERO => match (values[0], values[1]) {
+                    (Value::Known(cond), target) => {
+                        if (cond != 0) != (opcode == JMP_IF_NON_ZERO) {
+                            env.pc += 3;
+                            continue;
+                        }
+                        match target {
+                            Value::Known(target) if target >= 0 => env.pc = target as usize,
+                            Value::Known(_) => {
+                                self.fall_back(env);
+                                return None;
+                            }
+                            Value::Dynamic(_) => {
+                                self.flush(&mut env);
+                                self.emit(JMP_IF_NON_ZERO, &[Value::Known(1), target]);
+                                self.computed_jumps += 1;
+                                return None;
+                            }
+                        }
+                    }
+                    (Value::Dynamic(cond), Value::Known(target)) if target >= 0 => {
+                        let taken = self.enter(Env {
+                            pc: target as usize,
+                            ..env.clone()
+                        });
+                        self.jump(opcode, Some(cond), taken);
+                        env.pc += 3;
+                        return Some(self.enter(env));
+                    }
+                    (cond, target) => {
+                        self.flush(&mut env);
+                        self.emit(opcode, &[cond, target]);
+                        self.computed_jumps += 1;
+                        env.pc += 3;
+                    }
+                },
+                MOVE_RBASE => match values[0] {
+                    Value::Known(delta) => {
+                        env.relative_base = env.relative_base.wrapping_add(delta);
+                        env.pc += 2;
+                    }
+                    Value::Dynamic(_) => {
+                        self.fall_back(env);
+                        return None;
+                    }
+                },
+                _ => {
+                    self.flush(&mut env);
+                    self.emit(HALT, &[]);
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Words of the instruction at `env.pc`, or `None` if any of them is unknown or invalid,
+    /// which leaves the instruction to the original code.
+    fn fetch(&mut self, env: &Env) -> Option<Vec<ValueType>> {
+        let word = match self.cell(env, env.pc) {
+            Cell::Known { value, .. } => value,
+            Cell::Dynamic => return None,
+        };
+        let len = match word % 100 {
+            ADD | MULTIPLY | CMP_LT | CMP_EQ => 4,
+            JMP_IF_NON_ZERO | JMP_IF_ZERO => 3,
+            INPUT | OUTPUT | MOVE_RBASE => 2,
+            HALT => 1,
+            _ => return None,
+        };
+        let mut instr = vec![word];
+        for i in 1..len {
+            let mode = word / TENS[i - 1] % 10;
+            let writes = i == len - 1 && [ADD, MULTIPLY, CMP_LT, CMP_EQ, INPUT].contains(&(word % 100));
+            if mode > MODE_RELATIVE || (writes && mode == MODE_IMMEDIATE) {
+                return None;
+            }
+            match self.cell(env, env.pc + i) {
+                Cell::Known { value, .. } => instr.push(value),
+                Cell::Dynamic => return None,
+            }
+        }
+        Some(instr)
+    }
+}
+
+/// Evaluates `program` with `patches` applied and `inputs` known in advance, and builds a
+/// residual program that behaves like it on whatever inputs come after those.
+///
+/// Everything that depends only on known values is evaluated, including code after the first
+/// unknown input. The residual code keeps the instructions that read unknown values, with known
+/// operands turned into immediates, and emits the known outputs in order. A branch on an unknown
+/// value specializes both successors, sharing code for states already seen. The residual
+/// program keeps the original memory layout, with the residual code after it and a jump to it
+/// in cells 0..3. When specializing cannot continue, because the relative base or an
+/// instruction word becomes unknown, a jump goes to a computed address, an address was
+/// specialized too many times or `max_steps` instructions were evaluated, the residual code
+/// writes out what it knows and continues in the original code.
+///
+/// The residual code occupies cells the original program would see as zero; `code_at` moves it
+/// further out for a program that uses memory past the cells touched while specializing.
+pub fn specialize(
+    program: &[ValueType],
+    patches: &[(usize, ValueType)],
+    inputs: &[ValueType],
+    max_steps: Option<u64>,
+    code_at: Option<usize>,
+) -> Result<Residual, String> {
+    let mut image = program.to_vec();
+    for &(addr, value) in patches {
+        if image.len() <= addr {
+            image.resize(addr + 1, 0);
+        }
+        image[addr] = value;
+    }
+    image.resize(image.len().max(3), 0);
+
+    let mut specializer = Specializer {
+        image: &image,
+        inputs,
+        max_steps: max_steps.unwrap_or(u64::MAX),
+        steps: 0,
+        code: vec![],
+        fixups: vec![],
+        blocks: vec![],
+        entries: HashMap::new(),
+        versions: HashMap::new(),
+        pending: VecDeque::new(),
+        end: image.len(),
+        instructions: 0,
+        fallbacks: BTreeSet::new(),
+        computed_jumps: 0,
+        consumed: 0,
+    };
+    let root = specializer.enter(Env {
+        pc: 0,
+        relative_base: 0,
+        runtime_base: 0,
+        consumed: 0,
+        cells: BTreeMap::new(),
+    });
+    specializer.generate(root);
+    while let Some(id) = specializer.pending.pop_front() {
+        if specializer.blocks[id].at.is_none() {
+            specializer.generate(id);
+        }
+    }
+
+    let start = match code_at {
+        Some(addr) if addr < specializer.end => {
+            return Err(format!(
+                "Residual code at {} would overlap memory used up to {}",
+                addr, specializer.end
+            ))
+        }
+        Some(addr) => addr,
+        None => specializer.end,
+    };
+    let mut code = specializer.code;
+    for &(cell, id) in &specializer.fixups {
+        code[cell] = (start + specializer.blocks[id].at.unwrap()) as ValueType;
+    }
+    let mut memory = image.clone();
+    memory.resize(start, 0);
+    memory[..3].copy_from_slice(&[1105, 1, start as ValueType]);
+    memory.extend(code);
+
+    Ok(Residual {
+        program: memory,
+        start,
+        steps: specializer.steps,
+        instructions: specializer.instructions,
+        blocks: specializer.blocks.len(),
+        fallbacks: specializer.fallbacks,
+        computed_jumps: specializer.computed_jumps,
+        consumed: specializer.consumed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(program: &[ValueType], inputs: &[ValueType]) -> (Vec<ValueType>, Machine) {
+        let mut machine = Machine::new(&program.to_vec());
+        assert_eq!(run_all(&mut machine, inputs.iter().cloned()), State::Halted);
+        (machine.drain_output().collect(), machine)
+    }
+
+    #[test]
+    fn known_inputs_are_evaluated_away() {
+        // in [9]; [9] *= 2; out [9]
+        let program = vec![3, 9, 1002, 9, 2, 9, 4, 9, 99, 0];
+        let residual = specialize(&program, &[], &[21], None, None).unwrap();
+        // Halting writes back the known memory, the entry jump's cells included.
+        assert_eq!(
+            &residual.program[residual.start..],
+            &[104, 42, 1101, 3, 0, 0, 1101, 9, 0, 1, 1101, 1002, 0, 2, 1101, 42, 0, 9, 99]
+        );
+        assert_eq!(run(&residual.program, &[]).0, vec![42]);
+    }
+
+    #[test]
+    fn code_after_an_unknown_input_is_still_evaluated() {
+        // sum 10 + 9 + ... + 1 into [100], then out sum + in
+        let program = vec![
+            1101, 0, 0, 100, 1101, 0, 10, 101, 1, 100, 101, 100, 101, -1, 101, 101, 1005, 101, 8, 3, 102, 1,
+            100, 102, 103, 4, 103, 99,
+        ];
+        let residual = specialize(&program, &[], &[], None, None).unwrap();
+        assert_eq!(
+            &residual.program[residual.start..],
+            &[
+                3, 102, 101, 55, 102, 103, 4, 103, 1101, 1101, 0, 0, 1101, 0, 0, 1, 1101, 0, 0, 2, 1101, 55,
+                0, 100, 99
+            ]
+        );
+        assert_eq!(residual.steps, 36);
+        assert!(residual.fallbacks.is_empty());
+        for x in [0, 7, -100] {
+            assert_eq!(run(&residual.program, &[x]).0, run(&program, &[x]).0);
+        }
+    }
+
+    #[test]
+    fn both_sides_of_an_unknown_branch_are_specialized() {
+        // in [20]; jz [20] -> 9; out [21] + 1; halt; 9: out [21]; halt
+        let mut program = vec![3, 20, 1006, 20, 13, 1001, 21, 1, 22, 4, 22, 99, 0, 4, 21, 99];
+        program.resize(22, 0);
+        program[21] = 5;
+        let residual = specialize(&program, &[], &[], None, None).unwrap();
+        assert!(residual.fallbacks.is_empty());
+        for x in [0, 1, -3] {
+            assert_eq!(run(&residual.program, &[x]).0, run(&program, &[x]).0);
+        }
+    }
+
+    #[test]
+    fn loop_on_unknown_input_falls_back_to_the_original_code() {
+        // count inputs up to and including the first zero
+        let program = vec![3, 100, 101, 1, 101, 101, 1005, 100, 0, 4, 101, 99];
+        let residual = specialize(&program, &[], &[], None, None).unwrap();
+        assert!(residual.fallbacks.contains(&0));
+        let mut inputs = vec![1; 40];
+        inputs.push(0);
+        for inputs in [&inputs[..], &[0], &inputs[25..]] {
+            assert_eq!(run(&residual.program, inputs).0, run(&program, inputs).0);
+        }
+    }
+
+    #[test]
+    fn memory_at_halt_matches_including_the_entry_cells() {
+        let program = vec![1, 0, 0, 0, 99];
+        let residual = specialize(&program, &[(1, 4)], &[], None, None).unwrap();
+        let (_, original) = run(&[1, 4, 0, 0, 99], &[]);
+        let (_, specialized) = run(&residual.program, &[]);
+        assert_eq!(
+            &specialized.memory_snapshot()[..5],
+            &original.memory_snapshot()[..5]
+        );
+    }
+}
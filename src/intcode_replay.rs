@@ -0,0 +1,195 @@
+#![allow(dead_code)]
+
+use crate::intcode_machine::*;
+use std::fmt;
+use std::fs;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug)]
+pub struct Divergence {
+    pub index: usize,
+    pub expected: Option<IoEvent>,
+    pub actual: Option<IoEvent>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let show = |event: &Option<IoEvent>| match event {
+            Some(event) => format_event(event),
+            None => String::from("<nothing>"),
+        };
+        write!(
+            f,
+            "Divergence at event {}: expected {}, got {}",
+            self.index,
+            show(&self.expected),
+            show(&self.actual)
+        )
+    }
+}
+
+fn format_event(event: &IoEvent) -> String {
+    match event {
+        IoEvent::Input(step, value) => format!("in {} {}", step, value),
+        IoEvent::Output(step, value) => format!("out {} {}", step, value),
+        IoEvent::Patch(step, addr, value) => format!("set {} {} {}", step, addr, value),
+    }
+}
+
+fn parse_event(line: &str) -> Result<IoEvent, String> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let num = |index: usize| -> Result<i64, String> {
+        fields
+            .get(index)
+            .ok_or(format!("Missing field in {:?}", line))?
+            .parse()
+            .map_err(|e| format!("Bad number in {:?}. Error = {:#}", line, e))
+    };
+    match fields.first() {
+        Some(&"in") => Ok(IoEvent::Input(num(1)? as u64, num(2)?)),
+        Some(&"out") => Ok(IoEvent::Output(num(1)? as u64, num(2)?)),
+        Some(&"set") => Ok(IoEvent::Patch(num(1)? as u64, num(2)? as usize, num(3)?)),
+        _ => Err(format!("Unknown event {:?}", line)),
+    }
+}
+
+/// Observer collecting a machine's inputs, outputs and patches, stamped with the step count.
+pub struct SessionRecorder {
+    events: Arc<Mutex<Vec<IoEvent>>>,
+    steps: u64,
+}
+
+impl SessionRecorder {
+    /// Records from the machine's current step on. Keep the returned handle to save the
+    /// session after the run.
+    pub fn attach(machine: &mut Machine) -> Arc<Mutex<Vec<IoEvent>>> {
+        let events = Arc::new(Mutex::new(vec![]));
+        machine.add_observer(Box::new(SessionRecorder {
+            events: events.clone(),
+            steps: machine.steps(),
+        }));
+        events
+    }
+
+    fn push(&mut self, event: IoEvent) {
+        self.events.lock().unwrap().push(event);
+    }
+}
+
+impl Observer for SessionRecorder {
+    fn input(&mut self, value: &ValueType) {
+        self.push(IoEvent::Input(self.steps, *value));
+    }
+
+    fn output(&mut self, value: &ValueType) {
+        self.push(IoEvent::Output(self.steps, *value));
+    }
+
+    fn patch(&mut self, addr: usize, value: &ValueType) {
+        self.push(IoEvent::Patch(self.steps, addr, *value));
+    }
+
+    fn instruction(&mut self, executed: &Executed) {
+        // A halt does not count as a step.
+        if executed.opcode != HALT {
+            self.steps = executed.step + 1;
+        }
+    }
+}
+
+pub fn save_session(path: &str, events: &[IoEvent]) -> Result<(), String> {
+    let mut text = String::from("# intcode session: kind step [addr] value\n");
+    for event in events {
+        text.push_str(&format_event(event));
+        text.push('\n');
+    }
+    fs::write(path, text).map_err(|e| format!("Failed to write {}. Error = {:#}", path, e))
+}
+
+pub fn load_session(path: &str) -> Result<Vec<IoEvent>, String> {
+    fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}. Error = {:#}", path, e))?
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(parse_event)
+        .collect()
+}
+
+/// Reruns `program` feeding the recorded inputs and patches, and checks that the machine
+/// produces the same sequence of events. Events are compared as they happen, so a run that
+/// goes astray stops at its first wrong event, or once it passes the step the next expected
+/// event was recorded at without producing it.
+pub fn replay(program: &Vec<ValueType>, session: &[IoEvent]) -> Result<State, Divergence> {
+    let mut machine = Machine::new(program);
+    let recording = SessionRecorder::attach(&mut machine);
+    let mut patches = vec![];
+    for event in session {
+        match event {
+            IoEvent::Input(_, value) => machine.push_input(*value),
+            IoEvent::Patch(step, addr, value) => patches.push((*step, *addr, *value)),
+            IoEvent::Output(_, _) => (),
+        }
+    }
+
+    let mut patches = patches.into_iter().peekable();
+    let mut checked = 0;
+    let state = loop {
+        while let Some(&(_, addr, value)) = patches.peek().filter(|p| p.0 <= machine.steps()) {
+            machine.memset(addr, value);
+            patches.next();
+        }
+        let state = step(&mut machine);
+        for actual in recording.lock().unwrap().drain(..) {
+            if session.get(checked) != Some(&actual) {
+                return Err(Divergence {
+                    index: checked,
+                    expected: session.get(checked).cloned(),
+                    actual: Some(actual),
+                });
+            }
+            checked += 1;
+        }
+        let overdue = match session.get(checked) {
+            Some(IoEvent::Input(at, _)) | Some(IoEvent::Output(at, _)) | Some(IoEvent::Patch(at, _, _)) => {
+                machine.steps() > *at
+            }
+            None => false,
+        };
+        if state != State::Running || overdue {
+            break state;
+        }
+    };
+
+    match session.get(checked) {
+        Some(expected) => Err(Divergence {
+            index: checked,
+            expected: Some(expected.clone()),
+            actual: None,
+        }),
+        None => Ok(state),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // in [12]; [13] = [12] == 8; spins on its jump unless it was, then out [12]
+    const PROGRAM: [ValueType; 14] = [3, 12, 1008, 12, 8, 13, 1006, 13, 6, 4, 12, 99, 0, 0];
+
+    #[test]
+    fn matching_session_replays() {
+        let session = vec![IoEvent::Input(0, 8), IoEvent::Output(3, 8)];
+        assert_eq!(replay(&PROGRAM.to_vec(), &session).unwrap(), State::Halted);
+    }
+
+    #[test]
+    fn divergent_loop_stops_at_the_missing_event() {
+        let session = vec![IoEvent::Input(0, 7), IoEvent::Output(3, 8)];
+        let divergence = replay(&PROGRAM.to_vec(), &session).unwrap_err();
+        assert_eq!(divergence.index, 1);
+        assert_eq!(divergence.expected, Some(IoEvent::Output(3, 8)));
+        assert_eq!(divergence.actual, None);
+    }
+}
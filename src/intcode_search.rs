@@ -0,0 +1,246 @@
+#![allow(dead_code)]
+
+use crate::intcode_machine::*;
+use crate::util::permute;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Memory patches and inputs for one run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Candidate {
+    pub patches: Vec<(usize, ValueType)>,
+    pub inputs: Vec<ValueType>,
+}
+
+impl Candidate {
+    fn extend(&mut self, other: &Candidate) {
+        self.patches.extend(other.patches.iter().cloned());
+        self.inputs.extend(other.inputs.iter().cloned());
+    }
+}
+
+impl fmt::Display for Candidate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts: Vec<String> = self
+            .patches
+            .iter()
+            .map(|(addr, value)| format!("[{}]={}", addr, value))
+            .collect();
+        if !self.inputs.is_empty() {
+            let inputs: Vec<String> = self.inputs.iter().map(|v| v.to_string()).collect();
+            parts.push(format!("input {}", inputs.join(",")));
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+fn parse_value(text: &str) -> Result<ValueType, String> {
+    text.trim()
+        .parse()
+        .map_err(|e| format!("Bad value {:?}. Error = {:#}", text, e))
+}
+
+/// `LO..HI` excluding HI or `LO..=HI` including it.
+fn parse_range(text: &str) -> Result<Vec<ValueType>, String> {
+    let (lo, hi, inclusive) = match (text.split_once("..="), text.split_once("..")) {
+        (Some((lo, hi)), _) => (lo, hi, true),
+        (None, Some((lo, hi))) => (lo, hi, false),
+        _ => return Err(format!("Bad range {:?}, expected LO..HI or LO..=HI", text)),
+    };
+    let (lo, hi) = (parse_value(lo)?, parse_value(hi)?);
+    Ok(match inclusive {
+        true => (lo..=hi).collect(),
+        false => (lo..hi).collect(),
+    })
+}
+
+/// One dimension of a search space: the choices it contributes to a candidate.
+#[derive(Debug, Clone)]
+pub struct Axis(Vec<Candidate>);
+
+impl Axis {
+    /// Each value of `values` written to `addr`.
+    pub fn cell(addr: usize, values: impl IntoIterator<Item = ValueType>) -> Axis {
+        Axis(
+            values
+                .into_iter()
+                .map(|value| Candidate {
+                    patches: vec![(addr, value)],
+                    inputs: vec![],
+                })
+                .collect(),
+        )
+    }
+
+    /// Each value of `values` as one input.
+    pub fn input(values: impl IntoIterator<Item = ValueType>) -> Axis {
+        Axis(
+            values
+                .into_iter()
+                .map(|value| Candidate {
+                    patches: vec![],
+                    inputs: vec![value],
+                })
+                .collect(),
+        )
+    }
+
+    /// Every ordering of `values` as a sequence of inputs, like day7's phase settings.
+    pub fn permutations(values: &[ValueType]) -> Axis {
+        Axis(
+            permute(values.iter().cloned())
+                .map(|inputs| Candidate {
+                    patches: vec![],
+                    inputs,
+                })
+                .collect(),
+        )
+    }
+
+    /// Spec as given on the command line:
+    ///
+    /// - `cell:ADDR=RANGE`: the cell takes each value of the range
+    /// - `input:RANGE`: one input taking each value of the range
+    /// - `perm:A,B,...`: the values as inputs, in every order
+    ///
+    /// Ranges are `LO..HI` or `LO..=HI`.
+    pub fn parse(spec: &str) -> Result<Axis, String> {
+        if let Some(rest) = spec.strip_prefix("cell:") {
+            let (addr, range) = rest
+                .split_once('=')
+                .ok_or_else(|| format!("Bad axis {:?}, expected cell:ADDR=RANGE", spec))?;
+            let addr = addr
+                .trim()
+                .parse()
+                .map_err(|e| format!("Bad address {:?}. Error = {:#}", addr, e))?;
+            return Ok(Axis::cell(addr, parse_range(range)?));
+        }
+        if let Some(range) = spec.strip_prefix("input:") {
+            return Ok(Axis::input(parse_range(range)?));
+        }
+        if let Some(values) = spec.strip_prefix("perm:") {
+            let values: Vec<ValueType> = values.split(',').map(parse_value).collect::<Result<_, _>>()?;
+            return Ok(Axis::permutations(&values));
+        }
+        Err(format!("Bad axis {:?}, expected cell:, input: or perm:", spec))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+/// Cartesian product of axes. Candidates are numbered with the last axis varying fastest,
+/// so a search visits them in the order of nested loops over the axes.
+#[derive(Debug, Clone, Default)]
+pub struct Space {
+    pub axes: Vec<Axis>,
+}
+
+impl Space {
+    pub fn new(axes: Vec<Axis>) -> Space {
+        Space { axes }
+    }
+
+    pub fn len(&self) -> usize {
+        self.axes.iter().map(|axis| axis.len()).product()
+    }
+
+    /// Patches and inputs of every axis in order; inputs are queued in axis order too.
+    pub fn candidate(&self, mut index: usize) -> Candidate {
+        let mut choices = Vec::with_capacity(self.axes.len());
+        for axis in self.axes.iter().rev() {
+            choices.push(&axis.0[index % axis.len()]);
+            index /= axis.len();
+        }
+        let mut candidate = Candidate::default();
+        for choice in choices.into_iter().rev() {
+            candidate.extend(choice);
+        }
+        candidate
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Goal {
+    /// The lowest numbered candidate with a result; later candidates stop being evaluated.
+    First,
+    /// The candidate with the largest result, the lowest numbered one on ties.
+    Max,
+}
+
+#[derive(Debug, Clone)]
+pub struct Found<K> {
+    pub index: usize,
+    pub candidate: Candidate,
+    pub result: K,
+}
+
+/// Evaluates the candidates of `space` on `threads` threads. Each candidate gets a fork of
+/// `base` with its patches applied and inputs queued; `evaluate` runs it and returns a result,
+/// or None for a candidate that does not qualify. The outcome does not depend on `threads`.
+pub fn search<K, F>(base: &Machine, space: &Space, threads: usize, goal: Goal, evaluate: F) -> Option<Found<K>>
+where
+    K: Ord + Send,
+    F: Fn(&mut Machine, &Candidate) -> Option<K> + Sync,
+{
+    let len = space.len();
+    let next = AtomicUsize::new(0);
+    let first_found = AtomicUsize::new(usize::MAX);
+    let best: Mutex<Option<Found<K>>> = Mutex::new(None);
+    // Machines carry observers and are not shared between threads, so each worker forks its own.
+    let workers: Vec<Machine> = (0..threads.max(1)).map(|_| base.fork()).collect();
+    std::thread::scope(|scope| {
+        for worker in workers {
+            let (next, first_found, best, evaluate) = (&next, &first_found, &best, &evaluate);
+            scope.spawn(move || loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                if index >= len || (goal == Goal::First && index > first_found.load(Ordering::SeqCst)) {
+                    break;
+                }
+                let candidate = space.candidate(index);
+                let mut machine = worker.fork();
+                for &(addr, value) in &candidate.patches {
+                    machine.memset(addr, value);
+                }
+                for &value in &candidate.inputs {
+                    machine.push_input(value);
+                }
+                let result = match evaluate(&mut machine, &candidate) {
+                    Some(result) => result,
+                    None => continue,
+                };
+                let mut best = best.lock().unwrap();
+                let better = match (best.as_ref(), goal) {
+                    (None, _) => true,
+                    (Some(found), Goal::First) => index < found.index,
+                    (Some(found), Goal::Max) => {
+                        result > found.result || (result == found.result && index < found.index)
+                    }
+                };
+                if better {
+                    first_found.fetch_min(index, Ordering::SeqCst);
+                    *best = Some(Found {
+                        index,
+                        candidate,
+                        result,
+                    });
+                }
+            });
+        }
+    });
+    best.into_inner().unwrap()
+}
+
+/// Runs a prepared candidate to the end; its outputs if it halted.
+pub fn halted_outputs(machine: &mut Machine) -> Option<Vec<ValueType>> {
+    match run_all(machine, std::iter::empty()) {
+        State::Halted => Some(machine.drain_output().collect()),
+        _ => None,
+    }
+}
+
+pub fn default_threads() -> usize {
+    std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
@@ -0,0 +1,221 @@
+#![allow(dead_code)]
+
+use crate::intcode_machine::*;
+use std::panic;
+use std::path::{Path, PathBuf};
+
+/// One test case, written as a `[name]` section of `key = value` lines:
+///
+/// ```text
+/// [day5 diagnostics]
+/// program = day5.txt
+/// set 0 = 3
+/// input = 1
+/// output = 0, 0, 4511442
+/// memory 225 = 4511442
+/// state = halted
+/// max_steps = 100000
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Spec {
+    pub name: String,
+    pub program: PathBuf,
+    pub patches: Vec<(usize, ValueType)>,
+    pub input: Vec<ValueType>,
+    pub output: Option<Vec<ValueType>>,
+    pub memory: Vec<(usize, ValueType)>,
+    pub state: Option<String>,
+    pub limits: Limits,
+}
+
+#[derive(Debug)]
+pub struct Outcome {
+    pub name: String,
+    pub failures: Vec<String>,
+}
+
+impl Outcome {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+fn parse_list(text: &str) -> Result<Vec<ValueType>, String> {
+    match text.trim().is_empty() {
+        true => Ok(vec![]),
+        false => parse_program(text),
+    }
+}
+
+fn parse_cell(key: &str, value: &str) -> Result<(usize, ValueType), String> {
+    let addr = key
+        .split_whitespace()
+        .nth(1)
+        .ok_or(format!("Missing address in {:?}", key))?;
+    let addr = addr
+        .parse()
+        .map_err(|e| format!("Bad address {:?}. Error = {:#}", addr, e))?;
+    let value = Word::parse_word(value)?;
+    Ok((addr, value))
+}
+
+fn parse_limit<T: std::str::FromStr>(value: &str) -> Result<Option<T>, String> {
+    value
+        .parse()
+        .map(Some)
+        .map_err(|_| format!("Bad limit {:?}", value))
+}
+
+pub fn parse_specs(text: &str, base_dir: &Path) -> Result<Vec<Spec>, String> {
+    let mut specs: Vec<Spec> = vec![];
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let located = |e: String| format!("line {}: {}", number + 1, e);
+        if line.starts_with('[') && line.ends_with(']') {
+            specs.push(Spec {
+                name: String::from(line[1..line.len() - 1].trim()),
+                ..Spec::default()
+            });
+            continue;
+        }
+        let spec = specs
+            .last_mut()
+            .ok_or_else(|| located(String::from("expected a [name] section first")))?;
+        let mut parts = line.splitn(2, '=');
+        let key = parts.next().unwrap().trim();
+        let value = parts
+            .next()
+            .ok_or_else(|| located(format!("expected key = value, got {:?}", line)))?
+            .trim();
+        match key.split_whitespace().next().unwrap_or("") {
+            "program" => spec.program = base_dir.join(value),
+            "input" => spec.input = parse_list(value).map_err(located)?,
+            "output" => spec.output = Some(parse_list(value).map_err(located)?),
+            "memory" => spec.memory.push(parse_cell(key, value).map_err(located)?),
+            "set" => spec.patches.push(parse_cell(key, value).map_err(located)?),
+            "state" => spec.state = Some(String::from(value)),
+            "max_steps" => spec.limits.max_steps = parse_limit(value).map_err(located)?,
+            "max_memory" => spec.limits.max_memory = parse_limit(value).map_err(located)?,
+            _ => return Err(located(format!("unknown key {:?}", key))),
+        }
+    }
+    Ok(specs)
+}
+
+pub fn load_specs(path: &Path) -> Result<Vec<Spec>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}. Error = {:#}", path.display(), e))?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+    parse_specs(&text, base_dir).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn state_name(state: &State) -> &'static str {
+    match state {
+        State::Halted => "halted",
+        State::InputBlock => "input",
+        State::StepLimit => "step-limit",
+        State::MemoryLimit => "memory-limit",
+        _ => "running",
+    }
+}
+
+fn diff_outputs(expected: &[ValueType], actual: &[ValueType]) -> Option<String> {
+    if expected == actual {
+        return None;
+    }
+    let index = expected
+        .iter()
+        .zip(actual.iter())
+        .position(|(e, a)| e != a)
+        .unwrap_or_else(|| expected.len().min(actual.len()));
+    let show = |value: Option<&ValueType>| match value {
+        Some(value) => value.to_string(),
+        None => String::from("nothing"),
+    };
+    Some(format!(
+        "output differs at index {}: expected {}, got {}\n      expected: {:?}\n      actual:   {:?}",
+        index,
+        show(expected.get(index)),
+        show(actual.get(index)),
+        expected,
+        actual
+    ))
+}
+
+/// Runs a spec, treating a panic in the interpreter as a failure. `default_limits` applies
+/// to every limit the spec does not set itself.
+pub fn run_spec(spec: &Spec, default_limits: Limits) -> Outcome {
+    let mut failures = vec![];
+    let program: Vec<ValueType> = match load_program(&spec.program.to_string_lossy()) {
+        Ok(program) => program,
+        Err(e) => {
+            return Outcome {
+                name: spec.name.clone(),
+                failures: vec![e],
+            }
+        }
+    };
+
+    let limits = Limits {
+        max_steps: spec.limits.max_steps.or(default_limits.max_steps),
+        max_memory: spec.limits.max_memory.or(default_limits.max_memory),
+    };
+    let result = panic::catch_unwind(|| {
+        let mut machine = Machine::new(&program);
+        machine.set_limits(limits);
+        for &(addr, value) in &spec.patches {
+            machine.memset(addr, value);
+        }
+        let state = run_all(&mut machine, spec.input.iter().cloned());
+        (state, machine)
+    });
+    let (state, mut machine) = match result {
+        Ok(result) => result,
+        Err(cause) => {
+            let message = cause
+                .downcast_ref::<String>()
+                .cloned()
+                .or_else(|| cause.downcast_ref::<&str>().map(|s| String::from(*s)))
+                .unwrap_or_default();
+            return Outcome {
+                name: spec.name.clone(),
+                failures: vec![format!("machine panicked: {}", message)],
+            };
+        }
+    };
+
+    let actual_state = state_name(&state);
+    match &spec.state {
+        Some(expected) if expected != actual_state => failures.push(format!(
+            "end state: expected {}, got {} after {} steps",
+            expected,
+            actual_state,
+            machine.steps()
+        )),
+        None if state != State::Halted => failures.push(format!(
+            "machine stopped with {} after {} steps",
+            actual_state,
+            machine.steps()
+        )),
+        _ => (),
+    }
+
+    let outputs: Vec<ValueType> = machine.drain_output().collect();
+    if let Some(expected) = &spec.output {
+        failures.extend(diff_outputs(expected, &outputs));
+    }
+    for &(addr, expected) in &spec.memory {
+        let actual = machine.memget(addr);
+        if actual != expected {
+            failures.push(format!("memory[{}]: expected {}, got {}", addr, expected, actual));
+        }
+    }
+
+    Outcome {
+        name: spec.name.clone(),
+        failures,
+    }
+}
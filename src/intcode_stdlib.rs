@@ -0,0 +1,189 @@
+#![allow(dead_code)]
+
+use crate::intcode_asm::assemble;
+use crate::intcode_object::Object;
+
+/// `divmod(a, b)` with `a >= 0`, `b > 0`: quotient in `[rb+1]`, remainder in `[rb+2]`.
+/// Intcode has no division, so this doubles `b` recursively and subtracts on the way back.
+const DIVMOD: &str = "\
+.global divmod
+divmod:     LT [rb+1], [rb+2], [rb+4]
+            JZ [rb+4], #dm_recurse
+            ADD [rb+1], #0, [rb+2]      ; a < b: remainder a
+            ADD #0, #0, [rb+1]          ; quotient 0
+            ret
+dm_recurse: ADD [rb+1], #0, [rb+6]
+            MUL [rb+2], #2, [rb+7]
+            call divmod, 5              ; divmod(a, 2b)
+            MUL [rb+6], #2, [rb+1]
+            ADD [rb+7], #0, [rb+4]
+            LT [rb+4], [rb+2], [rb+3]
+            JNZ [rb+3], #dm_done
+            ADD [rb+1], #1, [rb+1]      ; remainder >= b: one more b fits
+            MUL [rb+2], #-1, [rb+3]
+            ADD [rb+4], [rb+3], [rb+4]
+dm_done:    ADD [rb+4], #0, [rb+2]
+            ret
+";
+
+/// `print_number(n)`: outputs `n` in decimal ASCII, with a leading '-' when negative.
+const PRINT_NUMBER: &str = "\
+.global print_number
+print_number:
+            LT [rb+1], #0, [rb+2]
+            JZ [rb+2], #pn_digits
+            OUT #45
+            MUL [rb+1], #-1, [rb+1]
+pn_digits:  ADD [rb+1], #0, [rb+4]
+            ADD #10, #0, [rb+5]
+            call divmod, 3              ; [rb+4] = n / 10, [rb+5] = n % 10
+            ADD [rb+5], #48, [rb+2]
+            JZ [rb+4], #pn_last
+            call print_number, 3        ; leading digits, argument already in [rb+4]
+pn_last:    OUT [rb+2]
+            ret
+";
+
+/// `read_number()`: reads ASCII input up to a newline and returns the decimal value in
+/// `[rb+1]`. A '-' negates it; other non-digits are skipped.
+const READ_NUMBER: &str = "\
+.global read_number
+read_number:
+            ADD #0, #0, [rb+1]
+            ADD #1, #0, [rb+2]          ; sign
+rn_next:    IN [rb+3]
+            EQ [rb+3], #10, [rb+4]
+            JNZ [rb+4], #rn_done
+            EQ [rb+3], #45, [rb+4]
+            JZ [rb+4], #rn_digit
+            MUL [rb+2], #-1, [rb+2]
+            JNZ #1, #rn_next
+rn_digit:   LT [rb+3], #48, [rb+4]
+            JNZ [rb+4], #rn_next
+            LT #57, [rb+3], [rb+4]
+            JNZ [rb+4], #rn_next
+            MUL [rb+1], #10, [rb+1]
+            ADD [rb+1], [rb+3], [rb+1]
+            ADD [rb+1], #-48, [rb+1]
+            JNZ #1, #rn_next
+rn_done:    MUL [rb+1], [rb+2], [rb+1]
+            ret
+";
+
+/// `pow(base, exp)` with `exp >= 0`: `base` multiplied by itself in a loop, result in `[rb+1]`.
+const POW: &str = "\
+.global pow
+pow:        ADD #1, #0, [rb+3]
+pw_loop:    LT #0, [rb+2], [rb+4]
+            JZ [rb+4], #pw_done
+            MUL [rb+3], [rb+1], [rb+3]
+            ADD [rb+2], #-1, [rb+2]
+            JNZ #1, #pw_loop
+pw_done:    ADD [rb+3], #0, [rb+1]
+            ret
+";
+
+pub const MODULES: [(&str, &str); 4] = [
+    ("divmod", DIVMOD),
+    ("print_number", PRINT_NUMBER),
+    ("read_number", READ_NUMBER),
+    ("pow", POW),
+];
+
+/// The bundled routines, assembled. Sources are recorded as `<stdlib>/name.ics`.
+pub fn library() -> Vec<Object> {
+    MODULES
+        .iter()
+        .map(|(name, text)| {
+            let source = format!("<stdlib>/{}.ics", name);
+            assemble(name, &source, text).unwrap_or_else(|e| panic!("stdlib: {}", e))
+        })
+        .collect()
+}
+
+pub fn source(name: &str) -> Option<&'static str> {
+    MODULES.iter().find(|(module, _)| *module == name).map(|(_, text)| *text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode_machine::*;
+    use crate::intcode_object::{link, with_library};
+
+    /// Links `main` with the library routines it needs.
+    fn build(main: &str) -> (Vec<Object>, Vec<ValueType>) {
+        let objects = with_library(vec![assemble("main", "main.ics", main).unwrap()], &library());
+        let (program, _) = link(&objects).unwrap();
+        (objects, program)
+    }
+
+    fn run(program: &[ValueType], input: Vec<ValueType>) -> Vec<ValueType> {
+        let mut machine = Machine::new(&program.to_vec());
+        assert_eq!(run_all(&mut machine, input.into_iter()), State::Halted);
+        machine.drain_output().collect()
+    }
+
+    fn run_ascii(program: &[ValueType], input: &str) -> String {
+        let input = input.bytes().map(|b| b as ValueType).collect();
+        run(program, input).into_iter().map(|v| v as u8 as char).collect()
+    }
+
+    #[test]
+    fn print_number_prints_decimal() {
+        let (objects, program) = build(
+            "\
+main:   ARB #__end
+        IN [rb+1]
+        call print_number, 0
+        OUT #10
+        HALT
+",
+        );
+        let names: Vec<&str> = objects.iter().map(|o| o.name.as_str()).collect();
+        assert_eq!(names, vec!["main", "print_number", "divmod"]);
+        for &n in &[0, 7, 42, -1205, 1 << 40] {
+            let output: String = run(&program, vec![n]).into_iter().map(|v| v as u8 as char).collect();
+            assert_eq!(output, format!("{}\n", n));
+        }
+    }
+
+    #[test]
+    fn reads_numbers_and_raises_to_a_power() {
+        let (_, program) = build(
+            "\
+main:   ARB #__end
+        call read_number, 0
+        ADD [rb+1], #0, [rb+20]
+        call read_number, 0
+        ADD [rb+1], #0, [rb+2]
+        ADD [rb+20], #0, [rb+1]
+        call pow, 0
+        call print_number, 0
+        OUT #10
+        HALT
+",
+        );
+        assert_eq!(run_ascii(&program, "-3\n5\n"), "-243\n");
+        assert_eq!(run_ascii(&program, "x1y0\n12\n"), "1000000000000\n");
+        assert_eq!(run_ascii(&program, "7\n0\n"), "1\n");
+    }
+
+    #[test]
+    fn divmod_returns_quotient_and_remainder() {
+        let (_, program) = build(
+            "\
+main:   ARB #__end
+        IN [rb+1]
+        IN [rb+2]
+        call divmod, 0
+        OUT [rb+1]
+        OUT [rb+2]
+        HALT
+",
+        );
+        for &(a, b) in &[(17, 5), (0, 3), (4, 4), (1_000_000_007, 10)] {
+            assert_eq!(run(&program, vec![a, b]), vec![a / b, a % b]);
+        }
+    }
+}
@@ -0,0 +1,222 @@
+#![allow(dead_code)]
+
+use crate::intcode_disasm::{reachable, Instruction, Mode};
+use crate::intcode_machine::*;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+
+/// Debug information kept next to a program, one entry per line:
+///
+/// ```text
+/// # intcode symbols
+/// file 0 game.ics
+/// label 20 loop_start
+/// line 20 0 14
+/// data 50 counter
+/// ```
+///
+/// `line` maps an address to a source file index and line; every instruction up to the
+/// next `line` entry belongs to the same source line.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Symbols {
+    pub files: Vec<String>,
+    pub labels: Labels,
+    pub lines: BTreeMap<usize, (usize, usize)>,
+    pub data: BTreeMap<usize, String>,
+}
+
+/// Where tools look for symbols when none are given: `day9.txt` pairs with `day9.sym`.
+pub fn sidecar_path(program: &str) -> String {
+    Path::new(program)
+        .with_extension("sym")
+        .to_string_lossy()
+        .into_owned()
+}
+
+impl Symbols {
+    pub fn parse(text: &str) -> Result<Symbols, String> {
+        let mut symbols = Symbols::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let located = |e: String| format!("line {}: {}", number + 1, e);
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let num = |index: usize| -> Result<usize, String> {
+                let field = fields
+                    .get(index)
+                    .ok_or_else(|| located(format!("missing field in {:?}", line)))?;
+                field
+                    .parse()
+                    .map_err(|e| located(format!("bad number {:?}. Error = {:#}", field, e)))
+            };
+            let name = |index: usize| -> Result<String, String> {
+                match fields.len() > index {
+                    true => Ok(fields[index..].join(" ")),
+                    false => Err(located(format!("missing name in {:?}", line))),
+                }
+            };
+            match fields[0] {
+                "file" => {
+                    let index = num(1)?;
+                    if symbols.files.len() <= index {
+                        symbols.files.resize(index + 1, String::new());
+                    }
+                    symbols.files[index] = name(2)?;
+                }
+                "label" => {
+                    symbols.labels.insert(num(1)?, name(2)?);
+                }
+                "line" => {
+                    symbols.lines.insert(num(1)?, (num(2)?, num(3)?));
+                }
+                "data" => {
+                    symbols.data.insert(num(1)?, name(2)?);
+                }
+                kind => return Err(located(format!("unknown entry {:?}", kind))),
+            }
+        }
+        Ok(symbols)
+    }
+
+    pub fn load(path: &str) -> Result<Symbols, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}. Error = {:#}", path, e))?;
+        Symbols::parse(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    /// Symbols from `explicit` if given, else from the program's sidecar file if there is one.
+    pub fn for_program(program: &str, explicit: Option<&str>) -> Result<Option<Symbols>, String> {
+        match explicit {
+            Some(path) => Symbols::load(path).map(Some),
+            None => {
+                let sidecar = sidecar_path(program);
+                match Path::new(&sidecar).exists() {
+                    true => Symbols::load(&sidecar).map(Some),
+                    false => Ok(None),
+                }
+            }
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::from("# intcode symbols\n");
+        for (index, file) in self.files.iter().enumerate() {
+            writeln!(text, "file {} {}", index, file).unwrap();
+        }
+        for (addr, label) in &self.labels {
+            writeln!(text, "label {} {}", addr, label).unwrap();
+        }
+        for (addr, (file, line)) in &self.lines {
+            writeln!(text, "line {} {} {}", addr, file, line).unwrap();
+        }
+        for (addr, name) in &self.data {
+            writeln!(text, "data {} {}", addr, name).unwrap();
+        }
+        text
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        std::fs::write(path, self.to_text())
+            .map_err(|e| format!("Failed to write {}. Error = {:#}", path, e))
+    }
+
+    /// `loop_start+3` style name for a code address.
+    pub fn describe(&self, addr: usize) -> String {
+        describe_addr(Some(&self.labels), addr)
+    }
+
+    /// Exact name of a memory cell, data names first.
+    pub fn name_of(&self, addr: usize) -> Option<&str> {
+        self.data
+            .get(&addr)
+            .or_else(|| self.labels.get(&addr))
+            .map(|name| name.as_str())
+    }
+
+    /// Address of a data name or label.
+    pub fn lookup(&self, name: &str) -> Option<usize> {
+        self.data
+            .iter()
+            .chain(self.labels.iter())
+            .find(|(_, candidate)| candidate.as_str() == name)
+            .map(|(&addr, _)| addr)
+    }
+
+    /// Source file and line the instruction at `addr` came from.
+    pub fn source_line(&self, addr: usize) -> Option<(&str, usize)> {
+        let (_, &(file, line)) = self.lines.range(..=addr).next_back()?;
+        Some((self.files.get(file)?.as_str(), line))
+    }
+
+    /// First address of each line entry for `line` in a file; `file` matches by path suffix,
+    /// since debuggers send absolute paths and symbol files usually hold relative ones.
+    pub fn addrs_for_line(&self, file: &str, line: usize) -> Vec<usize> {
+        let matches = |name: &str| !name.is_empty() && (file == name || Path::new(file).ends_with(name));
+        self.lines
+            .iter()
+            .filter(|(_, &(index, entry))| {
+                entry == line && self.files.get(index).is_some_and(|name| matches(name))
+            })
+            .map(|(&addr, _)| addr)
+            .collect()
+    }
+
+    /// Instruction text with named cells and jump targets: `JNZ [done], #loop_start`.
+    pub fn render(&self, inst: &Instruction) -> String {
+        let mut text = String::from(crate::intcode_disasm::mnemonic(inst.opcode));
+        for (index, param) in inst.params.iter().enumerate() {
+            text.push_str(match index {
+                0 => " ",
+                _ => ", ",
+            });
+            let name = match param.value >= 0 {
+                true => self.name_of(param.value as usize),
+                false => None,
+            };
+            let target = inst.is_jump() && index == 1;
+            match (param.mode, name) {
+                (Mode::Position, Some(name)) => write!(text, "[{}]", name).unwrap(),
+                (Mode::Immediate, Some(name)) if target => write!(text, "#{}", name).unwrap(),
+                _ => write!(text, "{}", param).unwrap(),
+            }
+        }
+        text
+    }
+}
+
+/// Like `intcode_disasm::disassemble`, with label lines, named operands and source lines.
+pub fn disassemble(memory: &[ValueType], symbols: &Symbols) -> String {
+    let mut entries = vec![0];
+    entries.extend(symbols.labels.keys());
+    let instructions = reachable(memory, &entries);
+    let mut text = String::new();
+    let mut addr = 0;
+    while addr < memory.len() {
+        if let Some(label) = symbols.labels.get(&addr) {
+            writeln!(text, "{}:", label).unwrap();
+        }
+        match instructions.get(&addr) {
+            Some(inst) => {
+                write!(text, "{:>6}: {}", addr, symbols.render(inst)).unwrap();
+                if let Some(&(file, line)) = symbols.lines.get(&addr) {
+                    let file = symbols.files.get(file).map_or("?", |name| name.as_str());
+                    write!(text, "    ; {}:{}", file, line).unwrap();
+                }
+                text.push('\n');
+                addr = inst.next_addr();
+            }
+            None => {
+                write!(text, "{:>6}: DATA {}", addr, memory[addr]).unwrap();
+                if let Some(name) = symbols.data.get(&addr) {
+                    write!(text, "    ; {}", name).unwrap();
+                }
+                text.push('\n');
+                addr += 1;
+            }
+        }
+    }
+    text
+}
@@ -0,0 +1,242 @@
+#![allow(dead_code)]
+
+use crate::intcode_machine::*;
+use std::fmt;
+use std::fs;
+use std::sync::{Arc, Mutex};
+
+/// One executed instruction and what it did.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TraceStep {
+    pub step: u64,
+    pub cursor: usize,
+    pub relative_base: ValueType,
+    /// The instruction as it was in memory: opcode word, then raw parameters.
+    pub words: Vec<ValueType>,
+    /// Memory loads made for position and relative parameters, in parameter order.
+    pub reads: Vec<(usize, ValueType)>,
+    pub writes: Vec<(usize, ValueType)>,
+    pub input: Option<ValueType>,
+    pub outputs: Vec<ValueType>,
+}
+
+fn join(values: &[ValueType]) -> String {
+    let text: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+    text.join(",")
+}
+
+fn join_cells(cells: &[(usize, ValueType)]) -> String {
+    let text: Vec<String> = cells.iter().map(|(addr, v)| format!("{}:{}", addr, v)).collect();
+    text.join(",")
+}
+
+/// `step @cursor rb=N op=WORDS [r=A:V,..] [w=A:V,..] [in=V] [out=V,..]`
+impl fmt::Display for TraceStep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} @{} rb={} op={}",
+            self.step,
+            self.cursor,
+            self.relative_base,
+            join(&self.words)
+        )?;
+        if !self.reads.is_empty() {
+            write!(f, " r={}", join_cells(&self.reads))?;
+        }
+        if !self.writes.is_empty() {
+            write!(f, " w={}", join_cells(&self.writes))?;
+        }
+        if let Some(input) = self.input {
+            write!(f, " in={}", input)?;
+        }
+        if !self.outputs.is_empty() {
+            write!(f, " out={}", join(&self.outputs))?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(text: &str, line: &str) -> Result<T, String>
+where
+    T::Err: fmt::Display,
+{
+    text.parse()
+        .map_err(|e| format!("Bad number {:?} in {:?}. Error = {:#}", text, line, e))
+}
+
+fn parse_list(text: &str, line: &str) -> Result<Vec<ValueType>, String> {
+    text.split(',').map(|field| parse_number(field, line)).collect()
+}
+
+fn parse_cells(text: &str, line: &str) -> Result<Vec<(usize, ValueType)>, String> {
+    text.split(',')
+        .map(|field| {
+            let (addr, value) = field
+                .split_once(':')
+                .ok_or_else(|| format!("Bad cell {:?} in {:?}", field, line))?;
+            Ok((parse_number(addr, line)?, parse_number(value, line)?))
+        })
+        .collect()
+}
+
+impl TraceStep {
+    fn parse(line: &str) -> Result<TraceStep, String> {
+        let mut fields = line.split_whitespace();
+        let mut step = TraceStep {
+            step: parse_number(fields.next().unwrap_or(""), line)?,
+            ..TraceStep::default()
+        };
+        for field in fields {
+            if let Some(cursor) = field.strip_prefix('@') {
+                step.cursor = parse_number(cursor, line)?;
+                continue;
+            }
+            let (key, value) = field
+                .split_once('=')
+                .ok_or_else(|| format!("Bad field {:?} in {:?}", field, line))?;
+            match key {
+                "rb" => step.relative_base = parse_number(value, line)?,
+                "op" => step.words = parse_list(value, line)?,
+                "r" => step.reads = parse_cells(value, line)?,
+                "w" => step.writes = parse_cells(value, line)?,
+                "in" => step.input = Some(parse_number(value, line)?),
+                "out" => step.outputs = parse_list(value, line)?,
+                _ => return Err(format!("Unknown field {:?} in {:?}", key, line)),
+            }
+        }
+        Ok(step)
+    }
+
+    /// Parts of two steps that differ, among those a divergence is judged by.
+    pub fn differences(&self, other: &TraceStep) -> Vec<&'static str> {
+        let mut fields = vec![];
+        if self.cursor != other.cursor {
+            fields.push("cursor");
+        }
+        if self.words != other.words || self.reads != other.reads {
+            fields.push("operands");
+        }
+        if self.writes != other.writes {
+            fields.push("writes");
+        }
+        if self.outputs != other.outputs {
+            fields.push("outputs");
+        }
+        fields
+    }
+}
+
+/// Memory when recording started, then every instruction executed.
+#[derive(Debug, Clone, Default)]
+pub struct Trace {
+    pub memory: Vec<ValueType>,
+    pub steps: Vec<TraceStep>,
+}
+
+impl Trace {
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let mut text = String::from("# intcode trace: step @cursor rb= op= [r=] [w=] [in=] [out=]\n");
+        text.push_str(&format!("memory {}\n", join(&self.memory)));
+        for step in &self.steps {
+            text.push_str(&step.to_string());
+            text.push('\n');
+        }
+        fs::write(path, text).map_err(|e| format!("Failed to write {}. Error = {:#}", path, e))
+    }
+
+    pub fn load(path: &str) -> Result<Trace, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Failed to read {}. Error = {:#}", path, e))?;
+        let mut trace = Trace::default();
+        for line in text.lines().map(|line| line.trim()) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.strip_prefix("memory ") {
+                Some(memory) => trace.memory = parse_list(memory, "memory")?,
+                None => trace.steps.push(TraceStep::parse(line)?),
+            }
+        }
+        Ok(trace)
+    }
+
+    /// Memory as it was before step `index` ran, rebuilt from the recorded writes.
+    pub fn memory_at(&self, index: usize) -> Vec<ValueType> {
+        let mut memory = self.memory.clone();
+        for step in &self.steps[..index.min(self.steps.len())] {
+            for &(addr, value) in &step.writes {
+                if memory.len() <= addr {
+                    memory.resize(addr + 1, 0);
+                }
+                memory[addr] = value;
+            }
+        }
+        memory
+    }
+}
+
+/// Where two traces part: the index of the first step that differs and how. A trace that
+/// ends first differs by `end of trace`.
+pub fn first_difference(a: &Trace, b: &Trace) -> Option<(usize, Vec<&'static str>)> {
+    for index in 0..a.steps.len().max(b.steps.len()) {
+        match (a.steps.get(index), b.steps.get(index)) {
+            (Some(a), Some(b)) => {
+                let fields = a.differences(b);
+                if !fields.is_empty() {
+                    return Some((index, fields));
+                }
+            }
+            _ => return Some((index, vec!["end of trace"])),
+        }
+    }
+    None
+}
+
+/// Observer adding every executed instruction to a shared trace.
+pub struct Tracer {
+    trace: Arc<Mutex<Trace>>,
+    pending: TraceStep,
+}
+
+impl Tracer {
+    /// Starts recording from the machine's current memory, so patches made before are part
+    /// of it. Keep the returned handle to read the trace after the run.
+    pub fn attach(machine: &mut Machine) -> Arc<Mutex<Trace>> {
+        let trace = Arc::new(Mutex::new(Trace {
+            memory: machine.memory_snapshot(),
+            steps: vec![],
+        }));
+        machine.add_observer(Box::new(Tracer {
+            trace: trace.clone(),
+            pending: TraceStep::default(),
+        }));
+        trace
+    }
+}
+
+impl Observer for Tracer {
+    fn read(&mut self, addr: usize, value: &ValueType) {
+        self.pending.reads.push((addr, *value));
+    }
+
+    fn write(&mut self, addr: usize, value: &ValueType) {
+        self.pending.writes.push((addr, *value));
+    }
+
+    fn input(&mut self, value: &ValueType) {
+        self.pending.input = Some(*value);
+    }
+
+    fn output(&mut self, value: &ValueType) {
+        self.pending.outputs.push(*value);
+    }
+
+    fn instruction(&mut self, executed: &Executed) {
+        let mut step = std::mem::take(&mut self.pending);
+        step.step = executed.step;
+        step.cursor = executed.addr;
+        step.relative_base = *executed.relative_base;
+        step.words = executed.words.to_vec();
+        self.trace.lock().unwrap().steps.push(step);
+    }
+}
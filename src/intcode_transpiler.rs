@@ -0,0 +1,331 @@
+#![allow(dead_code)]
+
+use crate::intcode_disasm::{code_cells, reachable, Instruction, Mode, Param};
+use crate::intcode_machine::*;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+const PRELUDE: &str = r#"
+pub struct TranspiledMachine {
+    memory: Vec<ValueType>,
+    cursor: usize,
+    relative_base: ValueType,
+    in_queue: VecDeque<ValueType>,
+    out_queue: VecDeque<ValueType>,
+    fallback: Option<Machine>,
+}
+
+impl TranspiledMachine {
+    pub fn new() -> TranspiledMachine {
+        TranspiledMachine {
+            memory: PROGRAM.to_vec(),
+            cursor: 0,
+            relative_base: 0,
+            in_queue: VecDeque::new(),
+            out_queue: VecDeque::new(),
+            fallback: None,
+        }
+    }
+
+    pub fn has_output(&self) -> bool {
+        match &self.fallback {
+            Some(machine) => machine.has_output(),
+            None => !self.out_queue.is_empty(),
+        }
+    }
+
+    pub fn pop_output(&mut self) -> ValueType {
+        match &mut self.fallback {
+            Some(machine) => machine.pop_output(),
+            None => self.out_queue.pop_front().unwrap(),
+        }
+    }
+
+    pub fn memset(&mut self, addr: usize, value: ValueType) {
+        match &mut self.fallback {
+            Some(machine) => machine.memset(addr, value),
+            None => {
+                let addr = self.as_addr(addr as ValueType);
+                self.memory[addr] = value;
+            }
+        }
+    }
+
+    fn as_addr(&mut self, val: ValueType) -> usize {
+        let val = val as usize;
+        if val >= self.memory.len() {
+            self.memory.resize(val + 1, 0);
+        }
+        val
+    }
+
+    fn load(&mut self, addr: ValueType) -> ValueType {
+        let addr = self.as_addr(addr);
+        self.memory[addr]
+    }
+
+    fn poke(&mut self, addr: ValueType, value: ValueType) {
+        let addr = self.as_addr(addr);
+        self.memory[addr] = value;
+    }
+
+    /// Returns true when the write lands in translated code.
+    fn store(&mut self, addr: ValueType, value: ValueType) -> bool {
+        let addr = self.as_addr(addr);
+        self.memory[addr] = value;
+        CODE.binary_search(&addr).is_ok()
+    }
+
+    fn fall_back(&mut self) -> State {
+        let machine = Machine::resume(
+            std::mem::take(&mut self.memory),
+            self.cursor,
+            self.relative_base,
+            std::mem::take(&mut self.in_queue),
+            std::mem::take(&mut self.out_queue),
+        );
+        self.fallback = Some(machine);
+        crate::intcode_machine::run_all(self.fallback.as_mut().unwrap(), std::iter::empty())
+    }
+}
+
+impl Default for TranspiledMachine {
+    fn default() -> TranspiledMachine {
+        TranspiledMachine::new()
+    }
+}
+"#;
+
+const BENCH_MAIN: &str = r#"
+fn main() {
+    let input: Vec<ValueType> = std::env::args()
+        .skip(1)
+        .map(|arg| arg.parse().expect("Inputs must be integers"))
+        .collect();
+
+    let start = std::time::Instant::now();
+    let mut machine = Machine::new(&PROGRAM.to_vec());
+    let interpreted_state = crate::intcode_machine::run_all(&mut machine, input.iter().cloned());
+    let mut interpreted = vec![];
+    while machine.has_output() {
+        interpreted.push(machine.pop_output());
+    }
+    let interpreted_time = start.elapsed();
+
+    let start = std::time::Instant::now();
+    let mut machine = TranspiledMachine::new();
+    let transpiled_state = run_all(&mut machine, input.iter().cloned());
+    let mut transpiled = vec![];
+    while machine.has_output() {
+        transpiled.push(machine.pop_output());
+    }
+    let transpiled_time = start.elapsed();
+
+    println!("interpreter: {:?} {:?} in {:?}", interpreted_state, interpreted, interpreted_time);
+    println!("transpiled:  {:?} {:?} in {:?}", transpiled_state, transpiled, transpiled_time);
+    if interpreted_state != transpiled_state || interpreted != transpiled {
+        eprintln!("Mismatch between interpreter and transpiled code");
+        std::process::exit(1);
+    }
+}
+"#;
+
+fn read(param: &Param) -> String {
+    match param.mode {
+        Mode::Immediate => format!("{}", param.value),
+        Mode::Position => format!("m.load({})", param.value),
+        Mode::Relative => format!("m.load(m.relative_base.wrapping_add({}))", param.value),
+    }
+}
+
+fn write(code: &BTreeSet<usize>, param: &Param, value: &str, next: usize) -> String {
+    match param.mode {
+        Mode::Position if !code.contains(&(param.value as usize)) => {
+            format!("m.poke({}, {});", param.value, value)
+        }
+        Mode::Position => format!(
+            "m.store({}, {}); m.cursor = {}; return m.fall_back();",
+            param.value, value, next
+        ),
+        _ => format!(
+            "if m.store(m.relative_base.wrapping_add({}), {}) {{ m.cursor = {}; return m.fall_back(); }}",
+            param.value, value, next
+        ),
+    }
+}
+
+fn translate(code: &BTreeSet<usize>, inst: &Instruction) -> Vec<String> {
+    let p = &inst.params;
+    let next = inst.next_addr();
+    // Arithmetic wraps like the interpreter's instead of panicking or differing on overflow.
+    let binary = |op: &str| {
+        let value = format!("ValueType::{}({}, {})", op, read(&p[0]), read(&p[1]));
+        vec![format!("let v = {};", value), write(code, &p[2], "v", next)]
+    };
+    let compare = |op: &str| {
+        let value = format!("if {} {} {} {{ 1 }} else {{ 0 }}", read(&p[0]), op, read(&p[1]));
+        vec![format!("let v = {};", value), write(code, &p[2], "v", next)]
+    };
+    let jump = |op: &str| {
+        vec![
+            format!("let v = {};", read(&p[0])),
+            format!("let target = {} as usize;", read(&p[1])),
+            format!("if v {} 0 {{ m.cursor = target; continue; }}", op),
+        ]
+    };
+    match inst.opcode {
+        ADD => binary("wrapping_add"),
+        MULTIPLY => binary("wrapping_mul"),
+        CMP_LT => compare("<"),
+        CMP_EQ => compare("=="),
+        INPUT => vec![
+            String::from("let v = match m.in_queue.pop_front() {"),
+            String::from("    Some(v) => v,"),
+            format!("    None => {{ m.cursor = {}; return State::InputBlock; }}", inst.addr),
+            String::from("};"),
+            write(code, &p[0], "v", next),
+        ],
+        OUTPUT => vec![format!("let v = {};", read(&p[0])), String::from("m.out_queue.push_back(v);")],
+        JMP_IF_NON_ZERO => jump("!="),
+        JMP_IF_ZERO => jump("=="),
+        MOVE_RBASE => vec![format!("m.relative_base = m.relative_base.wrapping_add({});", read(&p[0]))],
+        _ => vec![format!("m.cursor = {}; return State::Halted;", inst.addr)],
+    }
+}
+
+/// Translates every statically reachable instruction of `memory` into a Rust module
+/// exposing `TranspiledMachine` and a `run_all` mirroring `intcode_machine`.
+pub fn transpile(memory: &[ValueType], bench: bool) -> String {
+    let instructions = reachable(memory, &[0]);
+    let code = code_cells(&instructions);
+
+    let mut leaders: BTreeSet<usize> = BTreeSet::new();
+    leaders.insert(0);
+    for inst in instructions.values() {
+        if inst.is_jump() || inst.opcode == HALT {
+            leaders.insert(inst.next_addr());
+            leaders.extend(inst.static_target());
+        }
+    }
+
+    let mut out = String::new();
+    writeln!(out, "// Generated by intcode-transpile. Do not edit.").unwrap();
+    writeln!(out, "#![allow(dead_code, unused_variables, unreachable_code, clippy::all)]").unwrap();
+    writeln!(out).unwrap();
+    if bench {
+        writeln!(out, "mod intcode_machine;\n").unwrap();
+    }
+    writeln!(out, "use crate::intcode_machine::{{Machine, State, ValueType}};").unwrap();
+    writeln!(out, "use std::collections::VecDeque;\n").unwrap();
+
+    let words: Vec<String> = memory.iter().map(|v| v.to_string()).collect();
+    writeln!(out, "const PROGRAM: &[ValueType] = &[{}];\n", words.join(", ")).unwrap();
+    let cells: Vec<String> = code.iter().map(|v| v.to_string()).collect();
+    writeln!(out, "const CODE: &[usize] = &[{}];", cells.join(", ")).unwrap();
+    out.push_str(PRELUDE);
+
+    writeln!(out).unwrap();
+    writeln!(out, "pub fn run_all<T>(m: &mut TranspiledMachine, input: T) -> State").unwrap();
+    writeln!(out, "where\n    T: Iterator<Item = ValueType>,\n{{").unwrap();
+    writeln!(out, "    if let Some(machine) = &mut m.fallback {{").unwrap();
+    writeln!(out, "        return crate::intcode_machine::run_all(machine, input);").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "    m.in_queue.extend(input);").unwrap();
+    writeln!(out, "    loop {{").unwrap();
+    writeln!(out, "        match m.cursor {{").unwrap();
+    for &leader in leaders.iter().filter(|l| instructions.contains_key(l)) {
+        writeln!(out, "            {} => {{", leader).unwrap();
+        let mut addr = leader;
+        loop {
+            let inst = &instructions[&addr];
+            writeln!(out, "                // {}: {}", addr, inst).unwrap();
+            for line in translate(&code, inst) {
+                writeln!(out, "                {}", line).unwrap();
+            }
+            addr = inst.next_addr();
+            if inst.opcode == HALT {
+                break;
+            }
+            if inst.is_jump() || leaders.contains(&addr) || !instructions.contains_key(&addr) {
+                writeln!(out, "                m.cursor = {};", addr).unwrap();
+                break;
+            }
+        }
+        writeln!(out, "            }}").unwrap();
+    }
+    writeln!(out, "            _ => return m.fall_back(),").unwrap();
+    writeln!(out, "        }}\n    }}\n}}").unwrap();
+
+    if bench {
+        out.push_str(BENCH_MAIN);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::process::Command;
+
+    /// Builds the `--bench` output of `program`, which runs it on both `Machine` and the
+    /// transpiled code and fails when their states or outputs differ.
+    fn check_against_machine(name: &str, program: &[ValueType], input: &[ValueType]) {
+        let dir = std::env::temp_dir().join(format!("intcode-transpile-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("main.rs"), transpile(program, true)).unwrap();
+        fs::copy(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/src/intcode_machine.rs"),
+            dir.join("intcode_machine.rs"),
+        )
+        .unwrap();
+        let rustc = std::env::var("RUSTC").unwrap_or_else(|_| String::from("rustc"));
+        let built = Command::new(rustc)
+            .args(["--edition", "2018", "-o"])
+            .arg(dir.join("bench"))
+            .arg(dir.join("main.rs"))
+            .output()
+            .unwrap();
+        assert!(built.status.success(), "{}", String::from_utf8_lossy(&built.stderr));
+        let ran = Command::new(dir.join("bench"))
+            .args(input.iter().map(|v| v.to_string()))
+            .output()
+            .unwrap();
+        let _ = fs::remove_dir_all(&dir);
+        assert!(
+            ran.status.success(),
+            "{}{}",
+            String::from_utf8_lossy(&ran.stdout),
+            String::from_utf8_lossy(&ran.stderr)
+        );
+    }
+
+    #[test]
+    fn quine_matches_machine() {
+        let program = vec![109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99];
+        check_against_machine("quine", &program, &[]);
+    }
+
+    #[test]
+    fn large_numbers_match_machine() {
+        check_against_machine("large", &[1102, 34915192, 34915192, 7, 4, 7, 99, 0], &[]);
+        check_against_machine("literal", &[104, 1125899906842624, 99], &[]);
+    }
+
+    #[test]
+    fn comparisons_match_machine() {
+        let program = vec![
+            3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0, 0, 1002, 21,
+            125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4, 20, 1105, 1, 46, 98, 99,
+        ];
+        for input in [7, 8, 9] {
+            check_against_machine(&format!("compare{}", input), &program, &[input]);
+        }
+    }
+
+    #[test]
+    fn overflow_wraps_like_machine() {
+        let program = vec![1102, ValueType::MAX, 2, 11, 1001, 11, ValueType::MAX, 11, 4, 11, 99, 0];
+        check_against_machine("overflow", &program, &[]);
+    }
+}
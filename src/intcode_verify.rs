@@ -0,0 +1,152 @@
+#![allow(dead_code)]
+
+use crate::intcode_disasm::{code_cells, decode, mnemonic, DecodeError, Instruction, Mode};
+use crate::intcode_machine::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    InvalidOpcode(ValueType),
+    InvalidMode(ValueType),
+    /// Execution reaches the end of memory, or an instruction's operands run past it.
+    PastEnd,
+    ImmediateWrite,
+    JumpOutOfBounds(ValueType),
+    OpcodeBeyondLevel(ValueType, FeatureLevel),
+    ModeBeyondLevel(usize, FeatureLevel),
+    /// Jump target read from memory; code reached only that way is not checked.
+    DynamicJump,
+    /// Position-mode write into a reachable instruction.
+    WritesCode(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub addr: usize,
+    pub problem: Problem,
+}
+
+impl Finding {
+    pub fn severity(&self) -> Severity {
+        match self.problem {
+            Problem::DynamicJump | Problem::WritesCode(_) => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::InvalidOpcode(code) => write!(f, "invalid opcode in {}", code),
+            Problem::InvalidMode(code) => write!(f, "invalid parameter mode in {}", code),
+            Problem::PastEnd => write!(f, "execution runs past the end of the program"),
+            Problem::ImmediateWrite => write!(f, "output parameter in immediate mode"),
+            Problem::JumpOutOfBounds(target) => write!(f, "jump to {} is outside the program", target),
+            Problem::OpcodeBeyondLevel(opcode, level) => {
+                write!(f, "{} needs {}", mnemonic(*opcode), level)
+            }
+            Problem::ModeBeyondLevel(index, level) => {
+                write!(f, "mode of parameter {} needs {}", index + 1, level)
+            }
+            Problem::DynamicJump => write!(f, "jump target comes from memory and is not followed"),
+            Problem::WritesCode(target) => write!(f, "writes to the instruction at {}", target),
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.addr, self.severity(), self.problem)
+    }
+}
+
+fn check(inst: &Instruction, code: ValueType, level: FeatureLevel, len: usize, findings: &mut Vec<Finding>) {
+    let mut report = |problem| {
+        findings.push(Finding {
+            addr: inst.addr,
+            problem,
+        })
+    };
+    if let Some(needed) = FeatureLevel::of_opcode(inst.opcode).filter(|&needed| needed > level) {
+        report(Problem::OpcodeBeyondLevel(inst.opcode, needed));
+    }
+    for (index, ten) in TENS.iter().enumerate().take(inst.params.len()) {
+        if let Some(needed) = FeatureLevel::of_mode(code / ten % 10).filter(|&needed| needed > level) {
+            report(Problem::ModeBeyondLevel(index, needed));
+        }
+    }
+    if inst.out_param().is_some_and(|p| p.mode == Mode::Immediate) {
+        report(Problem::ImmediateWrite);
+    }
+    if inst.is_jump() && inst.constant_condition() != Some(false) {
+        match inst.static_target() {
+            Some(target) if target >= len => report(Problem::JumpOutOfBounds(inst.params[1].value)),
+            Some(_) => (),
+            None => report(Problem::DynamicJump),
+        }
+    }
+}
+
+/// Decodes everything reachable from `entries` by static control flow and checks it against
+/// `level`. Findings are sorted by address; errors mean `step` would fail or misbehave there
+/// if execution reaches it.
+pub fn verify(memory: &[ValueType], entries: &[usize], level: FeatureLevel) -> Vec<Finding> {
+    let mut findings = vec![];
+    let mut instructions: BTreeMap<usize, Instruction> = BTreeMap::new();
+    let mut visited: BTreeSet<usize> = BTreeSet::new();
+    let mut pending: Vec<usize> = entries.to_vec();
+    while let Some(addr) = pending.pop() {
+        if !visited.insert(addr) {
+            continue;
+        }
+        let problem = match decode(memory, addr) {
+            Ok(inst) => {
+                check(&inst, memory[addr], level, memory.len(), &mut findings);
+                pending.extend(
+                    inst.successors(memory)
+                        .into_iter()
+                        .filter(|&next| next == inst.next_addr() || next < memory.len()),
+                );
+                instructions.insert(addr, inst);
+                continue;
+            }
+            Err(DecodeError::OutOfBounds(_)) => Problem::PastEnd,
+            Err(DecodeError::InvalidOpcode(_, code)) => Problem::InvalidOpcode(code),
+            Err(DecodeError::InvalidMode(_, code)) => Problem::InvalidMode(code),
+        };
+        findings.push(Finding { addr, problem });
+    }
+
+    let cells = code_cells(&instructions);
+    for inst in instructions.values() {
+        if let Some(out) = inst.out_param().filter(|p| p.mode == Mode::Position && p.value >= 0) {
+            let target = out.value as usize;
+            if cells.contains(&target) {
+                let owner = instructions.range(..=target).next_back().map_or(target, |(&addr, _)| addr);
+                findings.push(Finding {
+                    addr: inst.addr,
+                    problem: Problem::WritesCode(owner),
+                });
+            }
+        }
+    }
+    findings.sort_by_key(|finding| (finding.addr, finding.severity()));
+    findings
+}
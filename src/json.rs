@@ -0,0 +1,409 @@
+#![allow(dead_code)]
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Just enough JSON for protocol messages. Integers are kept apart from floats so
+/// Intcode values survive a round trip.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Array(Vec<Json>),
+    Object(BTreeMap<String, Json>),
+}
+
+static NULL: Json = Json::Null;
+
+pub fn object(pairs: Vec<(&str, Json)>) -> Json {
+    Json::Object(
+        pairs
+            .into_iter()
+            .map(|(key, value)| (String::from(key), value))
+            .collect(),
+    )
+}
+
+impl Json {
+    pub fn parse(text: &str) -> Result<Json, String> {
+        let mut parser = Parser {
+            bytes: text.as_bytes(),
+            pos: 0,
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        match parser.pos == parser.bytes.len() {
+            true => Ok(value),
+            false => Err(parser.error("trailing characters")),
+        }
+    }
+
+    /// Member of an object, `Null` when missing or when this is not an object.
+    pub fn get(&self, key: &str) -> &Json {
+        match self {
+            Json::Object(map) => map.get(key).unwrap_or(&NULL),
+            _ => &NULL,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        *self == Json::Null
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::Str(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Json::Int(value) => Some(*value),
+            Json::Float(value) if value.fract() == 0.0 => Some(*value as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Json>> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Json {
+        Json::Bool(value)
+    }
+}
+
+impl From<i64> for Json {
+    fn from(value: i64) -> Json {
+        Json::Int(value)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(value: usize) -> Json {
+        Json::Int(value as i64)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Json {
+        Json::Str(String::from(value))
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Json {
+        Json::Str(value)
+    }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(items: Vec<Json>) -> Json {
+        Json::Array(items)
+    }
+}
+
+fn write_str(f: &mut fmt::Formatter, text: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in text.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Int(value) => write!(f, "{}", value),
+            Json::Float(value) => write!(f, "{}", value),
+            Json::Str(text) => write_str(f, text),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (index, item) in items.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(map) => {
+                write!(f, "{{")?;
+                for (index, (key, value)) in map.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_str(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, what: &str) -> String {
+        format!("Bad JSON at byte {}: {}", self.pos, what)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.bytes.len() && self.bytes[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).cloned()
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.peek() == Some(byte) {
+            true => {
+                self.pos += 1;
+                Ok(())
+            }
+            false => Err(self.error(&format!("expected '{}'", byte as char))),
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Json) -> Result<Json, String> {
+        match self.bytes[self.pos..].starts_with(word.as_bytes()) {
+            true => {
+                self.pos += word.len();
+                Ok(value)
+            }
+            false => Err(self.error("unknown literal")),
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => self.string().map(Json::Str),
+            Some(b't') => self.literal("true", Json::Bool(true)),
+            Some(b'f') => self.literal("false", Json::Bool(false)),
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(b'-') | Some(b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        let mut map = BTreeMap::new();
+        self.expect(b'{')?;
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Json::Object(map));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(b':')?;
+            map.insert(key, self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Json::Object(map));
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        let mut items = vec![];
+        self.expect(b'[')?;
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self
+            .bytes
+            .get(self.pos..self.pos + 4)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .ok_or_else(|| self.error("truncated escape"))?;
+        let code = u32::from_str_radix(digits, 16).map_err(|_| self.error("bad escape"))?;
+        self.pos += 4;
+        Ok(code)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if self.peek() != Some(b'"') {
+            return Err(self.error("expected a string"));
+        }
+        self.pos += 1;
+        let mut bytes = vec![];
+        loop {
+            let byte = self.peek().ok_or_else(|| self.error("unterminated string"))?;
+            self.pos += 1;
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let escape = self.peek().ok_or_else(|| self.error("unterminated string"))?;
+                    self.pos += 1;
+                    let c = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let mut code = self.hex4()?;
+                            if (0xd800..0xdc00).contains(&code) && self.bytes[self.pos..].starts_with(b"\\u") {
+                                self.pos += 2;
+                                let low = self.hex4()?;
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff);
+                            }
+                            std::char::from_u32(code).unwrap_or('\u{fffd}')
+                        }
+                        _ => return Err(self.error("bad escape")),
+                    };
+                    let mut buffer = [0; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+                }
+                _ => bytes.push(byte),
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| self.error("invalid UTF-8"))
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.pos;
+        while let Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') = self.peek() {
+            self.pos += 1;
+        }
+        let text = std::str::from_utf8(&self.bytes[start..self.pos]).unwrap();
+        match text.parse::<i64>() {
+            Ok(value) => Ok(Json::Int(value)),
+            Err(_) => text
+                .parse::<f64>()
+                .map(Json::Float)
+                .map_err(|_| self.error("bad number")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_nested_values() {
+        let value = Json::parse(r#" {"a": [1, -2, 3.5, true, false, null], "b": {}, "c": [] } "#).unwrap();
+        assert_eq!(
+            value.get("a"),
+            &Json::Array(vec![
+                Json::Int(1),
+                Json::Int(-2),
+                Json::Float(3.5),
+                Json::Bool(true),
+                Json::Bool(false),
+                Json::Null,
+            ])
+        );
+        assert_eq!(value.get("b"), &Json::Object(BTreeMap::new()));
+        assert_eq!(value.get("c"), &Json::Array(vec![]));
+        assert!(value.get("missing").is_null());
+    }
+
+    #[test]
+    fn integers_keep_full_precision() {
+        assert_eq!(Json::parse("9007199254740993").unwrap(), Json::Int(9007199254740993));
+        assert_eq!(Json::parse("-9223372036854775808").unwrap(), Json::Int(i64::MIN));
+        assert_eq!(Json::parse("1e3").unwrap().as_i64(), Some(1000));
+        assert_eq!(Json::parse("2.5").unwrap().as_i64(), None);
+    }
+
+    #[test]
+    fn parses_escapes() {
+        let text = r#""q\" b\\ s\/ \b\f\n\r\t \u00e9 \ud83d\ude00""#;
+        assert_eq!(
+            Json::parse(text).unwrap(),
+            Json::from("q\" b\\ s/ \u{8}\u{c}\n\r\t é 😀")
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_text() {
+        for text in ["", "[1,]", "{\"a\" 1}", "\"open", "tru", "1 2", "\"\\x\"", "\"\\u12\""] {
+            assert!(Json::parse(text).is_err(), "{:?} parsed", text);
+        }
+    }
+
+    #[test]
+    fn escapes_strings_when_printed() {
+        let text = "quote \" backslash \\ newline \n tab \t bell \u{7} é";
+        let printed = Json::from(text).to_string();
+        assert_eq!(printed, r#""quote \" backslash \\ newline \n tab \t bell \u0007 é""#);
+        assert_eq!(Json::parse(&printed).unwrap(), Json::from(text));
+    }
+
+    #[test]
+    fn objects_round_trip() {
+        let value = object(vec![
+            ("key \"x\"", Json::from(vec![Json::Int(-1), Json::Null])),
+            ("n", Json::Float(0.25)),
+        ]);
+        let printed = value.to_string();
+        assert_eq!(printed, r#"{"key \"x\"":[-1,null],"n":0.25}"#);
+        assert_eq!(Json::parse(&printed).unwrap(), value);
+    }
+}
@@ -0,0 +1,71 @@
+extern crate clap;
+mod intcode_asm;
+mod intcode_disasm;
+mod intcode_machine;
+mod intcode_object;
+mod intcode_stdlib;
+mod intcode_symbols;
+mod util;
+
+use clap::{App, Arg};
+use intcode_asm::assemble_file;
+use intcode_object::{link, with_library, Object};
+use intcode_symbols::sidecar_path;
+use util::{error_exit, write_program};
+
+fn main() {
+    let args = App::new("intcode-link")
+        .about("Link Intcode objects into a program; the first module is the entry point")
+        .arg(
+            Arg::with_name("modules")
+                .required_unless("list-stdlib")
+                .multiple(true)
+                .help(".ico objects, or .ics sources to assemble first"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .short("o")
+                .takes_value(true)
+                .help("Write the program here, and its symbols next to it, instead of stdout"),
+        )
+        .arg(
+            Arg::with_name("stdlib")
+                .long("stdlib")
+                .help("Resolve missing symbols from the bundled routines"),
+        )
+        .arg(
+            Arg::with_name("list-stdlib")
+                .long("list-stdlib")
+                .help("Print the sources of the bundled routines and exit"),
+        )
+        .get_matches();
+
+    if args.is_present("list-stdlib") {
+        for (_, text) in intcode_stdlib::MODULES.iter() {
+            println!("{}", text);
+        }
+        return;
+    }
+
+    let mut objects: Vec<Object> = args
+        .values_of("modules")
+        .unwrap()
+        .map(|path| match path.ends_with(".ics") {
+            true => assemble_file(path),
+            false => Object::load(path),
+        })
+        .collect::<Result<Vec<Object>, String>>()
+        .unwrap_or_else(|e| error_exit(&e));
+    if args.is_present("stdlib") {
+        objects = with_library(objects, &intcode_stdlib::library());
+    }
+    let (program, symbols) = link(&objects).unwrap_or_else(|e| error_exit(&e));
+
+    let names: Vec<&str> = objects.iter().map(|o| o.name.as_str()).collect();
+    eprintln!("linked {} words from {}", program.len(), names.join(", "));
+    write_program(args.value_of("output"), &program);
+    if let Some(out) = args.value_of("output") {
+        symbols.save(&sidecar_path(out)).unwrap_or_else(|e| error_exit(&e));
+    }
+}
@@ -0,0 +1,83 @@
+extern crate clap;
+mod intcode_disasm;
+mod intcode_machine;
+mod intcode_optimizer;
+mod util;
+
+use clap::{App, AppSettings, Arg};
+use intcode_machine::{load_program, parse_program, ValueType};
+use intcode_optimizer::{observe_writes, optimize, Options};
+use util::{error_exit, write_program};
+
+fn main() {
+    let args = App::new("intcode-optimize")
+        .about("Peephole optimizer: constant folding, constant jumps, unreachable code removal")
+        .setting(AppSettings::AllowNegativeNumbers)
+        .arg(Arg::with_name("program").required(true))
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .short("o")
+                .takes_value(true)
+                .help("Write the optimized program here instead of stdout"),
+        )
+        .arg(
+            Arg::with_name("input")
+                .long("input")
+                .short("i")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Comma-separated inputs for a trial run; writes seen are protected and outputs compared"),
+        )
+        .arg(
+            Arg::with_name("stack-writes")
+                .long("stack-writes")
+                .help("Trust that writes through the relative base never land in code"),
+        )
+        .arg(
+            Arg::with_name("no-compact")
+                .long("no-compact")
+                .help("Rewrite instructions in place without moving code"),
+        )
+        .get_matches();
+
+    let program: Vec<ValueType> =
+        load_program(args.value_of("program").unwrap()).unwrap_or_else(|e| error_exit(&e));
+    let runs: Vec<Vec<ValueType>> = args
+        .values_of("input")
+        .into_iter()
+        .flatten()
+        .map(|text| match text.trim().is_empty() {
+            true => vec![],
+            false => parse_program(text).unwrap_or_else(|e| error_exit(&e)),
+        })
+        .collect();
+
+    let mut options = Options {
+        compact: !args.is_present("no-compact"),
+        stack_writes_outside_code: args.is_present("stack-writes"),
+        ..Options::default()
+    };
+    let mut expected = vec![];
+    for input in &runs {
+        let (outputs, written) = observe_writes(&program, input);
+        options.observed_writes.extend(written);
+        expected.push(outputs);
+    }
+
+    let (optimized, report) = optimize(&program, &options).unwrap_or_else(|e| error_exit(&e));
+    for (input, expected) in runs.iter().zip(expected.iter()) {
+        let (outputs, _) = observe_writes(&optimized, input);
+        if outputs != *expected {
+            error_exit(&format!(
+                "Optimized program differs on input {:?}: expected {:?}, got {:?}",
+                input, expected, outputs
+            ));
+        }
+    }
+
+    eprint!("{}", report);
+    eprintln!("size: {} -> {}", program.len(), optimized.len());
+    write_program(args.value_of("output"), &optimized);
+}
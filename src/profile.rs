@@ -0,0 +1,75 @@
+extern crate clap;
+mod intcode_disasm;
+mod intcode_machine;
+mod intcode_symbols;
+mod util;
+
+use clap::{App, AppSettings, Arg};
+use intcode_machine::{load_program, parse_program, run_all, CallStack, Machine, State, ValueType};
+use intcode_symbols::Symbols;
+use std::sync::Arc;
+use util::error_exit;
+
+fn main() {
+    let args = App::new("intcode-profile")
+        .about("Runs a program with a shadow call stack and prints folded stacks for flamegraph tools")
+        .setting(AppSettings::AllowNegativeNumbers)
+        .arg(Arg::with_name("program").required(true))
+        .arg(
+            Arg::with_name("input")
+                .long("input")
+                .short("i")
+                .takes_value(true)
+                .help("Comma-separated input values"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .short("o")
+                .takes_value(true)
+                .help("Write the folded stacks here instead of stdout"),
+        )
+        .arg(
+            Arg::with_name("symbols")
+                .long("symbols")
+                .takes_value(true)
+                .help("Symbol file naming functions, defaults to the program's .sym sidecar"),
+        )
+        .arg(
+            Arg::with_name("debug")
+                .long("debug")
+                .help("Trace every instruction, with a backtrace on each call and return"),
+        )
+        .get_matches();
+
+    let path = args.value_of("program").unwrap();
+    let program: Vec<ValueType> = load_program(path).unwrap_or_else(|e| error_exit(&e));
+    let symbols = Symbols::for_program(path, args.value_of("symbols")).unwrap_or_else(|e| error_exit(&e));
+    let input: Vec<ValueType> = match args.value_of("input") {
+        Some(text) => parse_program(text).unwrap_or_else(|e| error_exit(&e)),
+        None => vec![],
+    };
+
+    let mut machine = Machine::new(&program);
+    machine.set_debug(args.is_present("debug"));
+    if let Some(symbols) = symbols {
+        machine.set_labels(Arc::new(symbols.labels));
+    }
+    let calls = CallStack::attach(&mut machine, args.is_present("debug"));
+    match run_all(&mut machine, input.into_iter()) {
+        State::Halted => (),
+        state => {
+            eprintln!("Machine stopped with {:?} after {} steps", state, machine.steps());
+            for line in calls.lock().unwrap().backtrace(machine.cursor(), machine.labels()) {
+                eprintln!("    {}", line);
+            }
+        }
+    }
+
+    let folded = calls.lock().unwrap().folded(machine.labels());
+    match args.value_of("output") {
+        Some(out) => std::fs::write(out, folded)
+            .unwrap_or_else(|e| error_exit(&format!("Failed to write {}. Error = {:#}", out, e))),
+        None => print!("{}", folded),
+    }
+}
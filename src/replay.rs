@@ -0,0 +1,24 @@
+extern crate clap;
+mod intcode_machine;
+mod intcode_replay;
+mod util;
+
+use clap::{App, Arg};
+use intcode_machine::load_program;
+use util::error_exit;
+
+fn main() {
+    let args = App::new("intcode-replay")
+        .arg(Arg::with_name("program").required(true))
+        .arg(Arg::with_name("session").required(true))
+        .get_matches();
+
+    let program = load_program(args.value_of("program").unwrap()).unwrap_or_else(|e| error_exit(&e));
+    let session = intcode_replay::load_session(args.value_of("session").unwrap())
+        .unwrap_or_else(|e| error_exit(&e));
+
+    match intcode_replay::replay(&program, &session) {
+        Ok(state) => println!("Replayed {} events, machine {:?}", session.len(), state),
+        Err(divergence) => error_exit(&format!("{}", divergence)),
+    }
+}
@@ -0,0 +1,164 @@
+extern crate clap;
+mod intcode_machine;
+mod intcode_search;
+mod util;
+
+use clap::{App, AppSettings, Arg, ArgGroup};
+use intcode_machine::*;
+use intcode_search::{default_threads, halted_outputs, search, Axis, Goal, Space};
+use util::{error_exit, parse_arg, parse_patch};
+
+/// What a finished run is judged by: its last output, or a memory cell.
+enum Measure {
+    LastOutput,
+    Cell(usize),
+}
+
+impl Measure {
+    fn of(&self, machine: &mut Machine) -> Option<ValueType> {
+        let outputs = halted_outputs(machine)?;
+        match self {
+            Measure::LastOutput => outputs.last().cloned(),
+            Measure::Cell(addr) => Some(machine.memget(*addr)),
+        }
+    }
+}
+
+fn main() {
+    let args = App::new("intcode-search")
+        .about("Run a program over a space of memory patches and inputs, in parallel")
+        .setting(AppSettings::AllowNegativeNumbers)
+        .arg(Arg::with_name("program").required(true))
+        .arg(
+            Arg::with_name("axis")
+                .long("axis")
+                .short("x")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .required(true)
+                .help("Search dimension: cell:ADDR=LO..HI, input:LO..HI or perm:A,B,..; ranges may use ..="),
+        )
+        .arg(
+            Arg::with_name("input")
+                .long("input")
+                .short("i")
+                .takes_value(true)
+                .help("Comma-separated input values queued after the searched ones"),
+        )
+        .arg(
+            Arg::with_name("set")
+                .long("set")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Memory patch applied to every candidate, as addr=value"),
+        )
+        .arg(
+            Arg::with_name("find-output")
+                .long("find-output")
+                .takes_value(true)
+                .help("Stop at the first candidate whose last output is this value"),
+        )
+        .arg(
+            Arg::with_name("find-cell")
+                .long("find-cell")
+                .takes_value(true)
+                .help("Stop at the first candidate leaving addr=value in memory"),
+        )
+        .arg(
+            Arg::with_name("max-output")
+                .long("max-output")
+                .help("Find the candidate with the largest last output"),
+        )
+        .arg(
+            Arg::with_name("max-cell")
+                .long("max-cell")
+                .takes_value(true)
+                .help("Find the candidate leaving the largest value in this cell"),
+        )
+        .group(
+            ArgGroup::with_name("objective")
+                .args(&["find-output", "find-cell", "max-output", "max-cell"])
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("level")
+                .long("level")
+                .takes_value(true)
+                .help("Instruction set allowed: day2, day5 or day9 (default)"),
+        )
+        .arg(
+            Arg::with_name("max-steps")
+                .long("max-steps")
+                .takes_value(true)
+                .default_value("1000000")
+                .help("Give up on a candidate after this many steps"),
+        )
+        .arg(
+            Arg::with_name("threads")
+                .long("threads")
+                .short("j")
+                .takes_value(true)
+                .help("Worker threads, defaults to the number of CPUs"),
+        )
+        .get_matches();
+
+    let program: Vec<ValueType> =
+        load_program(args.value_of("program").unwrap()).unwrap_or_else(|e| error_exit(&e));
+    let axes: Vec<Axis> = args
+        .values_of("axis")
+        .unwrap()
+        .map(|spec| Axis::parse(spec).unwrap_or_else(|e| error_exit(&e)))
+        .collect();
+    let space = Space::new(axes);
+
+    let mut base = Machine::new(&program);
+    for text in args.values_of("set").into_iter().flatten() {
+        let (addr, value) = parse_patch(text).unwrap_or_else(|e| error_exit(&e));
+        base.memset(addr, value);
+    }
+    if let Some(level) = args.value_of("level") {
+        base.set_level(FeatureLevel::parse(level).unwrap_or_else(|e| error_exit(&e)));
+    }
+    base.set_limits(Limits {
+        max_steps: parse_arg(&args, "max-steps"),
+        max_memory: None,
+    });
+    // Fixed inputs go after the searched ones, so they are pushed by each candidate's run.
+    let fixed: Vec<ValueType> = match args.value_of("input") {
+        Some(text) => parse_program(text).unwrap_or_else(|e| error_exit(&e)),
+        None => vec![],
+    };
+    let threads = parse_arg(&args, "threads").unwrap_or_else(default_threads);
+
+    let (goal, measure, target) = if let Some(text) = args.value_of("find-cell") {
+        let (addr, value) = parse_patch(text).unwrap_or_else(|e| error_exit(&e));
+        (Goal::First, Measure::Cell(addr), Some(value))
+    } else if let Some(addr) = parse_arg(&args, "max-cell") {
+        (Goal::Max, Measure::Cell(addr), None)
+    } else if args.is_present("max-output") {
+        (Goal::Max, Measure::LastOutput, None)
+    } else {
+        (Goal::First, Measure::LastOutput, parse_arg(&args, "find-output"))
+    };
+
+    eprintln!("searching {} candidates on {} threads", space.len(), threads);
+    let found = search(&base, &space, threads, goal, |machine, _| {
+        for &value in &fixed {
+            machine.push_input(value);
+        }
+        let value = measure.of(machine)?;
+        match target {
+            Some(target) if value != target => None,
+            _ => Some(value),
+        }
+    });
+    match found {
+        Some(found) => println!("{} -> {}", found.candidate, found.result),
+        None => {
+            eprintln!("No candidate found");
+            std::process::exit(1);
+        }
+    }
+}
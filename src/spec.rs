@@ -0,0 +1,82 @@
+extern crate clap;
+mod intcode_machine;
+mod intcode_spec;
+mod util;
+
+use clap::{App, Arg};
+use intcode_machine::Limits;
+use std::path::{Path, PathBuf};
+use util::{error_exit, parse_arg};
+
+fn spec_files(path: &Path) -> Vec<PathBuf> {
+    if !path.is_dir() {
+        return vec![path.to_path_buf()];
+    }
+    let mut files: Vec<PathBuf> = std::fs::read_dir(path)
+        .unwrap_or_else(|e| error_exit(&format!("Failed to list {}. Error = {:#}", path.display(), e)))
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext == "icspec"))
+        .collect();
+    files.sort();
+    files
+}
+
+fn main() {
+    let args = App::new("intcode-test")
+        .about("Runs Intcode program specs (*.icspec) and reports pass/fail")
+        .arg(Arg::with_name("specs").required(true).multiple(true))
+        .arg(
+            Arg::with_name("max-steps")
+                .long("max-steps")
+                .takes_value(true)
+                .help("Step limit for specs that do not set max_steps"),
+        )
+        .arg(
+            Arg::with_name("max-memory")
+                .long("max-memory")
+                .takes_value(true)
+                .help("Memory limit in cells for specs that do not set max_memory"),
+        )
+        .arg(
+            Arg::with_name("filter")
+                .long("filter")
+                .takes_value(true)
+                .help("Only run specs whose name contains this text"),
+        )
+        .get_matches();
+
+    let default_limits = Limits {
+        max_steps: parse_arg(&args, "max-steps"),
+        max_memory: parse_arg(&args, "max-memory"),
+    };
+    let filter = args.value_of("filter").unwrap_or("");
+
+    // Panics are reported as failures by the runner.
+    std::panic::set_hook(Box::new(|_| ()));
+
+    let (mut passed, mut failed) = (0, 0);
+    for path in args.values_of("specs").unwrap().flat_map(|p| spec_files(Path::new(p))) {
+        let specs = intcode_spec::load_specs(&path).unwrap_or_else(|e| error_exit(&e));
+        for spec in specs.iter().filter(|spec| spec.name.contains(filter)) {
+            let outcome = intcode_spec::run_spec(spec, default_limits);
+            match outcome.passed() {
+                true => {
+                    passed += 1;
+                    println!("PASS {}", outcome.name);
+                }
+                false => {
+                    failed += 1;
+                    println!("FAIL {}", outcome.name);
+                    for failure in &outcome.failures {
+                        println!("    {}", failure);
+                    }
+                }
+            }
+        }
+    }
+
+    println!("\n{} passed, {} failed", passed, failed);
+    if failed > 0 {
+        std::process::exit(1);
+    }
+}
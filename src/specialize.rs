@@ -0,0 +1,118 @@
+extern crate clap;
+mod intcode_machine;
+mod intcode_partial;
+mod util;
+
+use clap::{App, AppSettings, Arg};
+use intcode_machine::{load_program, parse_program, run_all, Machine, ValueType};
+use intcode_partial::specialize;
+use util::{error_exit, parse_arg, parse_patch, write_program};
+
+fn parse_inputs(text: &str) -> Vec<ValueType> {
+    match text.trim().is_empty() {
+        true => vec![],
+        false => parse_program(text).unwrap_or_else(|e| error_exit(&e)),
+    }
+}
+
+fn outputs(program: &Vec<ValueType>, input: Vec<ValueType>) -> Vec<ValueType> {
+    let mut machine = Machine::new(program);
+    run_all(&mut machine, input.into_iter());
+    machine.drain_output().collect()
+}
+
+fn main() {
+    let args = App::new("intcode-specialize")
+        .about("Partially evaluate a program for known leading inputs and memory patches")
+        .setting(AppSettings::AllowNegativeNumbers)
+        .arg(Arg::with_name("program").required(true))
+        .arg(
+            Arg::with_name("input")
+                .long("input")
+                .short("i")
+                .takes_value(true)
+                .help("Comma-separated inputs known in advance"),
+        )
+        .arg(
+            Arg::with_name("set")
+                .long("set")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Memory patch applied before running, as addr=value"),
+        )
+        .arg(
+            Arg::with_name("max-steps")
+                .long("max-steps")
+                .takes_value(true)
+                .default_value("10000000")
+                .help("Evaluate at most this many instructions ahead of time"),
+        )
+        .arg(
+            Arg::with_name("at")
+                .long("at")
+                .takes_value(true)
+                .help("Address for the residual code, if the program uses cells past those it touched while specializing"),
+        )
+        .arg(
+            Arg::with_name("check")
+                .long("check")
+                .short("c")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Remaining inputs to run both programs on and compare outputs"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .short("o")
+                .takes_value(true)
+                .help("Write the residual program here instead of stdout"),
+        )
+        .get_matches();
+
+    let program: Vec<ValueType> =
+        load_program(args.value_of("program").unwrap()).unwrap_or_else(|e| error_exit(&e));
+    let known = parse_inputs(args.value_of("input").unwrap_or(""));
+    let patches: Vec<(usize, ValueType)> = args
+        .values_of("set")
+        .into_iter()
+        .flatten()
+        .map(|text| parse_patch(text).unwrap_or_else(|e| error_exit(&e)))
+        .collect();
+    let max_steps = parse_arg(&args, "max-steps");
+    let at = parse_arg(&args, "at");
+
+    let residual = specialize(&program, &patches, &known, max_steps, at).unwrap_or_else(|e| error_exit(&e));
+    if residual.consumed < known.len() {
+        eprintln!(
+            "note: {} known inputs were never read",
+            known.len() - residual.consumed
+        );
+    }
+
+    let mut patched = program.clone();
+    for &(addr, value) in &patches {
+        if patched.len() <= addr {
+            patched.resize(addr + 1, 0);
+        }
+        patched[addr] = value;
+    }
+    for text in args.values_of("check").into_iter().flatten() {
+        let rest = parse_inputs(text);
+        let mut full = known.clone();
+        full.extend(rest.iter().cloned());
+        let expected = outputs(&patched, full);
+        let got = outputs(&residual.program, rest.clone());
+        if got != expected {
+            error_exit(&format!(
+                "Residual program differs on input {:?}: expected {:?}, got {:?}",
+                rest, expected, got
+            ));
+        }
+    }
+
+    eprint!("{}", residual);
+    write_program(args.value_of("output"), &residual.program);
+}
@@ -0,0 +1,98 @@
+extern crate clap;
+mod intcode_machine;
+mod util;
+
+use clap::{App, AppSettings, Arg};
+use intcode_machine::{load_program, parse_program, run_all, Machine, State, Taint, ValueType};
+use std::collections::BTreeSet;
+use util::error_exit;
+
+fn main() {
+    let args = App::new("intcode-taint")
+        .about("Runs a program and reports which inputs each output value depended on")
+        .setting(AppSettings::AllowNegativeNumbers)
+        .arg(Arg::with_name("program").required(true))
+        .arg(
+            Arg::with_name("input")
+                .long("input")
+                .short("i")
+                .takes_value(true)
+                .help("Comma-separated input values"),
+        )
+        .get_matches();
+
+    let program: Vec<ValueType> =
+        load_program(args.value_of("program").unwrap()).unwrap_or_else(|e| error_exit(&e));
+    let input: Vec<ValueType> = match args.value_of("input") {
+        Some(text) => parse_program(text).unwrap_or_else(|e| error_exit(&e)),
+        None => vec![],
+    };
+
+    let mut machine = Machine::new(&program);
+    let taint = Taint::attach(&mut machine);
+    match run_all(&mut machine, input.into_iter()) {
+        State::Halted => (),
+        state => eprintln!("Machine stopped with {:?} after {} steps", state, machine.steps()),
+    }
+    let taint = taint.lock().unwrap();
+
+    let mut used = BTreeSet::new();
+    for (index, (value, tags)) in taint.outputs.iter().enumerate() {
+        let sources: Vec<String> = tags
+            .iter()
+            .map(|&i| format!("in[{}]={}", i, taint.inputs[i]))
+            .collect();
+        let sources = match sources.is_empty() {
+            true => String::from("no inputs"),
+            false => sources.join(", "),
+        };
+        println!("out[{}] = {}  <- {}", index, value, sources);
+        used.extend(tags.iter().cloned());
+    }
+    let unused: Vec<String> = (0..taint.inputs.len())
+        .filter(|i| !used.contains(i))
+        .map(|i| format!("in[{}]={}", i, taint.inputs[i]))
+        .collect();
+    if !unused.is_empty() {
+        println!("inputs that reached no output: {}", unused.join(", "));
+    }
+    if !taint.control.is_empty() {
+        let control: Vec<String> = taint.control.iter().map(|i| format!("in[{}]", i)).collect();
+        println!("control flow depended on: {}", control.join(", "));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outputs(program: &[ValueType], input: &[ValueType]) -> Vec<(i64, BTreeSet<usize>)> {
+        let mut machine = Machine::new(&program.to_vec());
+        let taint = Taint::attach(&mut machine);
+        assert_eq!(run_all(&mut machine, input.iter().cloned()), State::Halted);
+        let outputs = taint.lock().unwrap().outputs.clone();
+        outputs
+    }
+
+    #[test]
+    fn data_flows_from_inputs_to_outputs() {
+        // out [100] + 1, out [101], out 7
+        let program = vec![3, 100, 3, 101, 1001, 100, 1, 102, 4, 102, 4, 101, 104, 7, 99];
+        let outputs = outputs(&program, &[5, 6]);
+        assert_eq!(outputs[0], (6, BTreeSet::from([0])));
+        assert_eq!(outputs[1], (6, BTreeSet::from([1])));
+        assert_eq!(outputs[2], (7, BTreeSet::new()));
+    }
+
+    #[test]
+    fn control_taint_outlives_the_branch() {
+        // out 7, then a jump on the input whose both paths lead to out 42. The constant after
+        // the merge still depends on the input: control taint is never cleared.
+        let program = vec![3, 100, 104, 7, 1005, 100, 7, 104, 42, 99];
+        for input in [0, 1] {
+            let outputs = outputs(&program, &[input]);
+            assert_eq!(outputs[0], (7, BTreeSet::new()));
+            assert_eq!(outputs[1], (42, BTreeSet::from([0])));
+        }
+    }
+}
@@ -0,0 +1,93 @@
+extern crate clap;
+mod intcode_dump;
+mod intcode_machine;
+mod intcode_trace;
+mod util;
+
+use clap::{App, Arg};
+use intcode_dump::{diff, dump, Layout};
+use intcode_trace::{first_difference, Trace};
+use std::collections::BTreeMap;
+use util::{error_exit, parse_arg};
+
+/// Steps around `index` on one side, the diverging one marked with `>`.
+fn context(name: &str, trace: &Trace, index: usize, lines: usize) {
+    println!("{}:", name);
+    let end = (index + lines + 1).min(trace.steps.len());
+    for at in index.saturating_sub(lines)..end {
+        let mark = match at == index {
+            true => ">",
+            false => " ",
+        };
+        println!("{} {}", mark, trace.steps[at]);
+    }
+    if index >= trace.steps.len() {
+        println!("> <end of trace>");
+    }
+}
+
+fn main() {
+    let args = App::new("intcode-tracediff")
+        .about("Find the first instruction where two recorded traces differ")
+        .arg(Arg::with_name("a").required(true).help("Trace from intcode run --trace-file"))
+        .arg(Arg::with_name("b").required(true).help("Trace to compare it with"))
+        .arg(
+            Arg::with_name("context")
+                .long("context")
+                .short("C")
+                .takes_value(true)
+                .default_value("3")
+                .help("Steps shown before and after the difference on each side"),
+        )
+        .arg(
+            Arg::with_name("dump")
+                .long("dump")
+                .help("Print both memories in full, not just the cells where they differ"),
+        )
+        .arg(
+            Arg::with_name("columns")
+                .long("columns")
+                .short("c")
+                .takes_value(true)
+                .default_value("10")
+                .help("Cells per row of memory"),
+        )
+        .arg(
+            Arg::with_name("color")
+                .long("color")
+                .help("Highlight differing cells with terminal colors instead of markers"),
+        )
+        .get_matches();
+
+    let (path_a, path_b) = (args.value_of("a").unwrap(), args.value_of("b").unwrap());
+    let a = Trace::load(path_a).unwrap_or_else(|e| error_exit(&e));
+    let b = Trace::load(path_b).unwrap_or_else(|e| error_exit(&e));
+    let (index, fields) = match first_difference(&a, &b) {
+        Some(found) => found,
+        None => {
+            println!("traces agree on all {} steps", a.steps.len());
+            return;
+        }
+    };
+
+    println!("first difference at entry {}: {}", index, fields.join(", "));
+    let lines = parse_arg(&args, "context").unwrap();
+    context(path_a, &a, index, lines);
+    context(path_b, &b, index, lines);
+
+    let (memory_a, memory_b) = (a.memory_at(index), b.memory_at(index));
+    let layout = Layout::new(&[&memory_a, &memory_b], parse_arg(&args, "columns").unwrap(), args.is_present("color"));
+    println!("memory before that step, - {} + {}:", path_a, path_b);
+    print!("{}", diff(&memory_a, &memory_b, &layout));
+    if args.is_present("dump") {
+        for (path, trace, memory) in [(path_a, &a, &memory_a), (path_b, &b, &memory_b)] {
+            let mut annotations = BTreeMap::new();
+            if let Some(step) = trace.steps.get(index) {
+                annotations.insert(step.cursor, String::from("next instruction"));
+            }
+            println!("{}:", path);
+            print!("{}", dump(memory, &layout, &annotations));
+        }
+    }
+    std::process::exit(1);
+}
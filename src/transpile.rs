@@ -0,0 +1,23 @@
+extern crate clap;
+mod intcode_disasm;
+mod intcode_machine;
+mod intcode_transpiler;
+mod util;
+
+use clap::{App, Arg};
+use intcode_machine::load_program;
+use util::error_exit;
+
+fn main() {
+    let args = App::new("intcode-transpile")
+        .arg(Arg::with_name("program").required(true))
+        .arg(
+            Arg::with_name("bench")
+                .long("bench")
+                .help("Emit a standalone main comparing the result against intcode_machine"),
+        )
+        .get_matches();
+
+    let program = load_program(args.value_of("program").unwrap()).unwrap_or_else(|e| error_exit(&e));
+    print!("{}", intcode_transpiler::transpile(&program, args.is_present("bench")));
+}
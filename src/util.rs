@@ -9,6 +9,37 @@ pub fn error_exit(msg: &str) -> ! {
     std::process::exit(-1);
 }
 
+/// Memory patch written as `addr=value`, as in `--set 0=2`.
+pub fn parse_patch(text: &str) -> Result<(usize, i64), String> {
+    let parts: Vec<&str> = text.split('=').collect();
+    let parsed = match parts.as_slice() {
+        [addr, value] => addr.trim().parse().ok().zip(value.trim().parse().ok()),
+        _ => None,
+    };
+    parsed.ok_or_else(|| format!("Bad patch {:?}, expected addr=value", text))
+}
+
+/// Value of option `name` if given, exiting with a message when it does not parse.
+pub fn parse_arg<T: std::str::FromStr>(args: &clap::ArgMatches, name: &str) -> Option<T>
+where
+    T::Err: std::fmt::Display,
+{
+    args.value_of(name).map(|text| {
+        text.parse()
+            .unwrap_or_else(|e| error_exit(&format!("Bad --{} {:?}. Error = {:#}", name, text, e)))
+    })
+}
+
+/// Writes a program as comma-separated text to `path`, or prints it without one.
+pub fn write_program(path: Option<&str>, program: &[i64]) {
+    let text: Vec<String> = program.iter().map(|v| v.to_string()).collect();
+    match path {
+        Some(path) => std::fs::write(path, text.join(",") + "\n")
+            .unwrap_or_else(|e| error_exit(&format!("Failed to write {}. Error = {:#}", path, e))),
+        None => println!("{}", text.join(",")),
+    }
+}
+
 pub fn clip_min<T: Ord>(value: T, min_v: T) -> T {
     cmp::max(min_v, value)
 }
@@ -38,14 +69,16 @@ pub enum PartID {
     Two,
 }
 
-pub fn part_id_from_cli() -> PartID {
-    let args = App::new("Day1")
-        .arg(
-            Arg::with_name("part")
-                .possible_value("part1")
-                .possible_value("part2"),
-        )
-        .get_matches();
+/// Command line taking the puzzle part, for days to extend with their own options.
+fn part_app() -> App<'static, 'static> {
+    App::new("Day").arg(
+        Arg::with_name("part")
+            .possible_value("part1")
+            .possible_value("part2"),
+    )
+}
+
+fn part_of(args: &clap::ArgMatches) -> PartID {
     match args.value_of("part") {
         Some("part1") => PartID::One,
         Some("part2") => PartID::Two,
@@ -53,6 +86,30 @@ pub fn part_id_from_cli() -> PartID {
     }
 }
 
+pub fn part_id_from_cli() -> PartID {
+    part_of(&part_app().get_matches())
+}
+
+pub struct Options {
+    pub part: PartID,
+    pub record: Option<String>,
+}
+
+pub fn options_from_cli() -> Options {
+    let args = part_app()
+        .arg(
+            Arg::with_name("record")
+                .long("record")
+                .takes_value(true)
+                .help("Write the Intcode session to this file for intcode-replay"),
+        )
+        .get_matches();
+    Options {
+        part: part_of(&args),
+        record: args.value_of("record").map(String::from),
+    }
+}
+
 #[macro_export]
 macro_rules! yield_iter {
     [$($x:expr,)*] => {
@@ -0,0 +1,73 @@
+extern crate clap;
+mod intcode_disasm;
+mod intcode_machine;
+mod intcode_symbols;
+mod intcode_verify;
+mod util;
+
+use clap::{App, Arg};
+use intcode_disasm::decode;
+use intcode_machine::{load_program, FeatureLevel};
+use intcode_symbols::Symbols;
+use intcode_verify::{verify, Severity};
+use util::error_exit;
+
+fn main() {
+    let args = App::new("intcode-verify")
+        .about("Check every statically reachable instruction before running a program")
+        .arg(Arg::with_name("program").required(true))
+        .arg(
+            Arg::with_name("level")
+                .long("level")
+                .short("l")
+                .takes_value(true)
+                .default_value("day9")
+                .help("Instruction set allowed: day2, day5 or day9"),
+        )
+        .arg(
+            Arg::with_name("entry")
+                .long("entry")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Extra entry address to check from, e.g. code only reached by computed jumps"),
+        )
+        .arg(
+            Arg::with_name("symbols")
+                .long("symbols")
+                .takes_value(true)
+                .help("Symbol file for naming addresses, defaults to the program's .sym sidecar"),
+        )
+        .get_matches();
+
+    let path = args.value_of("program").unwrap();
+    let program = load_program(path).unwrap_or_else(|e| error_exit(&e));
+    let level = FeatureLevel::parse(args.value_of("level").unwrap()).unwrap_or_else(|e| error_exit(&e));
+    let symbols = Symbols::for_program(path, args.value_of("symbols")).unwrap_or_else(|e| error_exit(&e));
+    let mut entries = vec![0];
+    for text in args.values_of("entry").into_iter().flatten() {
+        entries.push(
+            text.parse()
+                .unwrap_or_else(|e| error_exit(&format!("Bad entry {:?}. Error = {:#}", text, e))),
+        );
+    }
+
+    let findings = verify(&program, &entries, level);
+    for finding in &findings {
+        let place = match &symbols {
+            Some(symbols) => format!("{} ({})", finding.addr, symbols.describe(finding.addr)),
+            None => finding.addr.to_string(),
+        };
+        let text = match (decode(&program, finding.addr), &symbols) {
+            (Ok(inst), Some(symbols)) => symbols.render(&inst),
+            (Ok(inst), None) => inst.to_string(),
+            (Err(_), _) => String::new(),
+        };
+        println!("{}: {}: {}    {}", place, finding.severity(), finding.problem, text);
+    }
+    let errors = findings.iter().filter(|f| f.severity() == Severity::Error).count();
+    eprintln!("{} errors, {} warnings at level {}", errors, findings.len() - errors, level);
+    if errors > 0 {
+        std::process::exit(1);
+    }
+}
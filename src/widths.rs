@@ -0,0 +1,104 @@
+extern crate clap;
+mod intcode_bigint;
+mod intcode_machine;
+mod util;
+
+use clap::{App, AppSettings, Arg};
+use intcode_bigint::BigInt;
+use intcode_machine::{load_program, step, Machine, State, Word};
+use util::error_exit;
+
+/// Width-independent view of a machine, so lanes of different word types run side by side.
+trait Lane {
+    fn step(&mut self) -> State;
+    fn snapshot(&self) -> (usize, String, Option<String>, Vec<String>);
+}
+
+impl<W: Word> Lane for Machine<W> {
+    fn step(&mut self) -> State {
+        step(self)
+    }
+
+    fn snapshot(&self) -> (usize, String, Option<String>, Vec<String>) {
+        (
+            self.cursor(),
+            self.relative_base().to_string(),
+            self.last_write().map(|(addr, value)| format!("[{}] = {}", addr, value)),
+            self.outputs().map(|v| v.to_string()).collect(),
+        )
+    }
+}
+
+fn lane<W: Word>(path: &str, inputs: &[&str]) -> Result<Box<dyn Lane>, String> {
+    let program: Vec<W> = load_program(path)?;
+    let mut machine = Machine::new(&program);
+    for input in inputs {
+        machine.push_input(W::parse_word(input)?);
+    }
+    Ok(Box::new(machine))
+}
+
+fn main() {
+    let args = App::new("intcode-widths")
+        .about("Runs a program with i32, i64, i128 and arbitrary-precision words in lockstep")
+        .setting(AppSettings::AllowNegativeNumbers)
+        .arg(Arg::with_name("program").required(true))
+        .arg(Arg::with_name("input").multiple(true))
+        .get_matches();
+
+    let path = args.value_of("program").unwrap();
+    let inputs: Vec<&str> = args.values_of("input").map(|v| v.collect()).unwrap_or_default();
+    let candidates = vec![
+        ("i32", lane::<i32>(path, &inputs)),
+        ("i64", lane::<i64>(path, &inputs)),
+        ("i128", lane::<i128>(path, &inputs)),
+        ("bigint", lane::<BigInt>(path, &inputs)),
+    ];
+    let mut names = vec![];
+    let mut lanes = vec![];
+    for (name, candidate) in candidates {
+        match candidate {
+            Ok(lane) => {
+                names.push(name);
+                lanes.push(lane);
+            }
+            Err(e) if name == "bigint" => error_exit(&e),
+            Err(e) => println!("{:>6}: cannot load program or inputs: {}", name, e),
+        }
+    }
+
+    let mut steps: u64 = 0;
+    loop {
+        let states: Vec<State> = lanes.iter_mut().map(|lane| lane.step()).collect();
+        let snapshots: Vec<_> = lanes.iter().map(|lane| lane.snapshot()).collect();
+        let reference = &snapshots[names.len() - 1];
+        let diverged: Vec<usize> = (0..names.len())
+            .filter(|&i| snapshots[i] != *reference || states[i] != states[names.len() - 1])
+            .collect();
+        if !diverged.is_empty() {
+            println!("Results differ at step {}", steps);
+            for (name, (state, (cursor, rb, write, outputs))) in
+                names.iter().zip(states.iter().zip(snapshots.iter()))
+            {
+                println!(
+                    "{:>6}: {:?} cursor={} rb={} write={} outputs={:?}",
+                    name,
+                    state,
+                    cursor,
+                    rb,
+                    write.as_ref().map(|w| w.as_str()).unwrap_or("-"),
+                    outputs
+                );
+            }
+            std::process::exit(1);
+        }
+        match &states[0] {
+            State::Running => steps += 1,
+            state => {
+                println!("All widths agree after {} steps, machines {:?}", steps, state);
+                println!("Outputs: {:?}", reference.3);
+                return;
+            }
+        }
+    }
+}